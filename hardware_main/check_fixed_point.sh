@@ -0,0 +1,24 @@
+#!/bin/sh
+# Builds the v1 firmware with the fixed-point feature and fails if any of libm's trigonometry made
+# it into the binary, as the heading pipeline, the filters and the drawing should all use CORDIC
+# then. Needs the thumbv6m-none-eabi target and cargo-binutils:
+#
+#     rustup target add thumbv6m-none-eabi
+#     rustup component add llvm-tools
+#     cargo install cargo-binutils
+set -eu
+cd "$(dirname "$0")"
+
+# the release profile strips the symbols this looks for.
+export CARGO_PROFILE_RELEASE_STRIP=false
+symbols=$(cargo nm --release --no-default-features --features v1,fixed-point \
+    --target thumbv6m-none-eabi -- --demangle)
+
+trig='libm::math::(sinf|cosf|tanf|asinf|acosf|atanf|atan2f|k_sinf|k_cosf|rem_pio2f)::'
+found=$(echo "$symbols" | grep -E "$trig" || true)
+if [ -n "$found" ]; then
+    echo "libm trigonometry in the fixed point build:"
+    echo "$found"
+    exit 1
+fi
+echo "no libm trigonometry in the fixed point build"
@@ -1,6 +1,11 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x00000000, LENGTH = 256K
+  FLASH : ORIGIN = 0x00000000, LENGTH = 252K
+  /* the last 4K of flash is kept out of the program image to store the calibration in */
+  CALIBRATION : ORIGIN = 0x0003F000, LENGTH = 4K
   RAM : ORIGIN = 0x20000000, LENGTH = 16K
 }
+
+_calibration_start = ORIGIN(CALIBRATION);
+_calibration_len = LENGTH(CALIBRATION);
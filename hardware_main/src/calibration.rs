@@ -1,76 +1,69 @@
 #![allow(unused)]
-//! Translated from <https://github.com/lancaster-university/codal-microbit-v2/blob/006abf5566774fbcf674c0c7df27e8a9d20013de/source/MicroBitCompassCalibrator.cpp>
+//! Collects the magnetometer samples used for calibration. The math lives in
+//! [`independent_logic::calibration`].
 
-use core::fmt::Debug;
 use embedded_hal::blocking::delay::DelayUs;
-use embedded_hal::blocking::i2c::{Write, WriteRead};
-use libm::{fabsf, sqrtf};
-use lsm303agr::interface::I2cInterface;
-use lsm303agr::mode::MagContinuous;
-use lsm303agr::Lsm303agr;
-use lsm303agr::Measurement;
-use microbit::display::blocking::Display;
+use independent_logic::calibration::{checked_calibration, Calibration, CalibrationError};
+use independent_logic::frames::EnuReading;
+use independent_logic::sensor::MotionSensor;
+
+use crate::display::LedDisplay;
 
 const PERIMETER_POINTS: usize = 25;
 const PIXEL1_THRESHOLD: i32 = 200;
 const PIXEL2_THRESHOLD: i32 = 600;
-const CALIBRATION_INCREMENT: i32 = 200;
-
+const FAILED_DELAY: u32 = 2000;
+
+/// a cross with the center left out, so it can't be confused with a heading.
+const FAILED_PATTERN: [[u8; 5]; 5] = [
+    [9, 0, 0, 0, 9],
+    [0, 9, 0, 9, 0],
+    [0, 0, 0, 0, 0],
+    [0, 9, 0, 9, 0],
+    [9, 0, 0, 0, 9],
+];
+
+/// Why [`calc_calibration`] didn't come up with a calibration.
 #[derive(Debug)]
-pub struct Calibration {
-    center: Measurement,
-    scale: Measurement,
-    radius: u32,
+pub enum Error<E> {
+    /// the sensor stopped answering.
+    Sensor(E),
+    /// the samples were not good enough.
+    Rejected(CalibrationError),
 }
 
-impl Default for Calibration {
-    fn default() -> Calibration {
-        Calibration {
-            // center: Measurement { x: 0, y: 0, z: 0 },
-            // scale: Measurement {
-            //     x: 1024,
-            //     y: 1024,
-            //     z: 1024,
-            // },
-            // radius: 0,
-            center: Measurement {
-                x: 2434,
-                y: 5528,
-                z: -40156,
-            },
-            scale: Measurement {
-                x: 1044,
-                y: 1042,
-                z: 1049,
-            },
-            radius: 61751,
-        }
-    }
+pub fn calc_calibration<S, T>(
+    sensor: &mut S,
+    display: &mut LedDisplay,
+    timer: &mut T,
+) -> Result<Calibration, Error<S::Error>>
+where
+    S: MotionSensor,
+    T: DelayUs<u32>,
+{
+    let data = get_data(sensor, display, timer).map_err(Error::Sensor)?;
+    let (calibration, _quality) = checked_calibration(&data).map_err(Error::Rejected)?;
+    #[cfg(debug_assertions)]
+    rtt_target::rprintln!("Calibration quality: {:?}", _quality);
+    Ok(calibration)
 }
 
-pub fn calc_calibration<I, T, E>(
-    sensor: &mut Lsm303agr<I2cInterface<I>, MagContinuous>,
-    display: &mut Display,
-    timer: &mut T,
-) -> Calibration
+/// tells the user the calibration was rejected, and they should press B to try again.
+pub fn show_calibration_failed<T>(display: &mut LedDisplay, timer: &mut T)
 where
     T: DelayUs<u32>,
-    I: Write<Error = E> + WriteRead<Error = E>,
-    E: Debug,
 {
-    let data = get_data(sensor, display, timer);
-    calibrate(&data)
+    display.show_for(timer, FAILED_PATTERN, FAILED_DELAY);
 }
 
-fn get_data<I, T, E>(
-    sensor: &mut Lsm303agr<I2cInterface<I>, MagContinuous>,
-    display: &mut Display,
+fn get_data<S, T>(
+    sensor: &mut S,
+    display: &mut LedDisplay,
     timer: &mut T,
-) -> [Measurement; 25]
+) -> Result<[EnuReading; PERIMETER_POINTS], S::Error>
 where
+    S: MotionSensor,
     T: DelayUs<u32>,
-    I: Write<Error = E> + WriteRead<Error = E>,
-    E: Debug,
 {
     let mut leds = [
         [0, 0, 0, 0, 0],
@@ -80,12 +73,12 @@ where
         [0, 0, 0, 0, 0],
     ];
     let mut cursor = (2, 2);
-    let mut data = [Measurement { x: 0, y: 0, z: 0 }; PERIMETER_POINTS];
+    let mut data = [EnuReading::default(); PERIMETER_POINTS];
     let mut samples = 0;
 
     while samples < PERIMETER_POINTS {
-        while !sensor.accel_status().unwrap().xyz_new_data {}
-        let accel_data = sensor.accel_data().unwrap();
+        while !sensor.accel_ready()? {}
+        let accel_data = sensor.accel()?;
         let x = accel_data.x;
         let y = accel_data.y;
         if x < -PIXEL2_THRESHOLD {
@@ -115,158 +108,13 @@ where
         // Turn the y axis properly
         cursor.0 = 4 - cursor.0;
 
-        if leds[cursor.0][cursor.1] != 1 {
-            leds[cursor.0][cursor.1] = 1;
-            while !sensor.mag_status().unwrap().xyz_new_data {}
-            let mag_data = measurement_to_enu(sensor.mag_data().unwrap());
-            data[samples] = mag_data;
+        if leds[cursor.0][cursor.1] != 9 {
+            leds[cursor.0][cursor.1] = 9;
+            while !sensor.mag_ready()? {}
+            data[samples] = sensor.mag()?.to_enu();
             samples += 1;
         }
-        display.show(timer, leds, 200);
-    }
-    data
-}
-
-fn difference_square(a: Measurement, b: Measurement) -> f32 {
-    let dx = (a.x - b.x) as f32;
-    let dy = (a.y - b.y) as f32;
-    let dz = (a.z - b.z) as f32;
-
-    (dx * dx) + (dy * dy) + (dz * dz)
-}
-
-fn measure_score(center: Measurement, data: &[Measurement]) -> f32 {
-    let mut min_d = difference_square(center, data[0]);
-    let mut max_d = min_d;
-
-    for point in data[1..].iter() {
-        let d = difference_square(center, *point);
-        if d < min_d {
-            min_d = d;
-        }
-
-        if d > max_d {
-            max_d = d;
-        }
-    }
-
-    max_d - min_d
-}
-
-fn calibrate(data: &[Measurement]) -> Calibration {
-    // Approximate a center for the data
-    let mut center = Measurement { x: 0, y: 0, z: 0 };
-    let mut best = center;
-
-    for point in data {
-        center.x += point.x;
-        center.y += point.y;
-        center.z += point.z;
-    }
-
-    center.x /= data.len() as i32;
-    center.y /= data.len() as i32;
-    center.z /= data.len() as i32;
-
-    let mut current = center;
-    let mut score = measure_score(current, data);
-
-    // Calculate a fixpoint position
-    loop {
-        for x in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-            for y in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-                for z in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
-                    let mut attempt = current;
-                    attempt.x += x;
-                    attempt.y += y;
-                    attempt.z += z;
-
-                    let attempt_score = measure_score(attempt, data);
-                    if attempt_score < score {
-                        score = attempt_score;
-                        best = attempt;
-                    }
-                }
-            }
-        }
-
-        if best == current {
-            break;
-        }
-
-        current = best;
-    }
-
-    spherify(current, data)
-}
-
-fn spherify(center: Measurement, data: &[Measurement]) -> Calibration {
-    let mut radius = 0;
-    for point in data {
-        let d = sqrtf(difference_square(center, *point)) as u32;
-        if d > radius {
-            radius = d;
-        }
-    }
-
-    let mut scale: f32 = 0.0;
-    let mut weight_x = 0.0;
-    let mut weight_y = 0.0;
-    let mut weight_z = 0.0;
-
-    for point in data {
-        let d = sqrtf(difference_square(center, *point));
-        let s = (radius as f32 / d) - 1.0;
-        scale = scale.max(s);
-
-        let dx = point.x - center.x;
-        let dy = point.y - center.y;
-        let dz = point.z - center.z;
-
-        weight_x += s * fabsf(dx as f32 / d);
-        weight_y += s * fabsf(dy as f32 / d);
-        weight_z += s * fabsf(dz as f32 / d);
-    }
-
-    let wmag = sqrtf((weight_x * weight_x) + (weight_y * weight_y) + (weight_z * weight_z));
-    let scale_x = 1.0 + scale * (weight_x / wmag);
-    let scale_y = 1.0 + scale * (weight_y / wmag);
-    let scale_z = 1.0 + scale * (weight_z / wmag);
-
-    Calibration {
-        center,
-        radius,
-        scale: Measurement {
-            x: (1024.0 * scale_x) as i32,
-            y: (1024.0 * scale_y) as i32,
-            z: (1024.0 * scale_z) as i32,
-        },
-    }
-}
-
-pub fn calibrated_measurement(measurement: Measurement, calibration: &Calibration) -> Measurement {
-    let mut out = measurement_to_enu(measurement);
-    out = Measurement {
-        x: ((out.x - calibration.center.x) * calibration.scale.x) >> 10,
-        y: ((out.y - calibration.center.y) * calibration.scale.y) >> 10,
-        z: ((out.z - calibration.center.z) * calibration.scale.z) >> 10,
-    };
-    //to convert it back to the board-native SWU cordinates
-    measurement_to_enu(out)
-}
-
-fn measurement_to_enu(measurement: Measurement) -> Measurement {
-    Measurement {
-        x: -measurement.y,
-        y: -measurement.x,
-        z: measurement.z,
-    }
-}
-
-fn enu_to_cartesian(measurement: Measurement) -> Measurement {
-    Measurement {
-        x: -measurement.y,
-        y: measurement.x,
-        z: measurement.z,
+        display.show_for(timer, leds, 200);
     }
+    Ok(data)
 }
@@ -0,0 +1,51 @@
+//! A millisecond-ish clock for timing button presses and animations, counted by the RTC.
+
+use microbit::hal::clocks::Clocks;
+use microbit::hal::rtc::Rtc;
+use microbit::pac::{CLOCK, RTC0};
+
+/// ticks per second. The RTC runs at 32768 Hz, divided by the prescaler plus one.
+pub const TICKS_PER_SECOND: u32 = 1024;
+const PRESCALER: u32 = 32768 / TICKS_PER_SECOND - 1;
+/// the RTC counter is only 24 bits wide.
+const COUNTER_MASK: u32 = 0x00FF_FFFF;
+
+pub struct Clock {
+    rtc: Rtc<RTC0>,
+    last_counter: u32,
+    now: u32,
+}
+
+impl Clock {
+    /// starts the low frequency clock and the RTC.
+    pub fn new(clock: CLOCK, rtc: RTC0) -> Clock {
+        Clocks::new(clock).start_lfclk();
+        let rtc = Rtc::new(rtc, PRESCALER).unwrap();
+        rtc.enable_counter();
+        Clock {
+            rtc,
+            last_counter: 0,
+            now: 0,
+        }
+    }
+
+    /// ticks since the clock was started, wrapping around after about 48 days. Must be called at
+    /// least every 4 hours to notice the RTC wrapping around.
+    pub fn now(&mut self) -> u32 {
+        let counter = self.rtc.get_counter();
+        let elapsed = counter.wrapping_sub(self.last_counter) & COUNTER_MASK;
+        self.last_counter = counter;
+        self.now = self.now.wrapping_add(elapsed);
+        self.now
+    }
+
+    /// converts milliseconds to ticks. Saturates for intervals longer than about an hour.
+    pub const fn ticks(ms: u32) -> u32 {
+        ms.saturating_mul(TICKS_PER_SECOND) / 1000
+    }
+
+    /// converts ticks to milliseconds, wrapping around with the ticks.
+    pub fn millis(ticks: u32) -> u32 {
+        (ticks as u64 * 1000 / TICKS_PER_SECOND as u64) as u32
+    }
+}
@@ -0,0 +1,55 @@
+//! The LED display, refreshed from the TIMER1 interrupt so every LED can have one of 10
+//! brightness levels.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use embedded_hal::blocking::delay::DelayUs;
+use microbit::display::nonblocking::{Display, GreyscaleImage};
+use microbit::gpio::DisplayPins;
+use microbit::pac::{self, interrupt, TIMER1};
+
+static DISPLAY: Mutex<RefCell<Option<Display<TIMER1>>>> = Mutex::new(RefCell::new(None));
+
+/// A handle to the display. The display itself belongs to the TIMER1 interrupt.
+pub struct LedDisplay {
+    _private: (),
+}
+
+impl LedDisplay {
+    /// Must only be called once.
+    pub fn new(timer: TIMER1, pins: DisplayPins) -> LedDisplay {
+        let display = Display::new(timer, pins);
+        free(|cs| *DISPLAY.borrow(cs).borrow_mut() = Some(display));
+        // SAFETY: the interrupt handler only touches the display through the mutex.
+        #[allow(unsafe_code)]
+        unsafe {
+            pac::NVIC::unmask(pac::Interrupt::TIMER1)
+        };
+        LedDisplay { _private: () }
+    }
+
+    /// shows brightness levels from 0 to 9 until something else is shown.
+    pub fn show(&mut self, leds: [[u8; 5]; 5]) {
+        let image = GreyscaleImage::new(&leds);
+        free(|cs| {
+            if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+                display.show(&image);
+            }
+        });
+    }
+
+    /// shows the leds and waits, like the blocking display.
+    pub fn show_for<T: DelayUs<u32>>(&mut self, timer: &mut T, leds: [[u8; 5]; 5], ms: u32) {
+        self.show(leds);
+        timer.delay_us(ms * 1000);
+    }
+}
+
+#[interrupt]
+fn TIMER1() {
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.handle_display_event();
+        }
+    });
+}
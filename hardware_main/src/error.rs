@@ -0,0 +1,61 @@
+//! Everything that can go wrong talking to the sensors or the flash, and the codes the display
+//! shows for them.
+
+use independent_logic::fault::Fault;
+
+use crate::flash::NvmcError;
+
+#[cfg(feature = "v1")]
+pub type BusError = microbit::hal::twi::Error;
+#[cfg(feature = "v2")]
+pub type BusError = microbit::hal::twim::Error;
+
+// the details are only printed in debug builds, and only the v2 driver checks its settings.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum Error {
+    /// an i2c transfer failed. Shown as E1.
+    I2c(BusError),
+    /// the accelerometer did not identify itself. Shown as E2.
+    AccelNotFound,
+    /// the magnetometer did not identify itself, some v1 boards carry an LSM303 instead. Shown
+    /// as E3.
+    MagNotFound,
+    /// the sensor driver rejected a setting. Shown as E4.
+    InvalidConfig,
+    /// the calibration or the mounting couldn't be saved. Shown as E5.
+    Storage(NvmcError),
+}
+
+impl Fault for Error {
+    fn is_transient(&self) -> bool {
+        matches!(self, Error::I2c(_))
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Error::I2c(_) => 1,
+            Error::AccelNotFound => 2,
+            Error::MagNotFound => 3,
+            Error::InvalidConfig => 4,
+            Error::Storage(_) => 5,
+        }
+    }
+}
+
+#[cfg(feature = "v2")]
+impl From<lsm303agr::Error<BusError, ()>> for Error {
+    fn from(error: lsm303agr::Error<BusError, ()>) -> Error {
+        match error {
+            lsm303agr::Error::Comm(error) => Error::I2c(error),
+            // there are no chip select pins on i2c.
+            lsm303agr::Error::Pin(()) | lsm303agr::Error::InvalidInputData => Error::InvalidConfig,
+        }
+    }
+}
+
+impl From<NvmcError> for Error {
+    fn from(error: NvmcError) -> Error {
+        Error::Storage(error)
+    }
+}
@@ -0,0 +1,122 @@
+//! Sensor data-ready signals, collected by the GPIOTE interrupt so the main loop can sleep until
+//! something happens. Pressing or releasing a button wakes the main loop up too.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::{free, Mutex};
+use embedded_hal::digital::v2::InputPin;
+use microbit::hal::gpio::{Floating, Input, Pin, PullUp};
+use microbit::hal::gpiote::{Gpiote, GpioteChannel};
+use microbit::pac::{self, interrupt, GPIOTE};
+
+static GPIOTE: Mutex<RefCell<Option<Gpiote>>> = Mutex::new(RefCell::new(None));
+type Button = Pin<Input<Floating>>;
+
+/// buttons A and B, kept to check whether they are still held down.
+static BUTTONS: Mutex<RefCell<Option<[Button; 2]>>> = Mutex::new(RefCell::new(None));
+
+/// starts out set, so the sensors are read once and their data-ready lines are reset.
+static SENSOR_READY: AtomicBool = AtomicBool::new(true);
+
+/// which edge of a data-ready line means there is new data.
+#[derive(Debug, Clone, Copy)]
+pub enum Edge {
+    // only the v1 magnetometer drives its line high.
+    #[cfg_attr(feature = "v2", allow(dead_code))]
+    Rising,
+    Falling,
+}
+
+/// Must only be called once. Buttons use channels 0 and 1, and the data-ready lines the
+/// channels from 2 on.
+pub fn init(
+    gpiote: GPIOTE,
+    button_a: Button,
+    button_b: Button,
+    data_ready: &[(Pin<Input<PullUp>>, Edge)],
+) {
+    let gpiote = Gpiote::new(gpiote);
+    gpiote
+        .channel0()
+        .input_pin(&button_a)
+        .toggle()
+        .enable_interrupt();
+    gpiote
+        .channel1()
+        .input_pin(&button_b)
+        .toggle()
+        .enable_interrupt();
+    for (i, (pin, edge)) in data_ready.iter().enumerate() {
+        let channel = match i {
+            0 => gpiote.channel2(),
+            _ => gpiote.channel3(),
+        };
+        let event = channel.input_pin(pin);
+        match edge {
+            Edge::Rising => event.lo_to_hi(),
+            Edge::Falling => event.hi_to_lo(),
+        };
+        event.enable_interrupt();
+    }
+    gpiote.reset_events();
+    free(|cs| {
+        *GPIOTE.borrow(cs).borrow_mut() = Some(gpiote);
+        *BUTTONS.borrow(cs).borrow_mut() = Some([button_a, button_b]);
+    });
+    // SAFETY: the interrupt handler only touches the GPIOTE through the mutex.
+    #[allow(unsafe_code)]
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::GPIOTE)
+    };
+}
+
+/// clears a flag and returns whether it was set. thumbv6m has no atomic swap, so this is done
+/// with interrupts disabled instead.
+fn take(flag: &AtomicBool) -> bool {
+    free(|_| {
+        let set = flag.load(Ordering::Relaxed);
+        flag.store(false, Ordering::Relaxed);
+        set
+    })
+}
+
+/// true if a sensor signalled new data since the last call.
+pub fn sensor_ready() -> bool {
+    take(&SENSOR_READY)
+}
+
+/// makes the next [`sensor_ready`] return true, to read a sensor that was just restarted. Its
+/// data-ready line may already have been set before, in which case it won't fire again.
+pub fn poll_sensor() {
+    SENSOR_READY.store(true, Ordering::Relaxed);
+}
+
+/// whether buttons A and B are held down.
+pub fn buttons_down() -> (bool, bool) {
+    free(|cs| match BUTTONS.borrow(cs).borrow().as_ref() {
+        Some([button_a, button_b]) => (button_a.is_low().unwrap(), button_b.is_low().unwrap()),
+        None => (false, false),
+    })
+}
+
+fn check(channel: GpioteChannel, flag: Option<&AtomicBool>) {
+    if channel.is_event_triggered() {
+        channel.reset_events();
+        if let Some(flag) = flag {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[interrupt]
+fn GPIOTE() {
+    free(|cs| {
+        if let Some(gpiote) = GPIOTE.borrow(cs).borrow().as_ref() {
+            // the buttons only need to wake up the main loop.
+            check(gpiote.channel0(), None);
+            check(gpiote.channel1(), None);
+            check(gpiote.channel2(), Some(&SENSOR_READY));
+            check(gpiote.channel3(), Some(&SENSOR_READY));
+        }
+    });
+}
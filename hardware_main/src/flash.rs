@@ -0,0 +1,162 @@
+//! Persists the calibration and the mounting in the flash page reserved in memory.x, so they
+//! survive power cycles. See [`record_store`](independent_logic::record_store) for the layout.
+#![allow(unsafe_code)]
+
+use core::ptr::addr_of_mut;
+use core::slice;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use independent_logic::record_store::{PageStorage, RecordStore};
+use microbit::pac::NVMC;
+
+#[cfg(feature = "v1")]
+use self::nrf51::Nvmc;
+#[cfg(feature = "v1")]
+pub use self::nrf51::NvmcError;
+#[cfg(feature = "v2")]
+use microbit::hal::nvmc::Nvmc;
+#[cfg(feature = "v2")]
+pub use microbit::hal::nvmc::NvmcError;
+
+#[cfg(feature = "v1")]
+const PAGE_SIZE: usize = 1024;
+#[cfg(feature = "v2")]
+const PAGE_SIZE: usize = 4 * 1024;
+
+extern "C" {
+    static mut _calibration_start: u32;
+}
+
+pub type CalibrationStore = RecordStore<Flash>;
+
+/// The reserved page. It is only ever read and written from offset 0, as the nrf52 hal indexes
+/// the buffer by the offset into the flash.
+pub struct Flash {
+    nvmc: Nvmc<NVMC>,
+}
+
+impl Flash {
+    /// Must only be called once, as it steals the NVMC peripheral.
+    pub fn new() -> Flash {
+        // SAFETY: `Board` does not expose the NVMC, so this is the only handle to it. The reserved
+        // page is outside of the FLASH region in memory.x, so nothing else references it.
+        let (nvmc, storage) = unsafe {
+            (
+                microbit::pac::Peripherals::steal().NVMC,
+                slice::from_raw_parts_mut(addr_of_mut!(_calibration_start), PAGE_SIZE / 4),
+            )
+        };
+        Flash {
+            nvmc: Nvmc::new(nvmc, storage),
+        }
+    }
+}
+
+impl PageStorage for Flash {
+    type Error = NvmcError;
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), NvmcError> {
+        self.nvmc.read(0, bytes)
+    }
+
+    fn rewrite(&mut self, bytes: &[u8]) -> Result<(), NvmcError> {
+        self.nvmc.erase(0, PAGE_SIZE as u32)?;
+        self.nvmc.write(0, bytes)
+    }
+}
+
+/// The nrf51 hal has no NVMC driver, so this is a minimal one with the same interface as the
+/// nrf52 one. Pages are 1K on the nrf51, so only the first page of the reserved region is used.
+#[cfg(feature = "v1")]
+mod nrf51 {
+    use core::convert::TryInto;
+    use core::ops::Deref;
+    use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+    use microbit::pac::nvmc::RegisterBlock;
+
+    #[derive(Debug)]
+    pub enum NvmcError {
+        /// the offset or length is not a multiple of the word or page size.
+        Unaligned,
+        /// the operation does not fit in the storage.
+        OutOfBounds,
+    }
+
+    pub struct Nvmc<T> {
+        nvmc: T,
+        storage: &'static mut [u32],
+    }
+
+    impl<T: Deref<Target = RegisterBlock>> Nvmc<T> {
+        pub fn new(nvmc: T, storage: &'static mut [u32]) -> Nvmc<T> {
+            Nvmc { nvmc, storage }
+        }
+
+        fn wait_ready(&self) {
+            while self.nvmc.ready.read().ready().is_busy() {}
+        }
+
+        fn check(&self, offset: usize, len: usize, align: usize) -> Result<(), NvmcError> {
+            if !offset.is_multiple_of(align) || !len.is_multiple_of(align) {
+                Err(NvmcError::Unaligned)
+            } else if offset + len > self.capacity() {
+                Err(NvmcError::OutOfBounds)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl<T: Deref<Target = RegisterBlock>> ReadNorFlash for Nvmc<T> {
+        type Error = NvmcError;
+
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            self.check(offset, bytes.len(), Self::READ_SIZE)?;
+            self.wait_ready();
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let word = self.storage[(offset + i) / 4];
+                *byte = word.to_le_bytes()[(offset + i) % 4];
+            }
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.storage.len() * 4
+        }
+    }
+
+    impl<T: Deref<Target = RegisterBlock>> NorFlash for Nvmc<T> {
+        const WRITE_SIZE: usize = 4;
+
+        const ERASE_SIZE: usize = 1024;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let (from, to) = (from as usize, to as usize);
+            self.check(from, to.saturating_sub(from), Self::ERASE_SIZE)?;
+            self.nvmc.config.write(|w| w.wen().een());
+            for offset in (from..to).step_by(Self::ERASE_SIZE) {
+                let address = &self.storage[offset / 4] as *const u32 as u32;
+                self.nvmc.erasepage().write(|w| unsafe { w.bits(address) });
+                self.wait_ready();
+            }
+            self.nvmc.config.write(|w| w.wen().ren());
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+            self.nvmc.config.write(|w| w.wen().wen());
+            for (i, word) in bytes.chunks_exact(4).enumerate() {
+                self.wait_ready();
+                self.storage[offset / 4 + i] = u32::from_le_bytes(word.try_into().unwrap());
+                cortex_m::asm::dmb();
+            }
+            self.wait_ready();
+            self.nvmc.config.write(|w| w.wen().ren());
+            Ok(())
+        }
+    }
+}
@@ -5,14 +5,9 @@
 #[cfg(debug_assertions)]
 use core::f32::consts::PI;
 
-use calibration::Calibration;
 use cortex_m_rt::entry;
+use independent_logic::calibration::Calibration;
 use independent_logic::line_drawing::{FourQuadrantMatrix, UPoint};
-use lsm303agr::interface::I2cInterface;
-use lsm303agr::mode::MagContinuous;
-use lsm303agr::{AccelOutputDataRate, Lsm303agr, MagOutputDataRate, Measurement};
-use microbit::hal::{gpiote::Gpiote, Twim};
-use microbit::pac::TWIM0;
 #[cfg(not(debug_assertions))]
 use panic_halt as _;
 
@@ -22,26 +17,111 @@ use panic_rtt_target as _;
 use rtt_target::{rprintln, rtt_init_print};
 
 mod calibration;
+mod clock;
+mod display;
+mod error;
+mod events;
+mod flash;
+#[cfg(feature = "v2")]
+mod sensor;
+mod serial;
+#[cfg(feature = "v1")]
+mod v1_sensor;
 
-use microbit::{display::blocking::Display, hal::Timer};
+use microbit::hal::Timer;
 
 #[cfg(feature = "v1")]
-use microbit::{hal::twi, pac::twi0::frequency::FREQUENCY_A};
+use microbit::{
+    hal::twi,
+    hal::uart::{self, Baudrate, Parity},
+    pac::twi0::frequency::FREQUENCY_A,
+    pac::TWI0,
+};
 
 #[cfg(feature = "v2")]
-use microbit::{hal::twim, pac::twim0::frequency::FREQUENCY_A};
+use microbit::{
+    hal::twim,
+    hal::uarte::{self, Baudrate, Parity},
+    pac::twim0::frequency::FREQUENCY_A,
+    pac::TWIM0,
+};
 
-use crate::calibration::calc_calibration;
+use crate::calibration::{calc_calibration, show_calibration_failed};
+use crate::clock::Clock;
+use crate::display::LedDisplay;
+use crate::error::Error;
+use crate::events::Edge;
+use crate::flash::{CalibrationStore, Flash};
+#[cfg(feature = "v2")]
+use crate::sensor::Lsm303;
+#[cfg(feature = "v1")]
+use crate::v1_sensor::Mma8653Mag3110;
 
 use independent_logic::{
-    heading_drawing::draw_heading,
-    tilt_compensation::{
-        calc_attitude, calc_tilt_calibrated_measurement, heading_from_measurement, Heading,
-        NedMeasurement,
-    },
+    app::{Action, App, MenuItem, Setting, State},
+    auto_calibration::AutoCalibrator,
+    buttons::ButtonTracker,
+    compass::{poll_heading, LatestReadings},
+    fault::{fault_text, Backoff, Fault, FaultScreen, Supervisor},
+    font::ScrollingText,
+    heading_drawing::heading_text,
+    heading_filter::{FilterKind, HeadingFilter, NeedleHysteresis},
+    level::Level,
+    menu::{menu_icon, setting_icon, Scroller},
+    nmea::{sentence, HeadingData, SentenceKind},
+    protocol::{Config, ErrorCode, FrameDecoder, Message, Request, Response, MAX_FRAME_LEN},
+    record_store::LoadError,
+    recording::{Reading, Recorder},
+    tilt_compensation::{true_heading, Attitude, Declination, Heading},
 };
 
-const DELAY: u32 = 100;
+/// local magnetic declination in degrees, positive east. 0 points the needle at magnetic north.
+/// Can be changed over the serial port.
+const DECLINATION_DEGREES: f32 = 0.0;
+const HEADING_FILTER: FilterKind = FilterKind::LowPass { alpha: 0.3 };
+/// how many headings the mean and median filters look at.
+const HEADING_WINDOW: usize = 8;
+/// how far, in radians, the heading has to be into the next pixel before the needle moves there.
+const HYSTERESIS_MARGIN: f32 = 0.05;
+/// how long, in milliseconds, a button has to be held for a long press.
+const LONG_PRESS: u32 = 1000;
+/// how long, in milliseconds, the menu waits between scrolling one column.
+const SCROLL_STEP: u32 = 40;
+/// how long, in milliseconds, scrolling text waits between columns.
+const TEXT_STEP: u32 = 120;
+/// the NMEA 0183 sentences sent over the USB serial port, every `NMEA_INTERVAL` milliseconds.
+/// Leave it empty to turn them off. The interval can be changed over the serial port.
+const NMEA_SENTENCES: &[SentenceKind] = &[SentenceKind::Hdg, SentenceKind::Hdt];
+const NMEA_INTERVAL: u32 = 1000;
+/// NMEA 0183 devices expect 4800 baud, but anything works over USB.
+const NMEA_BAUDRATE: Baudrate = Baudrate::BAUD115200;
+/// how failed sensor transfers are retried: 4 attempts, waiting 1, 2 and 4 milliseconds.
+const SENSOR_BACKOFF: Backoff = Backoff::new(4, 1000);
+/// how long, in milliseconds, to wait between attempts to start sensors that stopped answering.
+const RESTART_INTERVAL: u32 = 5000;
+
+#[cfg(feature = "v1")]
+type Sensor = Mma8653Mag3110<twi::Twi<TWI0>>;
+#[cfg(feature = "v2")]
+type Sensor = Lsm303<twim::Twim<TWIM0>>;
+
+#[cfg(feature = "v1")]
+const CYCLES_PER_MICROSECOND: u32 = 16;
+#[cfg(feature = "v2")]
+const CYCLES_PER_MICROSECOND: u32 = 64;
+
+/// waits without a timer, so the sensors can be retried while the timer is used by the
+/// calibration.
+fn busy_wait(us: u32) {
+    cortex_m::asm::delay(us.saturating_mul(CYCLES_PER_MICROSECOND));
+}
+
+/// the code of an error that doesn't stop the compass, like a failed save, to scroll past once.
+fn error_text(error: Error) -> ScrollingText<5, 5> {
+    #[cfg(debug_assertions)]
+    rprintln!("Error: {:?}", error);
+    ScrollingText::new(fault_text(error.code()))
+}
 
 #[entry]
 fn main() -> ! {
@@ -55,111 +135,395 @@ fn main() -> ! {
     #[cfg(feature = "v2")]
     let i2c = { twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100) };
 
+    #[cfg(feature = "v1")]
+    let mut serial = serial::init(uart::Uart::new(
+        board.UART0,
+        board.uart.into(),
+        Parity::EXCLUDED,
+        NMEA_BAUDRATE,
+    ));
+    #[cfg(feature = "v2")]
+    let mut serial = serial::init(uarte::Uarte::new(
+        board.UARTE0,
+        board.uart.into(),
+        Parity::EXCLUDED,
+        NMEA_BAUDRATE,
+    ));
+
     let mut timer = Timer::new(board.TIMER0);
-    let mut display = Display::new(board.display_pins);
-
-    let gpiote = Gpiote::new(board.GPIOTE);
-    let channel_button_a = gpiote.channel0();
-    channel_button_a
-        .input_pin(&board.buttons.button_a.degrade())
-        .hi_to_lo();
-    channel_button_a.reset_events();
-
-    let channel_button_b = gpiote.channel1();
-    channel_button_b
-        .input_pin(&board.buttons.button_b.degrade())
-        .hi_to_lo();
-    channel_button_b.reset_events();
-
-    let mut sensor = Lsm303agr::new_with_i2c(i2c);
-    sensor.init().unwrap();
-    sensor.set_mag_odr(MagOutputDataRate::Hz10).unwrap();
-    sensor.set_accel_odr(AccelOutputDataRate::Hz10).unwrap();
-    let mut sensor = sensor.into_mag_continuous().ok().unwrap();
-
-    //TODO: re-callibrate with button.
+    let mut clock = Clock::new(board.CLOCK, board.RTC0);
+    let mut display = LedDisplay::new(board.TIMER1, board.display_pins);
+
+    // the accelerometer and magnetometer interrupt lines.
+    #[cfg(feature = "v1")]
+    let data_ready = [
+        (
+            board.pins.p0_28.into_pullup_input().degrade(),
+            Edge::Falling,
+        ),
+        (board.pins.p0_29.into_pullup_input().degrade(), Edge::Rising),
+    ];
+    // the shared internal interrupt line. Only the accelerometer signals on it, the magnetometer
+    // runs at the same rate and is read at the same time.
+    #[cfg(feature = "v2")]
+    let data_ready = [(
+        board.pins.p0_25.into_pullup_input().degrade(),
+        Edge::Falling,
+    )];
+
+    events::init(
+        board.GPIOTE,
+        board.buttons.button_a.degrade(),
+        board.buttons.button_b.degrade(),
+        &data_ready,
+    );
+
+    let mut sensor = Supervisor::<Sensor, _>::new(i2c, SENSOR_BACKOFF, busy_wait);
+    // shown instead of the compass and the level while the sensors are stopped.
+    let mut fault: Option<FaultScreen> = None;
+    let mut last_restart = 0;
+    // text being scrolled across the compass or the level, if any.
+    let mut text: Option<ScrollingText<5, 5>> = None;
+
+    let mut calibration_store = CalibrationStore::new(Flash::new());
+
     #[cfg(feature = "calibration")]
-    let mut calibration = calc_calibration(&mut sensor, &mut display, &mut timer);
+    let mut calibration = loop {
+        match calc_calibration(&mut sensor, &mut display, &mut timer) {
+            Ok(calibration) => {
+                if let Err(error) = calibration_store.save(&calibration) {
+                    text = Some(error_text(error.into()));
+                }
+                break calibration;
+            }
+            Err(_error) => {
+                #[cfg(debug_assertions)]
+                rprintln!("Calibration failed: {:?}", _error);
+                show_calibration_failed(&mut display, &mut timer);
+            }
+        }
+    };
     #[cfg(not(feature = "calibration"))]
-    let mut calibration = calibration::Calibration::default();
+    let mut calibration = match calibration_store.load() {
+        Ok(calibration) => calibration,
+        // a flash that can't be read is worth telling about, a page that was never written isn't.
+        Err(LoadError::Storage(error)) => {
+            text = Some(error_text(error.into()));
+            Calibration::Uncalibrated
+        }
+        Err(LoadError::Record(_error)) => {
+            #[cfg(debug_assertions)]
+            rprintln!("No stored calibration: {:?}", _error);
+            Calibration::Uncalibrated
+        }
+    };
 
     let mut current_display: FourQuadrantMatrix<5, 5, u8> =
         FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
     #[cfg(debug_assertions)]
     rprintln!("Calibration: {:?}", calibration);
 
-    let mut tilt_correction_enabled: bool = true;
+    let mut auto_calibrator = AutoCalibrator::new();
+    let mut latest_readings = LatestReadings::new();
+    let mut declination = Declination::from_degrees(DECLINATION_DEGREES);
+    let mut heading_filter: HeadingFilter<HEADING_WINDOW> = HeadingFilter::new(HEADING_FILTER);
+    let mut hysteresis = NeedleHysteresis::new(HYSTERESIS_MARGIN);
+    let mut app = App::new();
+    match calibration_store.load_mounting() {
+        Ok(mounting) => app.mounting = mounting,
+        Err(LoadError::Storage(error)) => text = Some(error_text(error.into())),
+        Err(LoadError::Record(_error)) => {
+            #[cfg(debug_assertions)]
+            rprintln!("No stored mounting: {:?}", _error);
+        }
+    }
+    let mut buttons = ButtonTracker::new(Clock::ticks(LONG_PRESS));
+    let mut scroller = Scroller::new();
+    let mut last_scroll = 0;
+    let mut level = Level::new();
+    let mut attitude = Attitude::default();
+    let mut heading = Heading(0.0);
+    let mut last_text_step = 0;
+    let mut last_nmea = 0;
+    let mut nmea_interval = NMEA_INTERVAL;
+    let mut frames: FrameDecoder<MAX_FRAME_LEN> = FrameDecoder::new();
+    // whether raw samples are sent over the serial port.
+    let mut streaming = false;
+    // whether a recording is sent over the serial port.
+    let mut recording = false;
+    let mut recorder = Recorder::new();
 
-    // let mut heading = Heading(0.0);
+    // the display refreshes from its own interrupt, so the loop only has to wake up when a button
+    // changes or a sensor has new data. The display interrupt also wakes it regularly, which keeps
+    // the button timing and the menu scrolling going.
     loop {
-        if channel_button_b.is_event_triggered() {
-            calibration = calc_calibration(&mut sensor, &mut display, &mut timer);
-            channel_button_b.reset_events();
-            #[cfg(debug_assertions)]
-            rprintln!("Calibration: {:?}", calibration);
+        let now = clock.now();
+        while let Some(byte) = serial::read() {
+            // frames that fail their checksum are noise on the line, the host sends them again.
+            let request = match frames.push(byte) {
+                Some(Ok(payload)) => Request::from_payload(payload),
+                _ => continue,
+            };
+            let response = match request {
+                Ok(Request::GetConfig) => Response::Config(Config {
+                    tilt_correction: app.tilt_correction,
+                    declination,
+                    display_style: app.display_style,
+                    nmea_interval,
+                    mounting: app.mounting,
+                }),
+                Ok(Request::SetConfig(config)) => {
+                    app.tilt_correction = config.tilt_correction;
+                    app.display_style = config.display_style;
+                    declination = config.declination;
+                    nmea_interval = config.nmea_interval;
+                    if config.mounting == app.mounting {
+                        Response::Ok
+                    } else {
+                        app.mounting = config.mounting;
+                        level.clear_zero();
+                        heading_filter.reset();
+                        hysteresis.reset();
+                        match calibration_store.save_mounting(&app.mounting) {
+                            Ok(()) => Response::Ok,
+                            Err(_) => Response::Error(ErrorCode::Storage),
+                        }
+                    }
+                }
+                Ok(Request::StartCalibration) => {
+                    app.start_calibration();
+                    text = None;
+                    Response::Ok
+                }
+                Ok(Request::GetCalibration) => Response::Calibration(calibration),
+                Ok(Request::SetCalibration(new_calibration)) => {
+                    calibration = new_calibration;
+                    auto_calibrator.reset();
+                    heading_filter.reset();
+                    hysteresis.reset();
+                    match calibration_store.save(&calibration) {
+                        Ok(()) => Response::Ok,
+                        Err(_) => Response::Error(ErrorCode::Storage),
+                    }
+                }
+                Ok(Request::StreamSamples(enabled)) => {
+                    streaming = enabled;
+                    Response::Ok
+                }
+                Ok(Request::StreamRecording(enabled)) => {
+                    recording = enabled;
+                    recorder.reset();
+                    Response::Ok
+                }
+                Err(_error) => {
+                    #[cfg(debug_assertions)]
+                    rprintln!("Bad request: {:?}", _error);
+                    Response::Error(ErrorCode::Malformed)
+                }
+            };
+            // a lost response can't be helped, the host asks again.
+            let _ = serial.send(&response);
         }
-        if channel_button_a.is_event_triggered() {
-            //toggles the bool.
-            tilt_correction_enabled ^= true;
-            channel_button_a.reset_events()
+
+        let (a_down, b_down) = events::buttons_down();
+        if let Some(press) = buttons.update(now, a_down, b_down) {
+            let before = app.state();
+            match app.handle(press) {
+                Some(Action::ShowHeading) => {
+                    text = Some(ScrollingText::new(heading_text(heading.0)))
+                }
+                Some(Action::ShowAngles) => {
+                    text = Some(ScrollingText::new(level.angles_text(attitude)))
+                }
+                Some(Action::ZeroLevel) => level.set_zero(attitude),
+                Some(Action::ClearLevelZero) => level.clear_zero(),
+                Some(Action::SaveMounting) => {
+                    level.clear_zero();
+                    heading_filter.reset();
+                    hysteresis.reset();
+                    if let Err(error) = calibration_store.save_mounting(&app.mounting) {
+                        text = Some(error_text(error.into()));
+                    }
+                }
+                None => {}
+            }
+            if app.state() != before {
+                text = None;
+            }
+            // show the new menu entry straight away when coming from another mode.
+            match (before, app.state()) {
+                (State::Menu(_), State::Menu(_)) | (State::Settings(_), State::Settings(_)) => {}
+                (_, State::Menu(item)) => scroller.jump_to(item.index()),
+                (_, State::Settings(setting)) => scroller.jump_to(setting.index()),
+                _ => {}
+            }
         }
 
-        current_display.reset_matrix();
+        if app.state() == State::Calibrating {
+            match calc_calibration(&mut sensor, &mut display, &mut timer) {
+                Ok(new_calibration) => {
+                    calibration = new_calibration;
+                    auto_calibrator.reset();
+                    heading_filter.reset();
+                    hysteresis.reset();
+                    if let Err(error) = calibration_store.save(&calibration) {
+                        text = Some(error_text(error.into()));
+                    }
+                    #[cfg(debug_assertions)]
+                    rprintln!("Calibration: {:?}", calibration);
+                }
+                // keep using the previous calibration.
+                Err(_error) => {
+                    #[cfg(debug_assertions)]
+                    rprintln!("Calibration failed: {:?}", _error);
+                    show_calibration_failed(&mut display, &mut timer);
+                }
+            }
+            app.calibration_finished();
+            latest_readings = LatestReadings::new();
+            // the buttons were used by the calibration game.
+            let (a_down, b_down) = events::buttons_down();
+            buttons.ignore_held(a_down, b_down);
+            continue;
+        }
 
-        let heading = calc_heading(&mut sensor, &calibration, &tilt_correction_enabled);
-        draw_heading::<5, 5>(heading.0, &mut current_display);
-        display.show(&mut timer, current_display.into(), DELAY)
-    }
-}
+        // the sensors are read in every mode, so their data-ready lines keep firing.
+        if events::sensor_ready() {
+            let reading = match poll_heading(
+                &mut sensor,
+                &mut latest_readings,
+                &mut calibration,
+                &mut auto_calibrator,
+                app.tilt_correction,
+                &app.mounting,
+            ) {
+                Ok(reading) => reading,
+                Err(_error) => {
+                    #[cfg(debug_assertions)]
+                    rprintln!("Sensor failed: {:?}", _error);
+                    if sensor.error().is_none() {
+                        events::poll_sensor();
+                    }
+                    None
+                }
+            };
+            if let (Some(_), true) = (reading, streaming) {
+                if let Some(sample) = latest_readings.sample() {
+                    let _ = serial.send(&Response::Sample(sample));
+                }
+            }
+            if let (Some((magnetic, _)), Some(sample), true) =
+                (reading, latest_readings.sample(), recording)
+            {
+                let reading = Reading {
+                    timestamp: Clock::millis(now),
+                    sample,
+                    tilt_correction: app.tilt_correction,
+                    heading: magnetic,
+                };
+                for entry in recorder.record(&calibration, &app.mounting, reading) {
+                    let _ = serial.send(&Response::Recording(entry));
+                }
+            }
+            if let Some((magnetic, new_attitude)) = reading {
+                attitude = new_attitude;
+                if nmea_interval != 0 && now.wrapping_sub(last_nmea) >= Clock::ticks(nmea_interval)
+                {
+                    last_nmea = now;
+                    let data = HeadingData {
+                        magnetic,
+                        // the calibration removes the deviation.
+                        deviation: match calibration {
+                            Calibration::Uncalibrated => None,
+                            _ => Some(0.0),
+                        },
+                        variation: declination,
+                    };
+                    for kind in NMEA_SENTENCES {
+                        let _ = serial.write_bytes(sentence(*kind, &data).as_bytes());
+                    }
+                }
+            }
+            if let (Some((new_heading, _)), State::Compass) = (reading, app.state()) {
+                let new_heading = true_heading(new_heading, declination);
+                #[cfg(all(not(feature = "calibration"), debug_assertions))]
+                rprintln!(
+                    "pitch: {:<+5.0}, roll: {:<+5.0}, heading: {:<+5.0}",
+                    attitude.pitch * (180.0 / PI),
+                    attitude.roll * (180.0 / PI),
+                    new_heading.0 * (180.0 / PI),
+                );
+                heading = heading_filter.update(new_heading);
+                let shown = hysteresis.update::<5, 5>(app.display_style, heading);
+                if text.is_none() {
+                    current_display.reset_matrix();
+                    app.display_style
+                        .draw::<5, 5>(shown.0, &mut current_display);
+                    display.show(app.mounting.turn_frame(current_display.into()));
+                }
+            }
+            if let (Some(_), State::Level, None) = (reading, app.state(), &text) {
+                current_display.reset_matrix();
+                level.draw_bubble::<5, 5>(attitude, &mut current_display);
+                display.show(app.mounting.turn_frame(current_display.into()));
+            }
+        }
 
-/// board has forward in the y direction and right in the -x direction, and down in the -z. (ENU),  algs for tilt compensation
-/// need forward in +x and right in +y (this is known as the NED (north, east, down) cordinate
-/// system)
-/// also converts to f32
-pub fn enu_to_ned(measurement: Measurement) -> NedMeasurement {
-    NedMeasurement {
-        x: -measurement.y as f32,
-        y: -measurement.x as f32,
-        z: -measurement.z as f32,
-    }
-}
+        // stopped sensors are tried again every so often.
+        if sensor.error().is_some()
+            && now.wrapping_sub(last_restart) >= Clock::ticks(RESTART_INTERVAL)
+        {
+            last_restart = now;
+            sensor.restart();
+            if sensor.error().is_none() {
+                latest_readings = LatestReadings::new();
+                events::poll_sensor();
+            }
+        }
+        fault = match (sensor.error(), fault.take()) {
+            (Some(error), Some(screen)) if screen.code() == error.code() => Some(screen),
+            (Some(error), _) => {
+                #[cfg(debug_assertions)]
+                rprintln!("Sensor stopped: {:?}", error);
+                Some(FaultScreen::new(error.code()))
+            }
+            (None, _) => None,
+        };
 
-fn calc_heading(
-    sensor: &mut Lsm303agr<I2cInterface<Twim<TWIM0>>, MagContinuous>,
-    mag_calibration: &Calibration,
-    tilt_correction_enabled: &bool,
-) -> Heading {
-    while !(sensor.mag_status().unwrap().xyz_new_data
-        && sensor.accel_status().unwrap().xyz_new_data)
-    {}
-    let mag_data = sensor.mag_data().unwrap();
-    let mag_data = calibration::calibrated_measurement(mag_data, mag_calibration);
-    let acel_data = sensor.accel_data().unwrap();
-
-    let mut ned_mag_data = enu_to_ned(mag_data);
-    let ned_acel_data = enu_to_ned(acel_data);
-
-    let attitude = calc_attitude(&ned_acel_data);
-
-    if *tilt_correction_enabled {
-        ned_mag_data = calc_tilt_calibrated_measurement(ned_mag_data, &attitude);
+        if now.wrapping_sub(last_text_step) >= Clock::ticks(TEXT_STEP) {
+            last_text_step = now;
+            if let Some(scrolling) = text.as_mut() {
+                match scrolling.next() {
+                    Some(frame) => display.show(app.mounting.turn_frame(frame.into())),
+                    None => text = None,
+                }
+            } else if let (Some(screen), State::Compass | State::Level) =
+                (fault.as_mut(), app.state())
+            {
+                // the compass and the level have nothing to show without the sensors.
+                if let Some(frame) = screen.next() {
+                    display.show(app.mounting.turn_frame(frame));
+                }
+            }
+        }
+
+        if now.wrapping_sub(last_scroll) >= Clock::ticks(SCROLL_STEP) {
+            last_scroll = now;
+            match app.state() {
+                State::Menu(item) => {
+                    scroller.step(item.index());
+                    let frame = scroller.frame(&MenuItem::ALL.map(menu_icon));
+                    display.show(app.mounting.turn_frame(frame));
+                }
+                State::Settings(setting) => {
+                    scroller.step(setting.index());
+                    let icons = Setting::ALL.map(|setting| setting_icon(setting, &app));
+                    display.show(app.mounting.turn_frame(scroller.frame(&icons)));
+                }
+                _ => {}
+            }
+        }
+
+        cortex_m::asm::wfi();
     }
-    //theta=0 at north, pi/-pi at south, pi/2 at east, and -pi/2 at west
-    let heading = heading_from_measurement(ned_mag_data);
-
-    #[cfg(all(not(feature = "calibration"), debug_assertions))]
-    rprintln!(
-        "pitch: {:<+5.0}, roll: {:<+5.0}, heading: {:<+5.0}",
-        attitude.pitch * (180.0 / PI),
-        attitude.roll * (180.0 / PI),
-        heading.0 * (180.0 / PI),
-    );
-    #[cfg(all(not(feature = "calibration"), debug_assertions))]
-    rprintln!(
-        "x: {:<+16}, y: {:<+16}, z: {:<+16}",
-        ned_acel_data.x,
-        ned_acel_data.y,
-        ned_acel_data.z
-    );
-    heading
 }
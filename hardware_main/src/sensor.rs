@@ -0,0 +1,95 @@
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use independent_logic::frames::{BoardReading, FrameVector};
+use independent_logic::sensor::{MotionSensor, Restart};
+use lsm303agr::interface::I2cInterface;
+use lsm303agr::mode::MagContinuous;
+use lsm303agr::{AccelOutputDataRate, Lsm303agr, MagOutputDataRate, Measurement};
+
+use crate::error::{BusError, Error};
+
+const ACCEL_ADDRESS: u8 = 0x19;
+const CTRL_REG3_A: u8 = 0x22;
+const CTRL_REG6_A: u8 = 0x25;
+/// accelerometer data-ready on INT1.
+const I1_ZYXDA: u8 = 1 << 4;
+/// interrupts are active low.
+const INT_POLARITY: u8 = 1 << 1;
+
+/// Makes the accelerometer pull its interrupt line low whenever it has new data. The line is
+/// shared with the interface chip, so it is active low like the rest of its users. The driver
+/// doesn't support interrupts, so this is written directly before handing it the bus.
+fn enable_data_ready_interrupt<I: Write>(i2c: &mut I) -> Result<(), I::Error> {
+    i2c.write(ACCEL_ADDRESS, &[CTRL_REG3_A, I1_ZYXDA])?;
+    i2c.write(ACCEL_ADDRESS, &[CTRL_REG6_A, INT_POLARITY])
+}
+
+/// the micro:bit v2s combined accelerometer and magnetometer.
+pub struct Lsm303<I>(pub Lsm303agr<I2cInterface<I>, MagContinuous>);
+
+/// the chip is mounted with its axes along the boards.
+fn board_reading(measurement: Measurement) -> BoardReading {
+    FrameVector::new(measurement.x, measurement.y, measurement.z)
+}
+
+impl<I> MotionSensor for Lsm303<I>
+where
+    I: Write<Error = BusError> + WriteRead<Error = BusError>,
+{
+    type Error = Error;
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.accel_status()?.xyz_new_data)
+    }
+
+    fn mag_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.mag_status()?.xyz_new_data)
+    }
+
+    fn accel(&mut self) -> Result<BoardReading, Self::Error> {
+        Ok(board_reading(self.0.accel_data()?))
+    }
+
+    fn mag(&mut self) -> Result<BoardReading, Self::Error> {
+        Ok(board_reading(self.0.mag_data()?))
+    }
+}
+
+impl<I> Restart for Lsm303<I>
+where
+    I: Write<Error = BusError> + WriteRead<Error = BusError>,
+{
+    type Bus = I;
+
+    /// checks both sensors are present and starts them measuring at 10Hz, with the magnetometer
+    /// measuring continuously.
+    fn start(mut i2c: I) -> Result<Self, (Error, I)> {
+        if let Err(error) = enable_data_ready_interrupt(&mut i2c) {
+            return Err((Error::I2c(error), i2c));
+        }
+        let mut sensor = Lsm303agr::new_with_i2c(i2c);
+        let configured = match (
+            sensor.accelerometer_is_detected(),
+            sensor.magnetometer_is_detected(),
+        ) {
+            (Err(error), _) | (_, Err(error)) => Err(error.into()),
+            (Ok(false), _) => Err(Error::AccelNotFound),
+            (_, Ok(false)) => Err(Error::MagNotFound),
+            (Ok(true), Ok(true)) => sensor
+                .init()
+                .and_then(|()| sensor.set_mag_odr(MagOutputDataRate::Hz10))
+                .and_then(|()| sensor.set_accel_odr(AccelOutputDataRate::Hz10))
+                .map_err(Error::from),
+        };
+        if let Err(error) = configured {
+            return Err((error, sensor.destroy()));
+        }
+        sensor
+            .into_mag_continuous()
+            .map(Lsm303)
+            .map_err(|failed| (failed.error.into(), failed.dev.destroy()))
+    }
+
+    fn stop(self) -> I {
+        self.0.destroy()
+    }
+}
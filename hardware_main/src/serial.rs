@@ -0,0 +1,158 @@
+//! The USB serial port. Received bytes are collected by the UART interrupt, so none are lost while
+//! the main loop is busy with something else, and picked up with [`read`].
+
+use core::cell::RefCell;
+use core::fmt;
+use cortex_m::interrupt::{free, Mutex};
+use embedded_hal::serial::Write;
+use independent_logic::protocol::{encode_frame, Message, MAX_FRAME_LEN};
+use microbit::pac::{self, interrupt};
+
+#[cfg(feature = "v1")]
+use microbit::hal::uart::Uart;
+#[cfg(feature = "v2")]
+use microbit::hal::uarte::{Uarte, UarteRx, UarteTx};
+
+/// how many received bytes are kept until the main loop reads them.
+const RX_BUFFER_LEN: usize = 256;
+/// the size of the DMA transfers when sending.
+#[cfg(feature = "v2")]
+const TX_BUFFER_LEN: usize = 32;
+
+/// A ring buffer of received bytes. Bytes that don't fit any more are dropped, which the frame
+/// checksum catches.
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_LEN],
+    start: usize,
+    len: usize,
+}
+
+impl RxBuffer {
+    const fn new() -> RxBuffer {
+        RxBuffer {
+            bytes: [0; RX_BUFFER_LEN],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < RX_BUFFER_LEN {
+            self.bytes[(self.start + self.len) % RX_BUFFER_LEN] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.start];
+        self.start = (self.start + 1) % RX_BUFFER_LEN;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+static RX_BUFFER: Mutex<RefCell<RxBuffer>> = Mutex::new(RefCell::new(RxBuffer::new()));
+
+#[cfg(feature = "v2")]
+static RX: Mutex<RefCell<Option<UarteRx<pac::UARTE0>>>> = Mutex::new(RefCell::new(None));
+
+#[cfg(feature = "v1")]
+type Tx = Uart<pac::UART0>;
+#[cfg(feature = "v2")]
+type Tx = UarteTx<pac::UARTE0>;
+
+/// The sending half of the serial port.
+pub struct SerialTx(Tx);
+
+impl SerialTx {
+    /// sends all bytes and waits until they are out.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+        for byte in bytes {
+            nb::block!(self.0.write(*byte)).map_err(|_| fmt::Error)?;
+        }
+        nb::block!(self.0.flush()).map_err(|_| fmt::Error)
+    }
+
+    /// sends a message as a protocol frame.
+    pub fn send<M: Message>(&mut self, message: &M) -> fmt::Result {
+        let mut out = [0; MAX_FRAME_LEN];
+        let frame = encode_frame(message, &mut out).map_err(|_| fmt::Error)?;
+        self.write_bytes(frame)
+    }
+}
+
+/// Must only be called once. Starts receiving and returns the half used for sending.
+#[cfg(feature = "v1")]
+pub fn init(uart: Uart<pac::UART0>) -> SerialTx {
+    // SAFETY: the interrupt handler is the only other place that touches the receiver, and the
+    // transmitter doesn't use the interrupt.
+    #[allow(unsafe_code)]
+    unsafe {
+        (*pac::UART0::ptr())
+            .intenset
+            .write(|w| w.rxdrdy().set_bit());
+        pac::NVIC::unmask(pac::Interrupt::UART0);
+    }
+    SerialTx(uart)
+}
+
+/// Must only be called once. Starts receiving and returns the half used for sending.
+#[cfg(feature = "v2")]
+pub fn init(uarte: Uarte<pac::UARTE0>) -> SerialTx {
+    let tx_buf = cortex_m::singleton!(: [u8; TX_BUFFER_LEN] = [0; TX_BUFFER_LEN]).unwrap();
+    let rx_buf = cortex_m::singleton!(: [u8; 1] = [0]).unwrap();
+    let (tx, mut rx) = uarte.split(tx_buf, rx_buf).unwrap();
+    // starts the first one byte transfer, every later one is started by the interrupt.
+    let _ = embedded_hal::serial::Read::read(&mut rx);
+    free(|cs| *RX.borrow(cs).borrow_mut() = Some(rx));
+    // SAFETY: the interrupt handler only touches the receiver through the mutex, and the
+    // transmitter doesn't use the interrupt.
+    #[allow(unsafe_code)]
+    unsafe {
+        (*pac::UARTE0::ptr())
+            .intenset
+            .write(|w| w.endrx().set_bit());
+        pac::NVIC::unmask(pac::Interrupt::UARTE0_UART0);
+    }
+    SerialTx(tx)
+}
+
+/// the next received byte, if there is one.
+pub fn read() -> Option<u8> {
+    free(|cs| RX_BUFFER.borrow(cs).borrow_mut().pop())
+}
+
+#[cfg(feature = "v1")]
+#[interrupt]
+fn UART0() {
+    // SAFETY: only the receive registers are touched, which nothing else uses.
+    #[allow(unsafe_code)]
+    let uart = unsafe { &*pac::UART0::ptr() };
+    if uart.events_rxdrdy.read().bits() == 1 {
+        uart.events_rxdrdy.reset();
+        let byte = uart.rxd.read().bits() as u8;
+        free(|cs| RX_BUFFER.borrow(cs).borrow_mut().push(byte));
+    }
+}
+
+#[cfg(feature = "v2")]
+#[interrupt]
+fn UARTE0_UART0() {
+    free(|cs| {
+        if let Some(rx) = RX.borrow(cs).borrow_mut().as_mut() {
+            let mut buffer = RX_BUFFER.borrow(cs).borrow_mut();
+            // reading a byte starts the transfer of the next one.
+            loop {
+                match embedded_hal::serial::Read::read(rx) {
+                    Ok(byte) => buffer.push(byte),
+                    Err(nb::Error::WouldBlock) => break,
+                    // the byte is lost, but the next one is received as usual.
+                    Err(nb::Error::Other(_)) => {}
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,148 @@
+//! The micro:bit v1s MMA8653FC accelerometer and MAG3110 magnetometer, which share the internal
+//! i2c bus.
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use independent_logic::calibration::Vector3;
+use independent_logic::frames::BoardReading;
+use independent_logic::sensor::{MotionSensor, Restart, MAG3110_AXES, MMA8653_AXES};
+
+use crate::error::{BusError, Error};
+
+const MMA8653_ADDRESS: u8 = 0x1D;
+const MMA8653_ID: u8 = 0x5A;
+const MAG3110_ADDRESS: u8 = 0x0E;
+const MAG3110_ID: u8 = 0xC4;
+
+// registers shared by both chips.
+const STATUS: u8 = 0x00;
+const OUT_X_MSB: u8 = 0x01;
+/// set in STATUS once a new reading of all three axes is available.
+const ZYXDR: u8 = 1 << 3;
+
+// MMA8653 registers.
+const MMA8653_WHO_AM_I: u8 = 0x0D;
+const MMA8653_XYZ_DATA_CFG: u8 = 0x0E;
+const MMA8653_CTRL_REG1: u8 = 0x2A;
+const MMA8653_CTRL_REG4: u8 = 0x2D;
+const MMA8653_CTRL_REG5: u8 = 0x2E;
+
+// MAG3110 registers.
+const MAG3110_WHO_AM_I: u8 = 0x07;
+const MAG3110_CTRL_REG1: u8 = 0x10;
+const MAG3110_CTRL_REG2: u8 = 0x11;
+
+pub struct Mma8653Mag3110<I> {
+    i2c: I,
+}
+
+impl<I> Mma8653Mag3110<I>
+where
+    I: Write<Error = BusError> + WriteRead<Error = BusError>,
+{
+    /// checks both chips are present and starts them measuring at about 10Hz. Both signal new data
+    /// on their interrupt line: the MMA8653 pulls it low, the MAG3110 drives it high.
+    fn init(&mut self) -> Result<(), Error> {
+        if self.read_register(MMA8653_ADDRESS, MMA8653_WHO_AM_I)? != MMA8653_ID {
+            return Err(Error::AccelNotFound);
+        }
+        if self.read_register(MAG3110_ADDRESS, MAG3110_WHO_AM_I)? != MAG3110_ID {
+            return Err(Error::MagNotFound);
+        }
+
+        // the MMA8653 can only be configured in standby.
+        self.write_register(MMA8653_ADDRESS, MMA8653_CTRL_REG1, 0)?;
+        // +-2g range.
+        self.write_register(MMA8653_ADDRESS, MMA8653_XYZ_DATA_CFG, 0)?;
+        // data-ready interrupt, routed to INT1.
+        self.write_register(MMA8653_ADDRESS, MMA8653_CTRL_REG4, 1)?;
+        self.write_register(MMA8653_ADDRESS, MMA8653_CTRL_REG5, 1)?;
+        // 12.5Hz output rate, active.
+        self.write_register(MMA8653_ADDRESS, MMA8653_CTRL_REG1, 0b101 << 3 | 1)?;
+
+        // reset the magnetic sensor before every measurement, as recommended by the datasheet.
+        self.write_register(MAG3110_ADDRESS, MAG3110_CTRL_REG2, 1 << 7)?;
+        // 10Hz output rate with 16x oversampling, active.
+        self.write_register(MAG3110_ADDRESS, MAG3110_CTRL_REG1, 0b011 << 5 | 1)
+    }
+
+    fn read_register(&mut self, address: u8, register: u8) -> Result<u8, Error> {
+        let mut value = [0];
+        self.i2c
+            .write_read(address, &[register], &mut value)
+            .map_err(Error::I2c)?;
+        Ok(value[0])
+    }
+
+    fn write_register(&mut self, address: u8, register: u8, value: u8) -> Result<(), Error> {
+        self.i2c
+            .write(address, &[register, value])
+            .map_err(Error::I2c)
+    }
+
+    /// both chips output big endian x, y and z starting at the same register.
+    fn read_xyz(&mut self, address: u8) -> Result<Vector3, Error> {
+        let mut data = [0; 6];
+        self.i2c
+            .write_read(address, &[OUT_X_MSB], &mut data)
+            .map_err(Error::I2c)?;
+        Ok(Vector3 {
+            x: i16::from_be_bytes([data[0], data[1]]) as i32,
+            y: i16::from_be_bytes([data[2], data[3]]) as i32,
+            z: i16::from_be_bytes([data[4], data[5]]) as i32,
+        })
+    }
+}
+
+impl<I> MotionSensor for Mma8653Mag3110<I>
+where
+    I: Write<Error = BusError> + WriteRead<Error = BusError>,
+{
+    type Error = Error;
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read_register(MMA8653_ADDRESS, STATUS)? & ZYXDR != 0)
+    }
+
+    fn mag_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.read_register(MAG3110_ADDRESS, STATUS)? & ZYXDR != 0)
+    }
+
+    fn accel(&mut self) -> Result<BoardReading, Self::Error> {
+        // readings are left aligned, so at +-2g 1g is 2^14.
+        let raw = self.read_xyz(MMA8653_ADDRESS)?;
+        let milli_g = |value: i32| (value * 1000) >> 14;
+        Ok(MMA8653_AXES.apply(Vector3 {
+            x: milli_g(raw.x),
+            y: milli_g(raw.y),
+            z: milli_g(raw.z),
+        }))
+    }
+
+    fn mag(&mut self) -> Result<BoardReading, Self::Error> {
+        // 0.1 microtesla per bit.
+        let raw = self.read_xyz(MAG3110_ADDRESS)?;
+        Ok(MAG3110_AXES.apply(Vector3 {
+            x: raw.x * 100,
+            y: raw.y * 100,
+            z: raw.z * 100,
+        }))
+    }
+}
+
+impl<I> Restart for Mma8653Mag3110<I>
+where
+    I: Write<Error = BusError> + WriteRead<Error = BusError>,
+{
+    type Bus = I;
+
+    fn start(i2c: I) -> Result<Self, (Error, I)> {
+        let mut sensor = Mma8653Mag3110 { i2c };
+        match sensor.init() {
+            Ok(()) => Ok(sensor),
+            Err(error) => Err((error, sensor.i2c)),
+        }
+    }
+
+    fn stop(self) -> I {
+        self.i2c
+    }
+}
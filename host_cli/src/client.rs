@@ -0,0 +1,415 @@
+//! Talks to the compass over anything that can be read and written, like its serial port.
+
+use std::io::{self, Read, Write};
+
+use independent_logic::{
+    protocol::{
+        encode_frame, FrameDecoder, Message, ProtocolError, Request, Response, MAX_FRAME_LEN,
+    },
+    recording::{file_entry, file_header, Entry, MAX_FILE_ENTRY_LEN},
+    sensor::Sample,
+};
+
+/// how many frames [`Client::request`] reads before it gives up on an answer.
+const MAX_SKIPPED_FRAMES: usize = 64;
+
+fn protocol_error(error: ProtocolError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error))
+}
+
+/// One side of the protocol. Reading from the port must return `Ok(0)` when nothing arrived for a
+/// while, which is treated as a timeout.
+pub struct Client<T> {
+    port: T,
+    frames: FrameDecoder<MAX_FRAME_LEN>,
+}
+
+impl<T: Read + Write> Client<T> {
+    pub fn new(port: T) -> Client<T> {
+        Client {
+            port,
+            frames: FrameDecoder::new(),
+        }
+    }
+
+    /// reads until the next frame that passes its checksum. Anything else on the line, like NMEA
+    /// sentences, is skipped.
+    fn receive(&mut self) -> io::Result<Response> {
+        let mut byte = [0];
+        loop {
+            if self.port.read(&mut byte)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no answer from the compass",
+                ));
+            }
+            if let Some(Ok(payload)) = self.frames.push(byte[0]) {
+                return Response::from_payload(payload).map_err(protocol_error);
+            }
+        }
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        let mut out = [0; MAX_FRAME_LEN];
+        let frame = encode_frame(request, &mut out).map_err(protocol_error)?;
+        self.port.write_all(frame)?;
+        self.port.flush()
+    }
+
+    /// sends a request and waits for the answer. Samples and recordings that were still being
+    /// streamed are skipped.
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        self.send(request)?;
+        for _ in 0..MAX_SKIPPED_FRAMES {
+            match self.receive()? {
+                Response::Sample(_) | Response::Recording(_) => continue,
+                Response::Error(code) => {
+                    return Err(io::Error::other(format!("the compass answered {:?}", code)))
+                }
+                response => return Ok(response),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "only samples from the compass",
+        ))
+    }
+
+    /// sends a request that is only acknowledged.
+    pub fn command(&mut self, request: &Request) -> io::Result<()> {
+        match self.request(request)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(&response)),
+        }
+    }
+
+    /// waits for the next streamed sample.
+    pub fn sample(&mut self) -> io::Result<Sample> {
+        loop {
+            if let Response::Sample(sample) = self.receive()? {
+                return Ok(sample);
+            }
+        }
+    }
+
+    /// waits for the next entry of a streamed recording.
+    pub fn recording_entry(&mut self) -> io::Result<Entry> {
+        loop {
+            if let Response::Recording(entry) = self.receive()? {
+                return Ok(entry);
+            }
+        }
+    }
+
+    /// records `count` readings, or until an error, into a recording file. Returns how many
+    /// readings were recorded.
+    pub fn record(&mut self, file: &mut impl Write, count: Option<usize>) -> io::Result<usize> {
+        file.write_all(&file_header())?;
+        self.command(&Request::StreamRecording(true))?;
+        let mut out = [0; MAX_FILE_ENTRY_LEN];
+        let mut readings = 0;
+        while count.is_none_or(|count| readings < count) {
+            let entry = self.recording_entry()?;
+            if let Entry::Reading(_) = entry {
+                readings += 1;
+            }
+            // written straight away, so nothing is lost when interrupted.
+            file.write_all(file_entry(&entry, &mut out).map_err(protocol_error)?)?;
+            file.flush()?;
+        }
+        self.command(&Request::StreamRecording(false))?;
+        Ok(readings)
+    }
+}
+
+pub fn unexpected(response: &Response) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected answer: {:?}", response),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use independent_logic::{
+        calibration::{Calibration, Vector3},
+        compass::heading_from_samples,
+        frames::BoardReading,
+        heading_drawing::DisplayStyle,
+        mounting::Mounting,
+        nmea::{sentence, HeadingData, SentenceKind},
+        protocol::{Config, Decoder, Encoder, ErrorCode},
+        recording::{FileReader, Player, Reading, Recorder},
+        tilt_compensation::{Declination, Heading},
+    };
+    use std::collections::VecDeque;
+
+    /// Answers requests like the firmware does, with NMEA sentences in between.
+    struct FakeCompass {
+        config: Config,
+        calibration: Calibration,
+        calibrating: bool,
+        streaming: bool,
+        recording: bool,
+        next_sample: i32,
+        recorder: Recorder,
+        frames: FrameDecoder<MAX_FRAME_LEN>,
+        outgoing: VecDeque<u8>,
+    }
+
+    impl FakeCompass {
+        fn new() -> FakeCompass {
+            FakeCompass {
+                config: Config {
+                    tilt_correction: true,
+                    declination: Declination::default(),
+                    display_style: DisplayStyle::default(),
+                    nmea_interval: 1000,
+                    mounting: Mounting::default(),
+                },
+                calibration: Calibration::Uncalibrated,
+                calibrating: false,
+                streaming: false,
+                recording: false,
+                next_sample: 0,
+                recorder: Recorder::new(),
+                frames: FrameDecoder::new(),
+                outgoing: VecDeque::new(),
+            }
+        }
+
+        fn send(&mut self, response: &Response) {
+            let mut out = [0; MAX_FRAME_LEN];
+            let frame = encode_frame(response, &mut out).unwrap();
+            self.outgoing.extend(frame);
+        }
+
+        fn nmea(&mut self) {
+            let data = HeadingData {
+                magnetic: Heading(1.0),
+                deviation: None,
+                variation: self.config.declination,
+            };
+            self.outgoing
+                .extend(sentence(SentenceKind::Hdg, &data).as_bytes());
+        }
+
+        fn stream_sample(&mut self) {
+            let sample = FakeCompass::sample(self.next_sample);
+            self.next_sample += 1;
+            self.send(&Response::Sample(sample));
+        }
+
+        fn sample(index: i32) -> Sample {
+            Sample {
+                accel: BoardReading::new(index, -index, -1000),
+                mag: BoardReading::new(20000, index * 100, -40000),
+            }
+        }
+
+        fn handle(&mut self, request: Request) -> Response {
+            match request {
+                Request::GetConfig => Response::Config(self.config),
+                Request::SetConfig(config) => {
+                    self.config = config;
+                    Response::Ok
+                }
+                Request::StartCalibration => {
+                    self.calibrating = true;
+                    Response::Ok
+                }
+                Request::GetCalibration => Response::Calibration(self.calibration),
+                Request::SetCalibration(calibration) => {
+                    self.calibration = calibration;
+                    Response::Ok
+                }
+                Request::StreamSamples(enabled) => {
+                    self.streaming = enabled;
+                    Response::Ok
+                }
+                Request::StreamRecording(enabled) => {
+                    self.recording = enabled;
+                    Response::Ok
+                }
+            }
+        }
+    }
+
+    impl Write for FakeCompass {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                let request = match self.frames.push(*byte) {
+                    Some(Ok(payload)) => Request::from_payload(payload),
+                    _ => continue,
+                };
+                // samples that were already on their way arrive before the answer.
+                if self.streaming {
+                    for _ in 0..3 {
+                        self.stream_sample();
+                    }
+                }
+                self.nmea();
+                let response = match request {
+                    Ok(request) => self.handle(request),
+                    Err(_) => Response::Error(ErrorCode::Malformed),
+                };
+                self.send(&response);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for FakeCompass {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() && self.streaming {
+                self.nmea();
+                self.stream_sample();
+            }
+            if self.outgoing.is_empty() && self.recording {
+                let sample = FakeCompass::sample(self.next_sample);
+                self.next_sample += 1;
+                let reading = Reading {
+                    timestamp: self.next_sample as u32 * 100,
+                    sample,
+                    tilt_correction: self.config.tilt_correction,
+                    heading: heading_from_samples(
+                        sample.accel,
+                        sample.mag,
+                        true,
+                        &self.config.mounting,
+                    )
+                    .0,
+                };
+                let entries: Vec<_> = self
+                    .recorder
+                    .record(&self.calibration, &self.config.mounting, reading)
+                    .collect();
+                for entry in entries {
+                    self.send(&Response::Recording(entry));
+                }
+            }
+            self.outgoing.read(buf)
+        }
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let mut client = Client::new(FakeCompass::new());
+        let Response::Config(mut config) = client.request(&Request::GetConfig).unwrap() else {
+            panic!("no config");
+        };
+        assert!(config.tilt_correction);
+        config.declination = Declination::from_degrees(-7.1);
+        config.display_style = DisplayStyle::Arrow;
+        config.nmea_interval = 0;
+        client.command(&Request::SetConfig(config)).unwrap();
+        assert_eq!(
+            client.request(&Request::GetConfig).unwrap(),
+            Response::Config(config)
+        );
+        assert_eq!(client.port.config, config);
+    }
+
+    #[test]
+    fn calibration_round_trip() {
+        let calibration = Calibration::AxisScale {
+            center: Vector3 {
+                x: -1200,
+                y: 300,
+                z: 2000,
+            },
+            scale: Vector3 {
+                x: 1024,
+                y: 980,
+                z: 1100,
+            },
+            radius: 45000,
+        };
+        let mut client = Client::new(FakeCompass::new());
+        client
+            .command(&Request::SetCalibration(calibration))
+            .unwrap();
+        assert_eq!(
+            client.request(&Request::GetCalibration).unwrap(),
+            Response::Calibration(calibration)
+        );
+        client.command(&Request::StartCalibration).unwrap();
+        assert!(client.port.calibrating);
+    }
+
+    #[test]
+    fn streams_samples() {
+        let mut client = Client::new(FakeCompass::new());
+        client.command(&Request::StreamSamples(true)).unwrap();
+        for index in 0..5 {
+            assert_eq!(client.sample().unwrap(), FakeCompass::sample(index));
+        }
+        // samples still coming in are skipped while waiting for the answer.
+        client.command(&Request::StreamSamples(false)).unwrap();
+        let fake = client.port;
+        assert!(!fake.streaming);
+        assert_eq!(fake.next_sample, 8);
+        assert!(fake.outgoing.is_empty());
+    }
+
+    #[test]
+    fn records_a_file() {
+        let mut client = Client::new(FakeCompass::new());
+        let mut file = Vec::new();
+        assert_eq!(client.record(&mut file, Some(4)).unwrap(), 4);
+        assert!(!client.port.recording);
+
+        let entries: Vec<_> = FileReader::new(&file)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0], Entry::Calibration(Calibration::Uncalibrated));
+        assert_eq!(entries[1], Entry::Mounting(Mounting::default()));
+        let mut player = Player::new();
+        for entry in &entries {
+            if let (Some((heading, _)), Entry::Reading(reading)) = (player.play(entry), entry) {
+                assert_eq!(heading.0.to_bits(), reading.heading.0.to_bits());
+            }
+        }
+    }
+
+    /// a request from a newer version of the protocol.
+    struct NewerRequest;
+
+    impl Message for NewerRequest {
+        fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+            encoder.varint(42)
+        }
+
+        fn decode(_: &mut Decoder) -> Result<Self, ProtocolError> {
+            Err(ProtocolError::UnknownTag(42))
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut fake = FakeCompass::new();
+        let mut out = [0; MAX_FRAME_LEN];
+        fake.write_all(encode_frame(&NewerRequest, &mut out).unwrap())
+            .unwrap();
+        let mut client = Client::new(fake);
+        let error = client.request(&Request::GetConfig).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert!(error.to_string().contains("Malformed"));
+        // the answer to the second request is still there.
+        assert!(matches!(client.receive(), Ok(Response::Config(_))));
+    }
+
+    #[test]
+    fn times_out_without_an_answer() {
+        let mut client = Client::new(io::Cursor::new(Vec::new()));
+        let error = client.request(&Request::GetConfig).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,243 @@
+//! Configures the compass and reads raw samples from it over its USB serial port, using the
+//! binary protocol from `independent_logic::protocol`.
+
+mod client;
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    process::{self, Command},
+};
+
+use independent_logic::{
+    calibration_record,
+    heading_drawing::DisplayStyle,
+    mounting::{Mounting, ALIGNED_COUNT},
+    protocol::{Config, Request, Response},
+    sensor::Sample,
+    tilt_compensation::Declination,
+};
+
+use crate::client::{unexpected, Client};
+
+const USAGE: &str = "usage: host_cli [--port PATH] [--baud RATE] COMMAND
+
+commands:
+    get-config
+    set-config [--tilt on|off] [--declination DEG] [--style STYLE] [--nmea-interval MS]
+               [--mounting N|M11,M12,...,M33]
+    calibrate
+    get-calibration [FILE]
+    set-calibration FILE
+    stream [COUNT]
+    record FILE [COUNT]
+
+set-config only changes the settings that are given. Styles are needle, needle-with-tail,
+perimeter-dot, north-letter and arrow. An NMEA interval of 0 turns the sentences off. Mountings
+are numbered from 1 to 24 as in the settings menu, or given as the 9 elements of a rotation
+matrix, row by row, that takes readings from the boards axes into the enclosures.
+get-calibration prints the calibration, and also saves it to FILE if one is given. set-calibration
+uploads a file saved like that and stores it in the compasses flash.
+stream prints COUNT raw samples, or until interrupted, in the format the simulator replays:
+    accel_x accel_y accel_z mag_x mag_y mag_z
+record saves COUNT readings, or until interrupted, with the calibration and settings they were
+taken with. The simulator replays them exactly.
+--port defaults to /dev/ttyACM0 and --baud to 115200.";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2)
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<String>, name: &str) -> T {
+    let arg = arg.unwrap_or_else(|| usage_error(&format!("missing {}", name)));
+    arg.parse()
+        .unwrap_or_else(|_| usage_error(&format!("invalid {}: {}", name, arg)))
+}
+
+fn style_name(style: DisplayStyle) -> &'static str {
+    match style {
+        DisplayStyle::Needle => "needle",
+        DisplayStyle::NeedleWithTail => "needle-with-tail",
+        DisplayStyle::PerimeterDot => "perimeter-dot",
+        DisplayStyle::NorthLetter => "north-letter",
+        DisplayStyle::Arrow => "arrow",
+    }
+}
+
+fn parse_mounting(arg: Option<String>) -> Mounting {
+    let arg: String = parse_arg(arg, "mounting");
+    let invalid = || usage_error(&format!("invalid mounting: {}", arg));
+    if let Ok(number) = arg.parse::<usize>() {
+        if !(1..=ALIGNED_COUNT).contains(&number) {
+            invalid();
+        }
+        return Mounting::Aligned(number as u8 - 1);
+    }
+    let values: Vec<f32> = arg
+        .split(',')
+        .map(|value| value.trim().parse().unwrap_or_else(|_| invalid()))
+        .collect();
+    if values.len() != 9 {
+        invalid();
+    }
+    let row = |i: usize| [values[i * 3], values[i * 3 + 1], values[i * 3 + 2]];
+    Mounting::custom([row(0), row(1), row(2)])
+        .unwrap_or_else(|| usage_error(&format!("not a rotation: {}", arg)))
+}
+
+fn parse_style(arg: Option<String>) -> DisplayStyle {
+    let arg: String = parse_arg(arg, "style");
+    DisplayStyle::ALL
+        .into_iter()
+        .find(|style| style_name(*style) == arg)
+        .unwrap_or_else(|| usage_error(&format!("unknown style: {}", arg)))
+}
+
+/// opens the serial port raw, with reads returning nothing after a second of silence.
+fn open_port(path: &str, baud: u32) -> io::Result<File> {
+    let file_flag = if cfg!(target_os = "macos") {
+        "-f"
+    } else {
+        "-F"
+    };
+    let status = Command::new("stty")
+        .args([file_flag, path, &baud.to_string()])
+        .args(["raw", "-echo", "min", "0", "time", "10"])
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("stty could not set up {}", path)));
+    }
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+fn print_config(config: &Config) {
+    println!(
+        "tilt correction: {}",
+        if config.tilt_correction { "on" } else { "off" }
+    );
+    println!("declination: {:.1}", config.declination.0.to_degrees());
+    println!("display style: {}", style_name(config.display_style));
+    println!("nmea interval: {} ms", config.nmea_interval);
+    match config.mounting {
+        Mounting::Aligned(index) => println!("mounting: {}", index + 1),
+        Mounting::Custom(matrix) => println!("mounting: {:?}", matrix),
+    }
+}
+
+fn print_sample(sample: &Sample) {
+    println!(
+        "{} {} {} {} {} {}",
+        sample.accel.x, sample.accel.y, sample.accel.z, sample.mag.x, sample.mag.y, sample.mag.z
+    );
+}
+
+fn get_config(client: &mut Client<File>) -> io::Result<Config> {
+    match client.request(&Request::GetConfig)? {
+        Response::Config(config) => Ok(config),
+        response => Err(unexpected(&response)),
+    }
+}
+
+fn run(client: &mut Client<File>, command: &str, mut args: env::Args) -> io::Result<()> {
+    match command {
+        "get-config" => print_config(&get_config(client)?),
+        "set-config" => {
+            let mut config = get_config(client)?;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--tilt" => {
+                        config.tilt_correction =
+                            match parse_arg::<String>(args.next(), "tilt").as_str() {
+                                "on" => true,
+                                "off" => false,
+                                other => usage_error(&format!("invalid tilt: {}", other)),
+                            }
+                    }
+                    "--declination" => {
+                        config.declination =
+                            Declination::from_degrees(parse_arg(args.next(), "declination"))
+                    }
+                    "--style" => config.display_style = parse_style(args.next()),
+                    "--nmea-interval" => {
+                        config.nmea_interval = parse_arg(args.next(), "nmea interval")
+                    }
+                    "--mounting" => config.mounting = parse_mounting(args.next()),
+                    other => usage_error(&format!("unknown argument: {}", other)),
+                }
+            }
+            client.command(&Request::SetConfig(config))?;
+            print_config(&config);
+        }
+        "calibrate" => {
+            client.command(&Request::StartCalibration)?;
+            println!("calibrating, follow the game on the display");
+        }
+        "get-calibration" => match client.request(&Request::GetCalibration)? {
+            Response::Calibration(calibration) => {
+                println!("{:?}", calibration);
+                if let Some(path) = args.next() {
+                    fs::write(path, calibration_record::encode(&calibration))?;
+                }
+            }
+            response => return Err(unexpected(&response)),
+        },
+        "set-calibration" => {
+            let path: String = parse_arg(args.next(), "file");
+            let calibration = calibration_record::decode(&fs::read(&path)?).map_err(|error| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {:?}", path, error))
+            })?;
+            client.command(&Request::SetCalibration(calibration))?;
+            println!("{:?}", calibration);
+        }
+        "stream" => {
+            let count: Option<usize> = args.next().map(|count| parse_arg(Some(count), "count"));
+            client.command(&Request::StreamSamples(true))?;
+            let mut received = 0;
+            while count.is_none_or(|count| received < count) {
+                print_sample(&client.sample()?);
+                received += 1;
+            }
+            client.command(&Request::StreamSamples(false))?;
+        }
+        "record" => {
+            let path: String = parse_arg(args.next(), "file");
+            let count: Option<usize> = args.next().map(|count| parse_arg(Some(count), "count"));
+            let readings = client.record(&mut File::create(&path)?, count)?;
+            println!("recorded {} readings to {}", readings, path);
+        }
+        other => usage_error(&format!("unknown command: {}", other)),
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut args = env::args();
+    args.next();
+    let mut port = "/dev/ttyACM0".to_string();
+    let mut baud = 115200;
+
+    let command = loop {
+        match args.next().as_deref() {
+            Some("--port") => port = parse_arg(args.next(), "port"),
+            Some("--baud") => baud = parse_arg(args.next(), "baud rate"),
+            Some("--help") | Some("-h") => {
+                println!("{}", USAGE);
+                return;
+            }
+            Some(command) => break command.to_string(),
+            None => usage_error("missing command"),
+        }
+    };
+
+    let file = open_port(&port, baud).unwrap_or_else(|error| {
+        eprintln!("could not open {}: {}", port, error);
+        process::exit(1)
+    });
+    let mut client = Client::new(file);
+    if let Err(error) = run(&mut client, &command, args) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
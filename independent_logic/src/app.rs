@@ -0,0 +1,313 @@
+//! The modes the compass can be in, and how button presses move it between them.
+//!
+//! Both buttons together open the menu from any mode. In the menu A and B move between items,
+//! both buttons (or a long press of B) enter the selected one and a long press of A goes back to
+//! the compass.
+//!
+//! Some presses ask the firmware to do something it has the data for, like zeroing the level
+//! against the current attitude. [`App::handle`] returns those as an [`Action`].
+
+use crate::buttons::{Button, Press};
+use crate::heading_drawing::DisplayStyle;
+use crate::mounting::Mounting;
+
+/// the entries of the main menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuItem {
+    Compass,
+    Level,
+    Calibrate,
+    Settings,
+}
+
+impl MenuItem {
+    pub const ALL: [MenuItem; 4] = [
+        MenuItem::Compass,
+        MenuItem::Level,
+        MenuItem::Calibrate,
+        MenuItem::Settings,
+    ];
+
+    pub fn index(self) -> usize {
+        index_of(&MenuItem::ALL, self)
+    }
+
+    fn state(self) -> State {
+        match self {
+            MenuItem::Compass => State::Compass,
+            MenuItem::Level => State::Level,
+            MenuItem::Calibrate => State::Calibrating,
+            MenuItem::Settings => State::Settings(Setting::ALL[0]),
+        }
+    }
+}
+
+/// the entries of the settings menu. A changes the shown setting, B moves on to the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    TiltCorrection,
+    DisplayStyle,
+    /// steps through the [aligned](Mounting::Aligned) mountings.
+    Mounting,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 3] = [
+        Setting::TiltCorrection,
+        Setting::DisplayStyle,
+        Setting::Mounting,
+    ];
+
+    pub fn index(self) -> usize {
+        index_of(&Setting::ALL, self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// showing the heading. A toggles tilt correction, a long press of A switches the display
+    /// style, B starts a calibration and a long press of B scrolls the heading in degrees.
+    Compass,
+    /// showing a spirit level bubble. A scrolls the angles, a long press of A zeros the level
+    /// against the current surface and a long press of B clears that again.
+    Level,
+    /// the firmware is running the calibration and should call [`App::calibration_finished`]
+    /// once it is done. Presses are ignored.
+    Calibrating,
+    Menu(MenuItem),
+    Settings(Setting),
+}
+
+/// things the firmware should do in response to a press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    ShowHeading,
+    ShowAngles,
+    ZeroLevel,
+    ClearLevelZero,
+    /// the mounting was changed and should be stored.
+    SaveMounting,
+}
+
+fn index_of<T: PartialEq>(all: &[T], item: T) -> usize {
+    all.iter().position(|other| *other == item).unwrap_or(0)
+}
+
+/// the item `by` steps away from `item`, wrapping around at either end.
+fn step<T: PartialEq + Copy>(all: &[T], item: T, by: isize) -> T {
+    let index = index_of(all, item) as isize + by;
+    all[index.rem_euclid(all.len() as isize) as usize]
+}
+
+/// The state of the app and the settings the user can change.
+#[derive(Debug, Clone)]
+pub struct App {
+    state: State,
+    pub tilt_correction: bool,
+    pub display_style: DisplayStyle,
+    pub mounting: Mounting,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        App::new()
+    }
+}
+
+impl App {
+    pub fn new() -> App {
+        App {
+            state: State::Compass,
+            tilt_correction: true,
+            display_style: DisplayStyle::default(),
+            mounting: Mounting::default(),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// the menu item for the current state, selected when the menu is opened.
+    fn current_item(&self) -> MenuItem {
+        match self.state {
+            State::Compass => MenuItem::Compass,
+            State::Level => MenuItem::Level,
+            State::Calibrating => MenuItem::Calibrate,
+            State::Menu(item) => item,
+            State::Settings(_) => MenuItem::Settings,
+        }
+    }
+
+    pub fn handle(&mut self, press: Press) -> Option<Action> {
+        let action = match (self.state, press) {
+            (State::Compass, Press::Long(Button::B)) => Some(Action::ShowHeading),
+            (State::Level, Press::Short(Button::A)) => Some(Action::ShowAngles),
+            (State::Level, Press::Long(Button::A)) => Some(Action::ZeroLevel),
+            (State::Level, Press::Long(Button::B)) => Some(Action::ClearLevelZero),
+            (State::Settings(Setting::Mounting), Press::Short(Button::A)) => {
+                Some(Action::SaveMounting)
+            }
+            _ => None,
+        };
+        self.state = match (self.state, press) {
+            (State::Calibrating, _) => State::Calibrating,
+            (State::Menu(item), Press::Both | Press::Long(Button::B)) => item.state(),
+            (_, Press::Both) => State::Menu(self.current_item()),
+
+            (State::Compass, Press::Short(Button::A)) => {
+                self.tilt_correction ^= true;
+                State::Compass
+            }
+            (State::Compass, Press::Long(Button::A)) => {
+                self.display_style = self.display_style.next();
+                State::Compass
+            }
+            (State::Compass, Press::Short(Button::B)) => State::Calibrating,
+
+            (State::Menu(item), Press::Short(Button::A)) => {
+                State::Menu(step(&MenuItem::ALL, item, -1))
+            }
+            (State::Menu(item), Press::Short(Button::B)) => {
+                State::Menu(step(&MenuItem::ALL, item, 1))
+            }
+            (State::Menu(_), Press::Long(Button::A)) => State::Compass,
+
+            (State::Settings(setting), Press::Short(Button::A)) => {
+                self.change(setting);
+                State::Settings(setting)
+            }
+            (State::Settings(setting), Press::Short(Button::B)) => {
+                State::Settings(step(&Setting::ALL, setting, 1))
+            }
+            (State::Settings(_), Press::Long(Button::A)) => State::Menu(MenuItem::Settings),
+
+            (state, _) => state,
+        };
+        action
+    }
+
+    fn change(&mut self, setting: Setting) {
+        match setting {
+            Setting::TiltCorrection => self.tilt_correction ^= true,
+            Setting::DisplayStyle => self.display_style = self.display_style.next(),
+            Setting::Mounting => self.mounting = self.mounting.next(),
+        }
+    }
+
+    /// starts calibrating from any state, for when it is asked for over the serial port.
+    pub fn start_calibration(&mut self) {
+        self.state = State::Calibrating;
+    }
+
+    /// goes back to the compass once a calibration has finished or failed.
+    pub fn calibration_finished(&mut self) {
+        if self.state == State::Calibrating {
+            self.state = State::Compass;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORT_A: Press = Press::Short(Button::A);
+    const SHORT_B: Press = Press::Short(Button::B);
+    const LONG_A: Press = Press::Long(Button::A);
+    const LONG_B: Press = Press::Long(Button::B);
+
+    fn run(app: &mut App, presses: &[Press]) -> State {
+        for press in presses {
+            app.handle(*press);
+        }
+        dbg!(app.state())
+    }
+
+    #[test]
+    fn compass_buttons() {
+        let mut app = App::new();
+        run(&mut app, &[SHORT_A]);
+        assert!(!app.tilt_correction);
+        run(&mut app, &[LONG_A, LONG_A]);
+        assert_eq!(app.display_style, DisplayStyle::default().next().next());
+        assert_eq!(app.handle(LONG_B), Some(Action::ShowHeading));
+        assert_eq!(run(&mut app, &[SHORT_B]), State::Calibrating);
+        assert_eq!(run(&mut app, &[Press::Both, SHORT_A]), State::Calibrating);
+        app.calibration_finished();
+        assert_eq!(app.state(), State::Compass);
+    }
+
+    #[test]
+    fn menu_wraps_around() {
+        let mut app = App::new();
+        assert_eq!(
+            run(&mut app, &[Press::Both]),
+            State::Menu(MenuItem::Compass)
+        );
+        assert_eq!(run(&mut app, &[SHORT_A]), State::Menu(MenuItem::Settings));
+        assert_eq!(run(&mut app, &[SHORT_B]), State::Menu(MenuItem::Compass));
+        assert_eq!(run(&mut app, &[SHORT_B]), State::Menu(MenuItem::Level));
+        assert_eq!(run(&mut app, &[LONG_A]), State::Compass);
+    }
+
+    #[test]
+    fn enter_from_menu() {
+        let mut app = App::new();
+        assert_eq!(
+            run(&mut app, &[Press::Both, SHORT_B, SHORT_B, Press::Both]),
+            State::Calibrating
+        );
+        app.calibration_finished();
+        assert_eq!(
+            run(&mut app, &[Press::Both, SHORT_A, LONG_B]),
+            State::Settings(Setting::TiltCorrection)
+        );
+    }
+
+    #[test]
+    fn level_actions() {
+        let mut app = App::new();
+        assert_eq!(
+            run(&mut app, &[Press::Both, SHORT_B, Press::Both]),
+            State::Level
+        );
+        assert_eq!(app.handle(SHORT_A), Some(Action::ShowAngles));
+        assert_eq!(app.handle(LONG_A), Some(Action::ZeroLevel));
+        assert_eq!(app.handle(LONG_B), Some(Action::ClearLevelZero));
+        assert_eq!(app.handle(SHORT_B), None);
+        assert_eq!(app.state(), State::Level);
+        // both buttons still open the menu.
+        assert_eq!(app.handle(Press::Both), None);
+        assert_eq!(app.state(), State::Menu(MenuItem::Level));
+    }
+
+    #[test]
+    fn settings() {
+        let mut app = App::new();
+        run(&mut app, &[Press::Both, SHORT_A, Press::Both]);
+        run(&mut app, &[SHORT_A]);
+        assert!(!app.tilt_correction);
+        assert_eq!(
+            run(&mut app, &[SHORT_B]),
+            State::Settings(Setting::DisplayStyle)
+        );
+        run(&mut app, &[SHORT_A]);
+        assert_eq!(app.display_style, DisplayStyle::default().next());
+        assert_eq!(
+            run(&mut app, &[SHORT_B]),
+            State::Settings(Setting::Mounting)
+        );
+        assert_eq!(app.handle(SHORT_A), Some(Action::SaveMounting));
+        assert_eq!(app.mounting, Mounting::Aligned(1));
+        // long A goes back a level, both buttons reopen the menu on the settings entry.
+        assert_eq!(run(&mut app, &[LONG_A]), State::Menu(MenuItem::Settings));
+        assert_eq!(
+            run(&mut app, &[LONG_B, Press::Both]),
+            State::Menu(MenuItem::Settings)
+        );
+        assert_eq!(run(&mut app, &[LONG_A]), State::Compass);
+        // the settings are kept.
+        assert!(!app.tilt_correction);
+    }
+}
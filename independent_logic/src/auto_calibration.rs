@@ -0,0 +1,427 @@
+//! Keeps the hard iron offset up to date while the compass is in normal use, without having to go
+//! through the calibration game again.
+
+use libm::{fabsf, roundf, sqrtf};
+
+use crate::calibration::{
+    assess, calibrated_measurement, direction_bucket, dot, invert, mat_vec_mul, solve, Calibration,
+    Matrix3, Vector3, DIRECTION_BUCKETS,
+};
+use crate::frames::EnuReading;
+
+/// how many buckets have to receive a new sample before the offset is refined again.
+pub const REFINE_NEW_BUCKETS: usize = 8;
+/// how many buckets have to hold a sample before the offset is refined at all.
+pub const MIN_FILLED_BUCKETS: usize = 12;
+/// how many updates a sample is kept for, about five minutes at the sensors 10 Hz. Older samples
+/// may be from before the magnetic surroundings changed.
+pub const MAX_SAMPLE_AGE: u32 = 3000;
+
+/// Collects at most one raw magnetometer sample per region of the sphere (see
+/// [`direction_bucket`]), so holding the board still doesn't drown out the other orientations,
+/// and refits the hard iron offset once enough new orientations have been seen. Samples are
+/// dropped after [`MAX_SAMPLE_AGE`] updates. The soft iron correction of the current calibration
+/// is kept as is.
+#[derive(Debug, Clone)]
+pub struct AutoCalibrator {
+    /// each sample, with the update it was taken at.
+    samples: [Option<(EnuReading, u32)>; DIRECTION_BUCKETS],
+    fresh: [bool; DIRECTION_BUCKETS],
+    updates: u32,
+}
+
+impl Default for AutoCalibrator {
+    fn default() -> AutoCalibrator {
+        AutoCalibrator::new()
+    }
+}
+
+impl AutoCalibrator {
+    pub fn new() -> AutoCalibrator {
+        AutoCalibrator {
+            samples: [None; DIRECTION_BUCKETS],
+            fresh: [false; DIRECTION_BUCKETS],
+            updates: 0,
+        }
+    }
+
+    /// forgets all collected samples. Should be called when the calibration is replaced, as the
+    /// samples were bucketed using the old one.
+    pub fn reset(&mut self) {
+        *self = AutoCalibrator::new();
+    }
+
+    /// the number of buckets that currently hold a sample.
+    pub fn filled_buckets(&self) -> usize {
+        self.samples.iter().filter(|s| s.is_some()).count()
+    }
+
+    /// Records a raw measurement. Returns a refined calibration if enough new orientations were
+    /// collected and the refit passes the same checks as
+    /// [`checked_calibration`](crate::calibration::checked_calibration).
+    pub fn update(
+        &mut self,
+        measurement: EnuReading,
+        calibration: &Calibration,
+    ) -> Option<Calibration> {
+        let corrected = calibrated_measurement(measurement, calibration);
+        let bucket = direction_bucket([corrected.x as f32, corrected.y as f32, corrected.z as f32]);
+        self.updates = self.updates.wrapping_add(1);
+        for (sample, fresh) in self.samples.iter_mut().zip(&mut self.fresh) {
+            if let Some((_, taken)) = sample {
+                if self.updates.wrapping_sub(*taken) > MAX_SAMPLE_AGE {
+                    *sample = None;
+                    *fresh = false;
+                }
+            }
+        }
+        self.samples[bucket] = Some((measurement, self.updates));
+        self.fresh[bucket] = true;
+
+        if self.fresh.iter().filter(|f| **f).count() < REFINE_NEW_BUCKETS
+            || self.filled_buckets() < MIN_FILLED_BUCKETS
+        {
+            return None;
+        }
+        self.fresh = [false; DIRECTION_BUCKETS];
+
+        let mut samples = [EnuReading::default(); DIRECTION_BUCKETS];
+        let mut len = 0;
+        for (sample, _) in self.samples.iter().flatten() {
+            samples[len] = *sample;
+            len += 1;
+        }
+        let samples = &samples[..len];
+
+        let refined = refine_offset(calibration, samples)?;
+        assess(&refined, samples).check().ok()?;
+        Some(refined)
+    }
+}
+
+/// the linear part of a calibration, that is applied after subtracting the center.
+fn soft_iron(calibration: &Calibration) -> Matrix3 {
+    match calibration {
+        Calibration::Uncalibrated => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        Calibration::AxisScale { scale, .. } => [
+            [scale.x as f32 / 1024.0, 0.0, 0.0],
+            [0.0, scale.y as f32 / 1024.0, 0.0],
+            [0.0, 0.0, scale.z as f32 / 1024.0],
+        ],
+        Calibration::Ellipsoid { soft_iron, .. } => *soft_iron,
+    }
+}
+
+/// finds the center that puts the samples on a sphere once the calibrations soft iron correction
+/// is applied, and returns the calibration with that center.
+fn refine_offset(calibration: &Calibration, data: &[EnuReading]) -> Option<Calibration> {
+    let matrix = soft_iron(calibration);
+    let mut points = [[0.0; 3]; DIRECTION_BUCKETS];
+    for (point, sample) in points.iter_mut().zip(data) {
+        *point = mat_vec_mul(
+            &matrix,
+            &[sample.x as f32, sample.y as f32, sample.z as f32],
+        );
+    }
+    let (center, radius) = fit_sphere(&points[..data.len()])?;
+    // the sphere was fitted after applying the soft iron matrix, so undo it to get the raw offset.
+    let center = mat_vec_mul(&invert(&matrix)?, &center);
+
+    Some(match calibration {
+        Calibration::Uncalibrated => Calibration::AxisScale {
+            center: round_vector(center),
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: roundf(radius) as u32,
+        },
+        Calibration::AxisScale { scale, .. } => Calibration::AxisScale {
+            center: round_vector(center),
+            scale: *scale,
+            radius: roundf(radius) as u32,
+        },
+        Calibration::Ellipsoid {
+            soft_iron,
+            residual,
+            ..
+        } => Calibration::Ellipsoid {
+            center,
+            soft_iron: *soft_iron,
+            radius,
+            residual: *residual,
+        },
+    })
+}
+
+fn round_vector(vector: [f32; 3]) -> Vector3 {
+    Vector3 {
+        x: roundf(vector[0]) as i32,
+        y: roundf(vector[1]) as i32,
+        z: roundf(vector[2]) as i32,
+    }
+}
+
+/// Least squares sphere fit. Solves |p|^2 = 2 p.c + (r^2 - |c|^2) for the center c and radius r.
+fn fit_sphere(points: &[[f32; 3]]) -> Option<([f32; 3], f32)> {
+    if points.len() < 4 {
+        return None;
+    }
+    // fit in a normalized space to keep the squared terms within f32 precision.
+    let mut mean = [0.0; 3];
+    for point in points {
+        for i in 0..3 {
+            mean[i] += point[i] / points.len() as f32;
+        }
+    }
+    let mut norm: f32 = 0.0;
+    for point in points {
+        for i in 0..3 {
+            norm = norm.max(fabsf(point[i] - mean[i]));
+        }
+    }
+    if norm == 0.0 {
+        return None;
+    }
+
+    let mut normal = [[0.0; 4]; 4];
+    let mut rhs = [0.0; 4];
+    for point in points {
+        let u = [0, 1, 2].map(|i| (point[i] - mean[i]) / norm);
+        let row = [2.0 * u[0], 2.0 * u[1], 2.0 * u[2], 1.0];
+        let target = dot(&u, &u);
+        for i in 0..4 {
+            for j in 0..4 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i] * target;
+        }
+    }
+    let [x, y, z, k] = solve(normal, rhs)?;
+    let center = [x, y, z];
+    let radius_squared = k + dot(&center, &center);
+    if radius_squared <= 0.0 {
+        return None;
+    }
+
+    Some((
+        [0, 1, 2].map(|i| mean[i] + center[i] * norm),
+        sqrtf(radius_squared) * norm,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frames::FrameVector;
+    use std::f32::consts::PI;
+
+    /// points on a sphere, walking around it in a spiral so consecutive samples are close
+    /// together, like they would be while the board is being carried around.
+    fn sphere_walk(center: Vector3, radius: f32, n: usize) -> Vec<EnuReading> {
+        let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+        (0..n)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / n as f32;
+                let r = (1.0 - z * z).sqrt();
+                let theta = golden_angle * i as f32;
+                FrameVector::new(
+                    center.x + (radius * r * theta.cos()) as i32,
+                    center.y + (radius * r * theta.sin()) as i32,
+                    center.z + (radius * z) as i32,
+                )
+            })
+            .collect()
+    }
+
+    fn run(
+        calibrator: &mut AutoCalibrator,
+        calibration: &mut Calibration,
+        samples: &[EnuReading],
+    ) -> usize {
+        let mut refinements = 0;
+        for sample in samples {
+            if let Some(refined) = calibrator.update(*sample, calibration) {
+                *calibration = refined;
+                refinements += 1;
+            }
+        }
+        refinements
+    }
+
+    #[test]
+    fn fit_sphere_recovers_center() {
+        let points: Vec<[f32; 3]> = sphere_walk(
+            Vector3 {
+                x: 2434,
+                y: 5528,
+                z: -40156,
+            },
+            50000.0,
+            20,
+        )
+        .iter()
+        .map(|p| [p.x as f32, p.y as f32, p.z as f32])
+        .collect();
+        let (center, radius) = fit_sphere(&points).unwrap();
+        dbg!(center, radius);
+        assert!((center[0] - 2434.0).abs() < 10.0);
+        assert!((center[1] - 5528.0).abs() < 10.0);
+        assert!((center[2] + 40156.0).abs() < 10.0);
+        assert!((radius - 50000.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn tracks_drifting_offset() {
+        let start = Vector3 {
+            x: 2000,
+            y: 5000,
+            z: -40000,
+        };
+        let mut calibration = Calibration::AxisScale {
+            center: start,
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 50000,
+        };
+        let mut calibrator = AutoCalibrator::new();
+
+        // something magnetic has been added near the board.
+        let drifted = Vector3 {
+            x: 6000,
+            y: 2000,
+            z: -37000,
+        };
+        let refinements = run(
+            &mut calibrator,
+            &mut calibration,
+            &sphere_walk(drifted, 50000.0, 100),
+        );
+        dbg!(calibration, refinements);
+        assert!(refinements > 0);
+        assert!((calibration.center().x - drifted.x).abs() < 50);
+        assert!((calibration.center().y - drifted.y).abs() < 50);
+        assert!((calibration.center().z - drifted.z).abs() < 50);
+    }
+
+    #[test]
+    fn keeps_soft_iron() {
+        let soft_iron = [[1.1, 0.05, 0.0], [0.05, 0.9, 0.0], [0.0, 0.0, 1.0]];
+        let mut calibration = Calibration::Ellipsoid {
+            center: [0.0; 3],
+            soft_iron,
+            radius: 50000.0,
+            residual: 0.01,
+        };
+        let mut calibrator = AutoCalibrator::new();
+
+        // a sphere after correction, so the raw samples are distorted by the inverse.
+        let distortion = invert(&soft_iron).unwrap();
+        let offset = [3000.0, -2000.0, 1000.0];
+        let samples: Vec<EnuReading> = sphere_walk(Vector3::default(), 50000.0, 100)
+            .iter()
+            .map(|p| {
+                let raw = mat_vec_mul(&distortion, &[p.x as f32, p.y as f32, p.z as f32]);
+                let [x, y, z] = [0, 1, 2].map(|i| roundf(raw[i] + offset[i]) as i32);
+                FrameVector::new(x, y, z)
+            })
+            .collect();
+        assert!(run(&mut calibrator, &mut calibration, &samples) > 0);
+        dbg!(calibration);
+
+        let Calibration::Ellipsoid {
+            center,
+            soft_iron: refined_soft_iron,
+            ..
+        } = calibration
+        else {
+            panic!("calibration kind changed")
+        };
+        assert_eq!(refined_soft_iron, soft_iron);
+        for i in 0..3 {
+            assert!((center[i] - offset[i]).abs() < 50.0);
+        }
+    }
+
+    #[test]
+    fn starts_from_uncalibrated() {
+        let offset = Vector3 {
+            x: 2434,
+            y: 5528,
+            z: -40156,
+        };
+        let mut calibration = Calibration::Uncalibrated;
+        let mut calibrator = AutoCalibrator::new();
+        // samples are bucketed around the wrong center to start with, so it takes a few passes.
+        let walk = sphere_walk(offset, 50000.0, 100);
+        for _ in 0..3 {
+            run(&mut calibrator, &mut calibration, &walk);
+        }
+        dbg!(calibration);
+        assert!(matches!(calibration, Calibration::AxisScale { .. }));
+        assert!((calibration.center().x - offset.x).abs() < 50);
+        assert!((calibration.center().y - offset.y).abs() < 50);
+        assert!((calibration.center().z - offset.z).abs() < 50);
+    }
+
+    #[test]
+    fn holding_still_does_not_refine() {
+        let mut calibration = Calibration::AxisScale {
+            center: Vector3::default(),
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 50000,
+        };
+        let mut calibrator = AutoCalibrator::new();
+        let sample = EnuReading::new(3000, 20000, -45000);
+        for _ in 0..1000 {
+            assert_eq!(calibrator.update(sample, &calibration), None);
+        }
+        assert_eq!(calibrator.filled_buckets(), 1);
+
+        // a handful of nearby orientations aren't enough either.
+        let walk = sphere_walk(Vector3::default(), 50000.0, 100);
+        assert_eq!(run(&mut calibrator, &mut calibration, &walk[..10]), 0);
+    }
+
+    #[test]
+    fn forgets_old_samples() {
+        let mut calibration = Calibration::AxisScale {
+            center: Vector3::default(),
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 50000,
+        };
+        let mut calibrator = AutoCalibrator::new();
+        let walk = sphere_walk(Vector3::default(), 50000.0, 100);
+        run(&mut calibrator, &mut calibration, &walk);
+        assert!(calibrator.filled_buckets() >= MIN_FILLED_BUCKETS);
+
+        // the board sits still for a long time, so only the bucket it points into is kept.
+        for _ in 0..=MAX_SAMPLE_AGE {
+            calibrator.update(walk[0], &calibration);
+        }
+        assert_eq!(calibrator.filled_buckets(), 1);
+    }
+
+    #[test]
+    fn reset_forgets_samples() {
+        let mut calibrator = AutoCalibrator::new();
+        for sample in sphere_walk(Vector3::default(), 50000.0, 10) {
+            calibrator.update(sample, &Calibration::Uncalibrated);
+        }
+        assert!(calibrator.filled_buckets() > 1);
+        calibrator.reset();
+        assert_eq!(calibrator.filled_buckets(), 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Turns the raw up/down state of the two buttons into short presses, long presses and presses
+//! of both buttons together.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Press {
+    /// pressed and released before it became a long press.
+    Short(Button),
+    /// held down for at least the long press time. Reported while the button is still held.
+    Long(Button),
+    /// both buttons held down at the same time. Reported as soon as the second one goes down.
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Held {
+    Up,
+    Down {
+        since: u32,
+    },
+    /// still down, but already reported as a press.
+    Reported,
+}
+
+impl Held {
+    fn next(self, down: bool, now: u32) -> Held {
+        match (self, down) {
+            (_, false) => Held::Up,
+            (Held::Up, true) => Held::Down { since: now },
+            (held, true) => held,
+        }
+    }
+}
+
+/// Detects presses from button states sampled over time. Time is in whatever unit the caller
+/// likes, as long as `long_press` is in the same one. It may wrap around.
+#[derive(Debug, Clone)]
+pub struct ButtonTracker {
+    long_press: u32,
+    a: Held,
+    b: Held,
+}
+
+impl ButtonTracker {
+    pub fn new(long_press: u32) -> ButtonTracker {
+        ButtonTracker {
+            long_press,
+            a: Held::Up,
+            b: Held::Up,
+        }
+    }
+
+    /// takes the current state of both buttons, returns a press if one has just finished.
+    pub fn update(&mut self, now: u32, a_down: bool, b_down: bool) -> Option<Press> {
+        let released = |held: Held, down: bool| !down && matches!(held, Held::Down { .. });
+        let (released_a, released_b) = (released(self.a, a_down), released(self.b, b_down));
+        self.a = self.a.next(a_down, now);
+        self.b = self.b.next(b_down, now);
+
+        if a_down && b_down {
+            // a long press of one button doesn't turn into a press of both.
+            let both = self.a != Held::Reported && self.b != Held::Reported;
+            self.a = Held::Reported;
+            self.b = Held::Reported;
+            return both.then_some(Press::Both);
+        }
+        if released_a {
+            return Some(Press::Short(Button::A));
+        }
+        if released_b {
+            return Some(Press::Short(Button::B));
+        }
+        for (held, button) in [(&mut self.a, Button::A), (&mut self.b, Button::B)] {
+            if let Held::Down { since } = *held {
+                if now.wrapping_sub(since) >= self.long_press {
+                    *held = Held::Reported;
+                    return Some(Press::Long(button));
+                }
+            }
+        }
+        None
+    }
+
+    /// treats buttons that are down as already reported, for example after they were used for
+    /// something the tracker didn't see.
+    pub fn ignore_held(&mut self, a_down: bool, b_down: bool) {
+        self.a = if a_down { Held::Reported } else { Held::Up };
+        self.b = if b_down { Held::Reported } else { Held::Up };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// feeds (time, a, b) samples and collects the presses.
+    fn run(samples: &[(u32, bool, bool)]) -> Vec<Press> {
+        let mut tracker = ButtonTracker::new(100);
+        let presses = samples
+            .iter()
+            .filter_map(|(now, a, b)| tracker.update(*now, *a, *b))
+            .collect();
+        dbg!(presses)
+    }
+
+    #[test]
+    fn short_press() {
+        assert_eq!(
+            run(&[(0, true, false), (50, true, false), (60, false, false)]),
+            [Press::Short(Button::A)]
+        );
+        assert_eq!(
+            run(&[(0, false, true), (99, false, false)]),
+            [Press::Short(Button::B)]
+        );
+    }
+
+    #[test]
+    fn long_press_reported_once() {
+        assert_eq!(
+            run(&[
+                (0, false, true),
+                (100, false, true),
+                (200, false, true),
+                (300, false, false)
+            ]),
+            [Press::Long(Button::B)]
+        );
+    }
+
+    #[test]
+    fn both_buttons() {
+        // B goes down a little after A, and they are released in any order.
+        assert_eq!(
+            run(&[
+                (0, true, false),
+                (20, true, true),
+                (200, true, true),
+                (210, false, true),
+                (220, false, false)
+            ]),
+            [Press::Both]
+        );
+    }
+
+    #[test]
+    fn long_press_then_other_button() {
+        assert_eq!(
+            run(&[
+                (0, true, false),
+                (100, true, false),
+                (150, true, true),
+                (160, false, true),
+                (170, false, false)
+            ]),
+            [Press::Long(Button::A)]
+        );
+    }
+
+    #[test]
+    fn time_wraps() {
+        let mut tracker = ButtonTracker::new(100);
+        assert_eq!(tracker.update(u32::MAX - 10, true, false), None);
+        assert_eq!(tracker.update(50, true, false), None);
+        assert_eq!(
+            tracker.update(90, true, false),
+            Some(Press::Long(Button::A))
+        );
+    }
+
+    #[test]
+    fn ignore_held() {
+        let mut tracker = ButtonTracker::new(100);
+        tracker.update(0, true, false);
+        tracker.ignore_held(true, false);
+        assert_eq!(tracker.update(10, false, false), None);
+        assert_eq!(tracker.update(20, true, false), None);
+        assert_eq!(
+            tracker.update(30, false, false),
+            Some(Press::Short(Button::A))
+        );
+    }
+}
@@ -0,0 +1,919 @@
+//! Translated from <https://github.com/lancaster-university/codal-microbit-v2/blob/006abf5566774fbcf674c0c7df27e8a9d20013de/source/MicroBitCompassCalibrator.cpp>
+
+use libm::{fabsf, powf, roundf, sqrtf};
+
+use crate::frames::{EnuReading, FrameVector};
+
+const CALIBRATION_INCREMENT: i32 = 200;
+
+/// the minimum fraction of [`DIRECTION_BUCKETS`] a calibration's samples have to fall into.
+pub const MIN_COVERAGE: f32 = 0.25;
+/// the maximum RMS deviation of corrected samples from their mean radius, as a fraction of it.
+pub const MAX_RESIDUAL: f32 = 0.05;
+/// the maximum difference between the longest and shortest corrected sample, as a fraction of
+/// their mean radius.
+pub const MAX_RADIUS_SPREAD: f32 = 0.3;
+
+/// the number of regions the sphere is split into when measuring coverage: each face of the
+/// enclosing cube split into quarters.
+pub const DIRECTION_BUCKETS: usize = 24;
+
+/// a 3d vector of integer sensor counts, for the parts of a calibration that are stored. Readings
+/// themselves carry their frame, see [`frames`](crate::frames).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Vector3 {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// A magnetometer calibration: a hard iron offset (center) to subtract and a soft iron correction
+/// that turns the ellipsoid traced out by the raw readings back into a sphere.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Calibration {
+    /// no calibration is available, measurements are passed through unchanged.
+    #[default]
+    Uncalibrated,
+    /// per axis soft iron scale, as calculated by [`calibrate`]. scale is fixed point, with 1024
+    /// being a scale of 1.
+    AxisScale {
+        center: Vector3,
+        scale: Vector3,
+        radius: u32,
+    },
+    /// full symmetric soft iron matrix, as calculated by [`fit_ellipsoid`]. The matrix keeps the
+    /// output in sensor units, so a corrected reading has a length of roughly `radius`. residual is
+    /// the RMS deviation of the corrected samples from `radius`, as a fraction of `radius`.
+    Ellipsoid {
+        center: [f32; 3],
+        soft_iron: Matrix3,
+        radius: f32,
+        residual: f32,
+    },
+}
+
+impl Calibration {
+    /// the hard iron offset, rounded to the nearest sensor count.
+    pub fn center(&self) -> Vector3 {
+        match self {
+            Calibration::Uncalibrated => Vector3::default(),
+            Calibration::AxisScale { center, .. } => *center,
+            Calibration::Ellipsoid { center, .. } => Vector3 {
+                x: roundf(center[0]) as i32,
+                y: roundf(center[1]) as i32,
+                z: roundf(center[2]) as i32,
+            },
+        }
+    }
+
+    /// the radius of the sphere the calibrated readings lie on, in sensor counts.
+    pub fn radius(&self) -> u32 {
+        match self {
+            Calibration::Uncalibrated => 0,
+            Calibration::AxisScale { radius, .. } => *radius,
+            Calibration::Ellipsoid { radius, .. } => roundf(*radius) as u32,
+        }
+    }
+}
+
+/// a 3x3 matrix, stored row major.
+pub type Matrix3 = [[f32; 3]; 3];
+
+fn difference_square(a: Vector3, b: EnuReading) -> f32 {
+    let dx = (a.x - b.x) as f32;
+    let dy = (a.y - b.y) as f32;
+    let dz = (a.z - b.z) as f32;
+
+    (dx * dx) + (dy * dy) + (dz * dz)
+}
+
+/// the difference between the squared distance of the furthest and closest points to the center.
+/// a perfect sphere around the center scores 0.
+fn measure_score(center: Vector3, data: &[EnuReading]) -> f32 {
+    let mut min_d = difference_square(center, data[0]);
+    let mut max_d = min_d;
+
+    for point in data[1..].iter() {
+        let d = difference_square(center, *point);
+        if d < min_d {
+            min_d = d;
+        }
+
+        if d > max_d {
+            max_d = d;
+        }
+    }
+
+    max_d - min_d
+}
+
+/// Calculates a calibration from a set of raw samples taken with the sensor in many different
+/// orientations. Panics if data is empty.
+pub fn calibrate(data: &[EnuReading]) -> Calibration {
+    // Approximate a center for the data
+    let mut center = Vector3 { x: 0, y: 0, z: 0 };
+    let mut best = center;
+
+    for point in data {
+        center.x += point.x;
+        center.y += point.y;
+        center.z += point.z;
+    }
+
+    center.x /= data.len() as i32;
+    center.y /= data.len() as i32;
+    center.z /= data.len() as i32;
+
+    let mut current = center;
+    let mut score = measure_score(current, data);
+
+    // Calculate a fixpoint position
+    loop {
+        for x in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+            for y in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+                for z in [-CALIBRATION_INCREMENT, 0, CALIBRATION_INCREMENT] {
+                    let mut attempt = current;
+                    attempt.x += x;
+                    attempt.y += y;
+                    attempt.z += z;
+
+                    let attempt_score = measure_score(attempt, data);
+                    if attempt_score < score {
+                        score = attempt_score;
+                        best = attempt;
+                    }
+                }
+            }
+        }
+
+        if best == current {
+            break;
+        }
+
+        current = best;
+    }
+
+    spherify(current, data)
+}
+
+fn spherify(center: Vector3, data: &[EnuReading]) -> Calibration {
+    let mut radius = 0;
+    for point in data {
+        let d = sqrtf(difference_square(center, *point)) as u32;
+        if d > radius {
+            radius = d;
+        }
+    }
+
+    let mut scale: f32 = 0.0;
+    let mut weight_x = 0.0;
+    let mut weight_y = 0.0;
+    let mut weight_z = 0.0;
+
+    for point in data {
+        let d = sqrtf(difference_square(center, *point));
+        let s = (radius as f32 / d) - 1.0;
+        scale = scale.max(s);
+
+        let dx = point.x - center.x;
+        let dy = point.y - center.y;
+        let dz = point.z - center.z;
+
+        weight_x += s * fabsf(dx as f32 / d);
+        weight_y += s * fabsf(dy as f32 / d);
+        weight_z += s * fabsf(dz as f32 / d);
+    }
+
+    let wmag = sqrtf((weight_x * weight_x) + (weight_y * weight_y) + (weight_z * weight_z));
+    let scale_x = 1.0 + scale * (weight_x / wmag);
+    let scale_y = 1.0 + scale * (weight_y / wmag);
+    let scale_z = 1.0 + scale * (weight_z / wmag);
+
+    Calibration::AxisScale {
+        center,
+        radius,
+        scale: Vector3 {
+            x: (1024.0 * scale_x) as i32,
+            y: (1024.0 * scale_y) as i32,
+            z: (1024.0 * scale_z) as i32,
+        },
+    }
+}
+
+/// removes the hard iron offset and applies the soft iron correction to a raw measurement.
+pub fn calibrated_measurement(measurement: EnuReading, calibration: &Calibration) -> EnuReading {
+    match calibration {
+        Calibration::Uncalibrated => measurement,
+        Calibration::AxisScale { center, scale, .. } => FrameVector::new(
+            ((measurement.x - center.x) * scale.x) >> 10,
+            ((measurement.y - center.y) * scale.y) >> 10,
+            ((measurement.z - center.z) * scale.z) >> 10,
+        ),
+        Calibration::Ellipsoid {
+            center, soft_iron, ..
+        } => {
+            let out = mat_vec_mul(
+                soft_iron,
+                &[
+                    measurement.x as f32 - center[0],
+                    measurement.y as f32 - center[1],
+                    measurement.z as f32 - center[2],
+                ],
+            );
+            FrameVector::new(
+                roundf(out[0]) as i32,
+                roundf(out[1]) as i32,
+                roundf(out[2]) as i32,
+            )
+        }
+    }
+}
+
+/// Fits an arbitrarily rotated ellipsoid to the samples using linear least squares, and derives the
+/// hard iron offset and the symmetric soft iron matrix that maps the ellipsoid back onto a sphere.
+/// Returns none if there are fewer than 9 samples, or the samples do not describe an ellipsoid
+/// (for example, because they all lie in a plane).
+pub fn fit_ellipsoid(data: &[EnuReading]) -> Option<Calibration> {
+    if data.len() < 9 {
+        return None;
+    }
+
+    // Squaring raw readings in the tens of thousands loses too much precision in f32, so fit in a
+    // normalized space centered on the mean with coordinates of roughly unit size.
+    let mut mean = [0.0; 3];
+    for point in data {
+        mean[0] += point.x as f32;
+        mean[1] += point.y as f32;
+        mean[2] += point.z as f32;
+    }
+    for m in mean.iter_mut() {
+        *m /= data.len() as f32;
+    }
+    let mut norm: f32 = 0.0;
+    for point in data {
+        let u = normalize(point, &mean, 1.0);
+        norm = norm.max(fabsf(u[0])).max(fabsf(u[1])).max(fabsf(u[2]));
+    }
+    if norm == 0.0 {
+        return None;
+    }
+
+    // Fit the general quadric
+    // ax^2 + by^2 + cz^2 + 2fyz + 2gxz + 2hxy + 2px + 2qy + 2rz = 1
+    // by solving the normal equations.
+    let mut normal = [[0.0; 9]; 9];
+    let mut rhs = [0.0; 9];
+    for point in data {
+        let [x, y, z] = normalize(point, &mean, norm);
+        let row = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * y * z,
+            2.0 * x * z,
+            2.0 * x * y,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for i in 0..9 {
+            for j in 0..9 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i];
+        }
+    }
+    let [a, b, c, f, g, h, p, q, r] = solve(normal, rhs)?;
+
+    let quadric = [[a, h, g], [h, b, f], [g, f, c]];
+    let linear = [p, q, r];
+
+    // (u - center)^T A (u - center) = 1 + center^T A center
+    let center = mat_vec_mul(&invert(&quadric)?, &linear).map(|v| -v);
+    let k = 1.0 + dot(&center, &mat_vec_mul(&quadric, &center));
+    if k <= 0.0 {
+        return None;
+    }
+    let shape = quadric.map(|row| row.map(|v| v / k));
+
+    // The square root of the shape matrix maps the ellipsoid onto the unit sphere.
+    let (eigenvalues, eigenvectors) = symmetric_eigen(&shape);
+    if eigenvalues.iter().any(|l| *l <= 0.0) {
+        return None;
+    }
+    let unit_radius = powf(eigenvalues[0] * eigenvalues[1] * eigenvalues[2], -1.0 / 6.0);
+    let mut soft_iron = [[0.0; 3]; 3];
+    for (i, row) in soft_iron.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            for k in 0..3 {
+                *value += eigenvectors[i][k] * sqrtf(eigenvalues[k]) * eigenvectors[j][k];
+            }
+            // scale so the output stays in sensor units. The normalization cancels out here.
+            *value *= unit_radius;
+        }
+    }
+
+    let center = [
+        mean[0] + center[0] * norm,
+        mean[1] + center[1] * norm,
+        mean[2] + center[2] * norm,
+    ];
+    let radius = unit_radius * norm;
+
+    let mut residual = 0.0;
+    for point in data {
+        let corrected = mat_vec_mul(&soft_iron, &normalize(point, &center, 1.0));
+        let error = (sqrtf(dot(&corrected, &corrected)) - radius) / radius;
+        residual += error * error;
+    }
+    let residual = sqrtf(residual / data.len() as f32);
+
+    Some(Calibration::Ellipsoid {
+        center,
+        soft_iron,
+        radius,
+        residual,
+    })
+}
+
+/// How well a set of samples pins down a calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationQuality {
+    /// the fraction of [`DIRECTION_BUCKETS`] that contain at least one sample, from 0 to 1.
+    pub coverage: f32,
+    /// RMS deviation of the corrected samples from their mean radius, as a fraction of it.
+    pub residual: f32,
+    /// difference between the longest and shortest corrected sample, as a fraction of their mean
+    /// radius.
+    pub radius_spread: f32,
+}
+
+impl CalibrationQuality {
+    /// rejects the quality if the samples don't cover enough of the sphere or don't agree with
+    /// the calibration.
+    pub fn check(self) -> Result<CalibrationQuality, CalibrationError> {
+        // NaN (from every corrected sample being zero) must fail these checks too.
+        if self.coverage < MIN_COVERAGE {
+            Err(CalibrationError::PoorCoverage(self))
+        } else if self.residual.is_nan() || self.residual > MAX_RESIDUAL {
+            Err(CalibrationError::HighResidual(self))
+        } else if self.radius_spread.is_nan() || self.radius_spread > MAX_RADIUS_SPREAD {
+            Err(CalibrationError::InconsistentRadius(self))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Reasons a set of samples was rejected by [`checked_calibration`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationError {
+    /// fewer samples than an ellipsoid fit needs.
+    NotEnoughSamples,
+    /// the samples are bunched up on one side of the sphere.
+    PoorCoverage(CalibrationQuality),
+    /// the samples don't lie on an ellipsoid, usually because the board was moved too quickly or
+    /// was near something magnetic.
+    HighResidual(CalibrationQuality),
+    /// the corrected samples are at very different distances from the center.
+    InconsistentRadius(CalibrationQuality),
+}
+
+/// sorts a direction into one of [`DIRECTION_BUCKETS`] roughly equal regions of the sphere.
+pub fn direction_bucket(direction: [f32; 3]) -> usize {
+    let [x, y, z] = direction.map(fabsf);
+    // the axis the direction points along the most picks the cube face, the signs of the other two
+    // pick the quarter of that face.
+    let (axis, a, b) = if x >= y && x >= z {
+        (0, 1, 2)
+    } else if y >= z {
+        (1, 0, 2)
+    } else {
+        (2, 0, 1)
+    };
+    let face = axis * 2 + (direction[axis] < 0.0) as usize;
+    face * 4 + (direction[a] < 0.0) as usize * 2 + (direction[b] < 0.0) as usize
+}
+
+/// Measures how well the samples agree with a calibration.
+pub fn assess(calibration: &Calibration, data: &[EnuReading]) -> CalibrationQuality {
+    let mut buckets = [false; DIRECTION_BUCKETS];
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    let mut min: f32 = f32::MAX;
+    let mut max: f32 = 0.0;
+    for point in data {
+        let corrected = calibrated_measurement(*point, calibration);
+        let corrected = [corrected.x as f32, corrected.y as f32, corrected.z as f32];
+        buckets[direction_bucket(corrected)] = true;
+
+        let radius = sqrtf(dot(&corrected, &corrected));
+        sum += radius;
+        sum_squares += radius * radius;
+        min = min.min(radius);
+        max = max.max(radius);
+    }
+
+    let n = data.len() as f32;
+    let mean = sum / n;
+    let variance = (sum_squares / n - mean * mean).max(0.0);
+    CalibrationQuality {
+        coverage: buckets.iter().filter(|b| **b).count() as f32 / DIRECTION_BUCKETS as f32,
+        residual: sqrtf(variance) / mean,
+        radius_spread: (max - min) / mean,
+    }
+}
+
+/// Calculates a calibration, preferring [`fit_ellipsoid`] and falling back to [`calibrate`], and
+/// rejects it if the samples don't cover enough of the sphere or don't agree with the result.
+pub fn checked_calibration(
+    data: &[EnuReading],
+) -> Result<(Calibration, CalibrationQuality), CalibrationError> {
+    if data.len() < 9 {
+        return Err(CalibrationError::NotEnoughSamples);
+    }
+    let calibration = fit_ellipsoid(data).unwrap_or_else(|| calibrate(data));
+    let quality = assess(&calibration, data).check()?;
+    Ok((calibration, quality))
+}
+
+fn normalize(point: &EnuReading, offset: &[f32; 3], scale: f32) -> [f32; 3] {
+    [
+        (point.x as f32 - offset[0]) / scale,
+        (point.y as f32 - offset[1]) / scale,
+        (point.z as f32 - offset[2]) / scale,
+    ]
+}
+
+pub(crate) fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn mat_vec_mul(matrix: &Matrix3, vector: &[f32; 3]) -> [f32; 3] {
+    matrix.map(|row| dot(&row, vector))
+}
+
+pub(crate) fn invert(m: &Matrix3) -> Option<Matrix3> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    let det = m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    Some(adjugate.map(|row| row.map(|v| v / det)))
+}
+
+/// solves ax=b using gaussian elimination with partial pivoting. Returns none if a is singular.
+pub(crate) fn solve<const N: usize>(mut a: [[f32; N]; N], mut b: [f32; N]) -> Option<[f32; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|i, j| fabsf(a[*i][col]).total_cmp(&fabsf(a[*j][col])))?;
+        if fabsf(a[pivot][col]) < f32::EPSILON {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col + 1..N {
+            let factor = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (k, value) in a[row].iter_mut().enumerate().skip(col) {
+                *value -= factor * pivot_row[k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let mut sum = b[row];
+        for k in row + 1..N {
+            sum -= a[row][k] * x[k];
+        }
+        x[row] = sum / a[row][row];
+    }
+    Some(x)
+}
+
+/// Eigen decomposition of a symmetric matrix using the cyclic Jacobi method.
+/// Returns the eigenvalues, and a matrix with the corresponding eigenvectors as its columns.
+fn symmetric_eigen(m: &Matrix3) -> ([f32; 3], Matrix3) {
+    let mut a = *m;
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..16 {
+        let off_diagonal = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off_diagonal < 1e-12 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            // rotation that zeroes a[p][q]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (fabsf(theta) + sqrtf(theta * theta + 1.0));
+            let c = 1.0 / sqrtf(t * t + 1.0);
+            let s = t * c;
+            for row in a.iter_mut() {
+                let akp = row[p];
+                let akq = row[q];
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = core::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = core::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let vkp = row[p];
+                let vkq = row[q];
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// evenly distributes points over the unit sphere using a fibonacci lattice, then maps them
+    /// through the distortion matrix and offsets them.
+    fn distorted_points<const N: usize>(center: Vector3, distortion: &Matrix3) -> [EnuReading; N] {
+        let golden_angle = PI * (3.0 - 5.0_f32.sqrt());
+        let mut points = [EnuReading::default(); N];
+        for (i, point) in points.iter_mut().enumerate() {
+            let z = 1.0 - 2.0 * (i as f32 + 0.5) / N as f32;
+            let r = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f32;
+            let p = mat_vec_mul(distortion, &[r * theta.cos(), r * theta.sin(), z]);
+            *point = FrameVector::new(
+                center.x + p[0] as i32,
+                center.y + p[1] as i32,
+                center.z + p[2] as i32,
+            );
+        }
+        points
+    }
+
+    fn ellipsoid_points<const N: usize>(
+        center: Vector3,
+        radii: (f32, f32, f32),
+    ) -> [EnuReading; N] {
+        distorted_points(
+            center,
+            &[
+                [radii.0, 0.0, 0.0],
+                [0.0, radii.1, 0.0],
+                [0.0, 0.0, radii.2],
+            ],
+        )
+    }
+
+    /// a symmetric distortion matrix with the given radii along axes rotated about z and then x.
+    fn rotated_distortion(radii: [f32; 3], yaw: f32, roll: f32) -> Matrix3 {
+        let rz = [
+            [yaw.cos(), -yaw.sin(), 0.0],
+            [yaw.sin(), yaw.cos(), 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let rx = [
+            [1.0, 0.0, 0.0],
+            [0.0, roll.cos(), -roll.sin()],
+            [0.0, roll.sin(), roll.cos()],
+        ];
+        let mut rotation = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    rotation[i][j] += rx[i][k] * rz[k][j];
+                }
+            }
+        }
+        let mut out = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    out[i][j] += rotation[i][k] * radii[k] * rotation[j][k];
+                }
+            }
+        }
+        out
+    }
+
+    fn radius_spread(points: &[EnuReading], calibration: &Calibration) -> f32 {
+        measure_score(
+            Vector3::default(),
+            &points
+                .iter()
+                .map(|p| calibrated_measurement(*p, calibration))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn scale(calibration: &Calibration) -> Vector3 {
+        match calibration {
+            Calibration::AxisScale { scale, .. } => *scale,
+            _ => panic!("not an axis scale calibration"),
+        }
+    }
+
+    fn assert_center_near(calibration: &Calibration, center: Vector3, tolerance: i32) {
+        assert!((calibration.center().x - center.x).abs() <= tolerance);
+        assert!((calibration.center().y - center.y).abs() <= tolerance);
+        assert!((calibration.center().z - center.z).abs() <= tolerance);
+    }
+
+    #[test]
+    fn centered_sphere() {
+        let points = ellipsoid_points::<25>(Vector3::default(), (50000.0, 50000.0, 50000.0));
+        let calibration = calibrate(&points);
+        dbg!(calibration);
+        assert_center_near(&calibration, Vector3::default(), CALIBRATION_INCREMENT);
+        assert!((calibration.radius() as i32 - 50000).abs() < 500);
+    }
+
+    #[test]
+    fn offset_sphere() {
+        let offset = Vector3 {
+            x: 2434,
+            y: 5528,
+            z: -40156,
+        };
+        let points = ellipsoid_points::<25>(offset, (50000.0, 50000.0, 50000.0));
+        let calibration = calibrate(&points);
+        dbg!(calibration);
+        assert_center_near(&calibration, offset, CALIBRATION_INCREMENT);
+        // a sphere needs (almost) no soft iron correction.
+        assert!((scale(&calibration).x - 1024).abs() < 50);
+        assert!((scale(&calibration).y - 1024).abs() < 50);
+        assert!((scale(&calibration).z - 1024).abs() < 50);
+    }
+
+    #[test]
+    fn offset_ellipsoid() {
+        let offset = Vector3 {
+            x: -12000,
+            y: 3000,
+            z: 20000,
+        };
+        let points = ellipsoid_points::<25>(offset, (50000.0, 50000.0, 40000.0));
+        let calibration = calibrate(&points);
+        dbg!(calibration);
+        assert_center_near(&calibration, offset, 2 * CALIBRATION_INCREMENT);
+        // the squashed axis should be stretched the most.
+        assert!(scale(&calibration).z > scale(&calibration).x);
+        assert!(scale(&calibration).z > scale(&calibration).y);
+
+        let uncalibrated = Calibration::AxisScale {
+            center: offset,
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 0,
+        };
+        assert!(radius_spread(&points, &calibration) < radius_spread(&points, &uncalibrated));
+    }
+
+    #[test]
+    fn calibrated_measurement_removes_offset() {
+        let calibration = Calibration::AxisScale {
+            center: Vector3 {
+                x: 100,
+                y: -200,
+                z: 300,
+            },
+            scale: Vector3 {
+                x: 1024,
+                y: 2048,
+                z: 512,
+            },
+            radius: 0,
+        };
+        assert_eq!(
+            calibrated_measurement(FrameVector::new(100, -200, 300), &calibration),
+            EnuReading::default()
+        );
+        assert_eq!(
+            calibrated_measurement(FrameVector::new(1100, 800, 1300), &calibration),
+            FrameVector::new(1000, 2000, 500)
+        );
+    }
+
+    #[test]
+    fn uncalibrated_passes_through() {
+        let measurement = EnuReading::new(1100, 800, -1300);
+        assert_eq!(
+            calibrated_measurement(measurement, &Calibration::Uncalibrated),
+            measurement
+        );
+    }
+
+    #[test]
+    fn fit_offset_sphere() {
+        let offset = Vector3 {
+            x: 2434,
+            y: 5528,
+            z: -40156,
+        };
+        let points = ellipsoid_points::<25>(offset, (50000.0, 50000.0, 50000.0));
+        let calibration = fit_ellipsoid(&points).unwrap();
+        dbg!(calibration);
+        assert_center_near(&calibration, offset, 20);
+        assert!((calibration.radius() as i32 - 50000).abs() < 50);
+        let Calibration::Ellipsoid {
+            soft_iron,
+            residual,
+            ..
+        } = calibration
+        else {
+            panic!("not an ellipsoid calibration")
+        };
+        for (i, row) in soft_iron.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-3);
+            }
+        }
+        assert!(residual < 1e-3);
+    }
+
+    #[test]
+    fn fit_rotated_ellipsoid() {
+        let offset = Vector3 {
+            x: -12000,
+            y: 3000,
+            z: 20000,
+        };
+        let distortion = rotated_distortion([55000.0, 45000.0, 35000.0], 0.6, -0.4);
+        let points = distorted_points::<50>(offset, &distortion);
+        let calibration = fit_ellipsoid(&points).unwrap();
+        dbg!(calibration);
+        assert_center_near(&calibration, offset, 20);
+
+        let Calibration::Ellipsoid {
+            soft_iron,
+            radius,
+            residual,
+            ..
+        } = calibration
+        else {
+            panic!("not an ellipsoid calibration")
+        };
+        assert!(residual < 1e-3);
+
+        // undoing the distortion should leave a sphere of the fitted radius.
+        let mut undistorted = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    undistorted[i][j] += soft_iron[i][k] * distortion[k][j];
+                }
+            }
+        }
+        for (i, row) in undistorted.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { radius } else { 0.0 };
+                assert!((value - expected).abs() < radius * 1e-3);
+            }
+        }
+
+        // the axis scale calibration can't correct a rotated ellipsoid nearly as well.
+        assert!(radius_spread(&points, &calibration) < radius_spread(&points, &calibrate(&points)));
+    }
+
+    #[test]
+    fn fit_reports_residual_for_noisy_data() {
+        let mut points = ellipsoid_points::<25>(Vector3::default(), (50000.0, 50000.0, 50000.0));
+        for (i, point) in points.iter_mut().enumerate() {
+            let noise = if i % 2 == 0 { 1000 } else { -1000 };
+            point.x += noise;
+            point.y -= noise;
+        }
+        let Some(Calibration::Ellipsoid { residual, .. }) = fit_ellipsoid(&points) else {
+            panic!("fit failed")
+        };
+        dbg!(residual);
+        assert!(residual > 1e-3);
+        assert!(residual < 0.1);
+    }
+
+    #[test]
+    fn direction_buckets_are_distinct() {
+        let mut seen = [false; DIRECTION_BUCKETS];
+        for face in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            for sign in [1.0, -1.0] {
+                for a in [0.1, -0.1] {
+                    for b in [0.2, -0.2] {
+                        let mut direction = face.map(|v: f32| v * sign);
+                        let others: Vec<usize> = (0..3).filter(|i| face[*i] == 0.0).collect();
+                        direction[others[0]] = a;
+                        direction[others[1]] = b;
+                        let bucket = direction_bucket(direction);
+                        assert!(!seen[bucket], "{:?} reused bucket {}", direction, bucket);
+                        seen[bucket] = true;
+                    }
+                }
+            }
+        }
+        assert!(seen.iter().all(|b| *b));
+    }
+
+    #[test]
+    fn good_samples_are_accepted() {
+        let offset = Vector3 {
+            x: -12000,
+            y: 3000,
+            z: 20000,
+        };
+        let distortion = rotated_distortion([55000.0, 45000.0, 35000.0], 0.6, -0.4);
+        let points = distorted_points::<25>(offset, &distortion);
+        let (calibration, quality) = checked_calibration(&points).unwrap();
+        dbg!(quality);
+        assert!(matches!(calibration, Calibration::Ellipsoid { .. }));
+        assert!(quality.coverage > 0.5);
+        assert!(quality.residual < 1e-3);
+        assert!(quality.radius_spread < 1e-2);
+    }
+
+    #[test]
+    fn clustered_samples_are_rejected() {
+        // every sample within ~25 degrees of +z, as if the board was barely moved.
+        let points: Vec<EnuReading> = ellipsoid_points::<200>(
+            Vector3 {
+                x: 2434,
+                y: 5528,
+                z: -40156,
+            },
+            (50000.0, 50000.0, 50000.0),
+        )
+        .into_iter()
+        .filter(|p| p.z + 40156 > 45000)
+        .collect();
+        assert!(points.len() >= 9);
+        let result = checked_calibration(&points);
+        dbg!(&result);
+        assert!(matches!(result, Err(CalibrationError::PoorCoverage(_))));
+    }
+
+    #[test]
+    fn noisy_samples_are_rejected() {
+        let mut points = ellipsoid_points::<25>(Vector3::default(), (50000.0, 50000.0, 50000.0));
+        for (i, point) in points.iter_mut().enumerate() {
+            let noise = [0, 15000, -5000, -15000, 5000][i % 5];
+            point.x += noise;
+            point.z -= noise;
+        }
+        let result = checked_calibration(&points);
+        dbg!(&result);
+        assert!(matches!(
+            result,
+            Err(CalibrationError::HighResidual(_)) | Err(CalibrationError::InconsistentRadius(_))
+        ));
+    }
+
+    #[test]
+    fn too_few_samples_are_rejected() {
+        let points = ellipsoid_points::<8>(Vector3::default(), (50000.0, 50000.0, 50000.0));
+        assert_eq!(
+            checked_calibration(&points),
+            Err(CalibrationError::NotEnoughSamples)
+        );
+        assert!(matches!(
+            checked_calibration(&[EnuReading::default(); 25]),
+            Err(CalibrationError::PoorCoverage(_))
+        ));
+    }
+
+    #[test]
+    fn fit_rejects_degenerate_data() {
+        assert_eq!(fit_ellipsoid(&[EnuReading::default(); 8]), None);
+        assert_eq!(fit_ellipsoid(&[EnuReading::default(); 25]), None);
+
+        // all in the z=0 plane
+        let flat = ellipsoid_points::<25>(Vector3::default(), (50000.0, 50000.0, 0.0));
+        assert_eq!(fit_ellipsoid(&flat), None);
+    }
+}
@@ -0,0 +1,299 @@
+//! Binary format used to persist a [`Calibration`] in flash.
+//!
+//! All fields are little endian:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic, `b"CMPS"`                              |
+//! | 4      | 2    | format version                                |
+//! | 6      | 1    | calibration kind                              |
+//! | 7      | 1    | reserved, always 0                            |
+//! | 8      | 56   | payload, zero padded                          |
+//! | 64     | 4    | CRC-32 (IEEE) of all preceding bytes          |
+//!
+//! The length is a multiple of 4 so it can be written to flash one word at a time.
+
+use crate::calibration::{Calibration, Matrix3, Vector3};
+
+pub const RECORD_LEN: usize = 68;
+pub const MAGIC: [u8; 4] = *b"CMPS";
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 8;
+const PAYLOAD_LEN: usize = 56;
+const CRC_OFFSET: usize = HEADER_LEN + PAYLOAD_LEN;
+
+const KIND_UNCALIBRATED: u8 = 0;
+const KIND_AXIS_SCALE: u8 = 1;
+const KIND_ELLIPSOID: u8 = 2;
+
+/// Reasons a stored record could not be turned back into a [`Calibration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordError {
+    /// fewer than [`RECORD_LEN`] bytes were given.
+    TooShort,
+    /// the record does not start with [`MAGIC`], usually because the flash page is erased.
+    BadMagic,
+    /// the record was written by an incompatible firmware.
+    UnsupportedVersion(u16),
+    /// the record is corrupt.
+    BadChecksum,
+    /// the record contains a calibration kind this firmware does not know about.
+    UnknownKind(u8),
+    /// a field of the record has a value outside of its range.
+    InvalidValue,
+}
+
+/// serializes a calibration into a record ready to be written to flash.
+pub fn encode(calibration: &Calibration) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[0..4].copy_from_slice(&MAGIC);
+    record[4..6].copy_from_slice(&VERSION.to_le_bytes());
+
+    let mut payload = Writer {
+        buf: &mut record[HEADER_LEN..CRC_OFFSET],
+        pos: 0,
+    };
+    let kind = match calibration {
+        Calibration::Uncalibrated => KIND_UNCALIBRATED,
+        Calibration::AxisScale {
+            center,
+            scale,
+            radius,
+        } => {
+            payload.vector3(center);
+            payload.vector3(scale);
+            payload.bytes(&radius.to_le_bytes());
+            KIND_AXIS_SCALE
+        }
+        Calibration::Ellipsoid {
+            center,
+            soft_iron,
+            radius,
+            residual,
+        } => {
+            for value in center.iter().chain(soft_iron.iter().flatten()) {
+                payload.f32(*value);
+            }
+            payload.f32(*radius);
+            payload.f32(*residual);
+            KIND_ELLIPSOID
+        }
+    };
+    record[6] = kind;
+
+    let crc = crc32(&record[..CRC_OFFSET]);
+    record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// validates and deserializes a record read back from flash.
+pub fn decode(record: &[u8]) -> Result<Calibration, RecordError> {
+    if record.len() < RECORD_LEN {
+        return Err(RecordError::TooShort);
+    }
+    if record[0..4] != MAGIC {
+        return Err(RecordError::BadMagic);
+    }
+    let version = u16::from_le_bytes([record[4], record[5]]);
+    if version != VERSION {
+        return Err(RecordError::UnsupportedVersion(version));
+    }
+    let crc = u32::from_le_bytes(record[CRC_OFFSET..RECORD_LEN].try_into().unwrap());
+    if crc != crc32(&record[..CRC_OFFSET]) {
+        return Err(RecordError::BadChecksum);
+    }
+
+    let mut payload = Reader {
+        buf: &record[HEADER_LEN..CRC_OFFSET],
+        pos: 0,
+    };
+    match record[6] {
+        KIND_UNCALIBRATED => Ok(Calibration::Uncalibrated),
+        KIND_AXIS_SCALE => Ok(Calibration::AxisScale {
+            center: payload.vector3(),
+            scale: payload.vector3(),
+            radius: payload.u32(),
+        }),
+        KIND_ELLIPSOID => {
+            let center = [payload.f32(), payload.f32(), payload.f32()];
+            let mut soft_iron: Matrix3 = [[0.0; 3]; 3];
+            for value in soft_iron.iter_mut().flatten() {
+                *value = payload.f32();
+            }
+            Ok(Calibration::Ellipsoid {
+                center,
+                soft_iron,
+                radius: payload.f32(),
+                residual: payload.f32(),
+            })
+        }
+        kind => Err(RecordError::UnknownKind(kind)),
+    }
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zip and ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn vector3(&mut self, vector: &Vector3) {
+        self.bytes(&vector.x.to_le_bytes());
+        self.bytes(&vector.y.to_le_bytes());
+        self.bytes(&vector.z.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> [u8; 4] {
+        let word = self.buf[self.pos..self.pos + 4].try_into().unwrap();
+        self.pos += 4;
+        word
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.word())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.word())
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.word())
+    }
+
+    fn vector3(&mut self) -> Vector3 {
+        Vector3 {
+            x: self.i32(),
+            y: self.i32(),
+            z: self.i32(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis_scale() -> Calibration {
+        Calibration::AxisScale {
+            center: Vector3 {
+                x: 2434,
+                y: 5528,
+                z: -40156,
+            },
+            scale: Vector3 {
+                x: 1044,
+                y: 1042,
+                z: 1049,
+            },
+            radius: 61751,
+        }
+    }
+
+    fn ellipsoid() -> Calibration {
+        Calibration::Ellipsoid {
+            center: [-12000.5, 3000.25, 20000.0],
+            soft_iron: [[1.1, 0.05, -0.02], [0.05, 0.9, 0.01], [-0.02, 0.01, 1.0]],
+            radius: 45000.0,
+            residual: 0.012,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        for calibration in [Calibration::Uncalibrated, axis_scale(), ellipsoid()] {
+            assert_eq!(decode(&encode(&calibration)), Ok(calibration));
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let record = encode(&axis_scale());
+        assert_eq!(&record[0..4], b"CMPS");
+        assert_eq!(&record[4..6], &[1, 0]);
+        assert_eq!(record[6], KIND_AXIS_SCALE);
+        assert_eq!(&record[8..12], &2434_i32.to_le_bytes());
+        // unused payload is zero padded.
+        assert!(record[HEADER_LEN + 28..CRC_OFFSET].iter().all(|b| *b == 0));
+        assert_eq!(RECORD_LEN % 4, 0);
+    }
+
+    #[test]
+    fn erased_flash() {
+        assert_eq!(decode(&[0xFF; RECORD_LEN]), Err(RecordError::BadMagic));
+    }
+
+    #[test]
+    fn too_short() {
+        assert_eq!(
+            decode(&encode(&axis_scale())[..RECORD_LEN - 1]),
+            Err(RecordError::TooShort)
+        );
+    }
+
+    #[test]
+    fn corrupt_payload() {
+        let mut record = encode(&ellipsoid());
+        record[20] ^= 0x01;
+        assert_eq!(decode(&record), Err(RecordError::BadChecksum));
+    }
+
+    #[test]
+    fn corrupt_checksum() {
+        let mut record = encode(&ellipsoid());
+        record[RECORD_LEN - 1] ^= 0x80;
+        assert_eq!(decode(&record), Err(RecordError::BadChecksum));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut record = encode(&axis_scale());
+        record[4] = 2;
+        assert_eq!(decode(&record), Err(RecordError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn unknown_kind() {
+        let mut record = encode(&axis_scale());
+        record[6] = 7;
+        let crc = crc32(&record[..CRC_OFFSET]);
+        record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(decode(&record), Err(RecordError::UnknownKind(7)));
+    }
+}
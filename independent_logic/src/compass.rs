@@ -0,0 +1,404 @@
+//! The heading pipeline, from raw sensor readings to a heading.
+
+use crate::auto_calibration::AutoCalibrator;
+use crate::calibration::Calibration;
+use crate::fixed_point;
+use crate::frames::BoardReading;
+use crate::mounting::Mounting;
+use crate::sensor::{MotionSensor, Sample};
+use crate::tilt_compensation::{
+    calc_attitude, calc_tilt_calibrated_measurement, heading_from_measurement, Attitude, Heading,
+    Orientation,
+};
+
+/// applies the calibration to a raw magnetometer reading.
+pub fn calibrated_mag(measurement: BoardReading, calibration: &Calibration) -> BoardReading {
+    measurement.to_enu().calibrated(calibration).to_board()
+}
+
+/// calculates the heading from an accelerometer and an already calibrated magnetometer reading.
+/// Both are the enclosures, as the board is mounted in it. Uses
+/// [`fixed_point::heading_from_samples`] with the `fixed-point` feature, and
+/// [`float_heading_from_samples`] otherwise.
+pub fn heading_from_samples(
+    accel: BoardReading,
+    mag: BoardReading,
+    tilt_correction_enabled: bool,
+    mounting: &Mounting,
+) -> (Heading, Attitude) {
+    if cfg!(feature = "fixed-point") {
+        fixed_point::heading_from_samples(accel, mag, tilt_correction_enabled, mounting)
+    } else {
+        float_heading_from_samples(accel, mag, tilt_correction_enabled, mounting)
+    }
+}
+
+/// the floating point pipeline. The attitude, and with tilt correction the heading, come from the
+/// full [`Orientation`], so they stay well-defined when the board is held vertically.
+pub fn float_heading_from_samples(
+    accel: BoardReading,
+    mag: BoardReading,
+    tilt_correction_enabled: bool,
+    mounting: &Mounting,
+) -> (Heading, Attitude) {
+    let mut ned_mag_data = mounting.apply(mag.to_ned().to_f32());
+    let ned_acel_data = mounting.apply(accel.to_ned().to_f32());
+
+    let orientation = Orientation::from_measurements(&ned_acel_data, &ned_mag_data);
+    // the level needs the attitude with tilt correction off too.
+    let attitude = match orientation {
+        Some(orientation) => orientation.attitude(),
+        None => calc_attitude(&ned_acel_data),
+    };
+
+    if tilt_correction_enabled {
+        if let Some(orientation) = orientation {
+            return (orientation.heading(), attitude);
+        }
+        // free fall, or a field straight along gravity. Fall back to the plain tilt correction.
+        ned_mag_data = calc_tilt_calibrated_measurement(ned_mag_data, &attitude);
+    }
+    //theta=0 at north, pi/-pi at south, pi/2 at east, and -pi/2 at west
+    (heading_from_measurement(ned_mag_data), attitude)
+}
+
+/// Waits for both sensors to have new data, then calculates the heading. The raw magnetometer
+/// reading is fed to the auto calibrator, and the calibration is updated if it was refined.
+pub fn read_heading<S: MotionSensor>(
+    sensor: &mut S,
+    calibration: &mut Calibration,
+    auto_calibrator: &mut AutoCalibrator,
+    tilt_correction_enabled: bool,
+    mounting: &Mounting,
+) -> Result<(Heading, Attitude), S::Error> {
+    while !sensor.data_ready()? {}
+    let mag_data = sensor.mag()?;
+    if let Some(refined) = auto_calibrator.update(mag_data.to_enu(), calibration) {
+        *calibration = refined;
+    }
+    let mag_data = calibrated_mag(mag_data, calibration);
+    let acel_data = sensor.accel()?;
+
+    Ok(heading_from_samples(
+        acel_data,
+        mag_data,
+        tilt_correction_enabled,
+        mounting,
+    ))
+}
+
+/// The latest reading of each sensor, so [`poll_heading`] can calculate a heading as soon as
+/// either sensor has new data instead of waiting for both.
+#[derive(Debug, Clone, Default)]
+pub struct LatestReadings {
+    accel: Option<BoardReading>,
+    /// raw, so it is always calibrated with the current calibration.
+    mag: Option<BoardReading>,
+}
+
+impl LatestReadings {
+    pub fn new() -> LatestReadings {
+        LatestReadings::default()
+    }
+
+    /// the raw readings, once both sensors have been read.
+    pub fn sample(&self) -> Option<Sample> {
+        Some(Sample {
+            accel: self.accel?,
+            mag: self.mag?,
+        })
+    }
+}
+
+/// Reads whichever sensors have new data, without waiting. Returns a new heading if there was
+/// new data and both sensors have been read at least once. New magnetometer readings are fed to
+/// the auto calibrator, like in [`read_heading`].
+pub fn poll_heading<S: MotionSensor>(
+    sensor: &mut S,
+    latest: &mut LatestReadings,
+    calibration: &mut Calibration,
+    auto_calibrator: &mut AutoCalibrator,
+    tilt_correction_enabled: bool,
+    mounting: &Mounting,
+) -> Result<Option<(Heading, Attitude)>, S::Error> {
+    let mut updated = false;
+    if sensor.accel_ready()? {
+        latest.accel = Some(sensor.accel()?);
+        updated = true;
+    }
+    if sensor.mag_ready()? {
+        let mag_data = sensor.mag()?;
+        if let Some(refined) = auto_calibrator.update(mag_data.to_enu(), calibration) {
+            *calibration = refined;
+        }
+        latest.mag = Some(mag_data);
+        updated = true;
+    }
+
+    match (updated, latest.accel, latest.mag) {
+        (true, Some(accel), Some(mag)) => Ok(Some(heading_from_samples(
+            accel,
+            calibrated_mag(mag, calibration),
+            tilt_correction_enabled,
+            mounting,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::Vector3;
+    use crate::frames::{FrameVector, Ned};
+    use crate::sensor::{ScriptExhausted, ScriptedSensor};
+    use std::f32::consts::PI;
+
+    const FLAT: BoardReading = FrameVector::new(0, 0, -1000);
+
+    /// the board-native magnetometer reading of a level board facing the heading.
+    fn flat_mag(heading: f32) -> BoardReading {
+        FrameVector::new(
+            (-20000.0 * heading.sin()) as i32,
+            (-20000.0 * heading.cos()) as i32,
+            -45000,
+        )
+    }
+
+    fn headings(samples: &[Sample], calibration: &mut Calibration) -> Vec<f32> {
+        let mut sensor = ScriptedSensor::new(samples);
+        let mut auto_calibrator = AutoCalibrator::new();
+        let mut out = Vec::new();
+        while sensor.remaining() > 0 {
+            let (heading, _) = read_heading(
+                &mut sensor,
+                calibration,
+                &mut auto_calibrator,
+                true,
+                &Mounting::default(),
+            )
+            .unwrap();
+            out.push(heading.0);
+        }
+        out
+    }
+
+    #[test]
+    fn level_headings() {
+        let expected = [0.0, PI / 2.0, -PI / 2.0, 3.0 * PI / 4.0];
+        let samples = expected.map(|h| Sample {
+            accel: FLAT,
+            mag: flat_mag(h),
+        });
+        let headings = headings(&samples, &mut Calibration::Uncalibrated);
+        for (heading, expected) in headings.iter().zip(expected) {
+            assert!(
+                (heading - expected).abs() < 1e-3,
+                "{} {}",
+                heading,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn applies_hard_iron_offset() {
+        let offset = BoardReading::new(2434, 5528, -40156);
+        let center = offset.to_enu();
+        let mut calibration = Calibration::AxisScale {
+            center: Vector3 {
+                x: center.x,
+                y: center.y,
+                z: center.z,
+            },
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 0,
+        };
+        let mag = flat_mag(PI / 2.0);
+        let samples = [Sample {
+            accel: FLAT,
+            mag: FrameVector::new(mag.x + offset.x, mag.y + offset.y, mag.z + offset.z),
+        }];
+        let heading = headings(&samples, &mut calibration)[0];
+        assert!((heading - PI / 2.0).abs() < 1e-3, "{}", heading);
+
+        // without the calibration, the offset pulls the heading off.
+        let heading = headings(&samples, &mut Calibration::Uncalibrated)[0];
+        assert!((heading - PI / 2.0).abs() > 0.1, "{}", heading);
+    }
+
+    #[test]
+    fn propagates_sensor_errors() {
+        let mut sensor = ScriptedSensor::new(&[]);
+        assert_eq!(
+            read_heading(
+                &mut sensor,
+                &mut Calibration::Uncalibrated,
+                &mut AutoCalibrator::new(),
+                true,
+                &Mounting::default(),
+            )
+            .map(|(heading, _)| heading.0),
+            Err(ScriptExhausted)
+        );
+    }
+
+    #[test]
+    fn mounted_upright() {
+        // upright with the USB connector up, and the display facing backwards.
+        let mounting = Mounting::Aligned(12);
+        let matrix = mounting.matrix();
+        // the readings of a board in an enclosure lying flat. The transposed matrix takes them
+        // from the enclosures axes back into the boards.
+        let mounted = |reading: BoardReading| {
+            let ned = reading.to_ned().to_f32();
+            let [x, y, z] = [0, 1, 2]
+                .map(|i| matrix[0][i] * ned.x + matrix[1][i] * ned.y + matrix[2][i] * ned.z);
+            let board = FrameVector::<Ned>::new(x, y, z).to_board();
+            FrameVector::new(
+                board.x.round() as i32,
+                board.y.round() as i32,
+                board.z.round() as i32,
+            )
+        };
+        assert_eq!(mounted(FLAT), FrameVector::new(0, 1000, 0));
+
+        let expected = [0.0, PI / 2.0, -PI / 2.0, 3.0 * PI / 4.0];
+        let samples = expected.map(|h| Sample {
+            accel: mounted(FLAT),
+            mag: mounted(flat_mag(h)),
+        });
+        let mut sensor = ScriptedSensor::new(&samples);
+        for expected in expected {
+            let (heading, attitude) = read_heading(
+                &mut sensor,
+                &mut Calibration::Uncalibrated,
+                &mut AutoCalibrator::new(),
+                true,
+                &mounting,
+            )
+            .unwrap();
+            assert!((heading.0 - expected).abs() < 1e-3, "{}", heading.0);
+            assert!(attitude.pitch.abs() < 1e-3 && attitude.roll.abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn attitude_from_orientation() {
+        let (pitch, roll) = (0.4_f32, -0.3_f32);
+        let accel = FrameVector::<Ned>::new(
+            -pitch.sin() * 1000.0,
+            roll.sin() * pitch.cos() * 1000.0,
+            roll.cos() * pitch.cos() * 1000.0,
+        );
+        let mag = FrameVector::<Ned>::new(20000.0, 3000.0, 45000.0);
+        let board = |v: FrameVector<Ned>| {
+            let v = v.to_board();
+            BoardReading::new(v.x.round() as i32, v.y.round() as i32, v.z.round() as i32)
+        };
+        let mounting = Mounting::default();
+        let (accel, mag) = (board(accel), board(mag));
+        let orientation =
+            Orientation::from_measurements(&accel.to_ned().to_f32(), &mag.to_ned().to_f32())
+                .unwrap();
+        for tilt_correction in [false, true] {
+            let (_, attitude) = float_heading_from_samples(accel, mag, tilt_correction, &mounting);
+            assert_eq!(attitude, orientation.attitude());
+            assert!((attitude.pitch - pitch).abs() < 1e-3 && (attitude.roll - roll).abs() < 1e-3);
+        }
+
+        // without a field there is no orientation, so it falls back to the accelerometer alone.
+        let no_field = BoardReading::default();
+        let (_, attitude) = float_heading_from_samples(accel, no_field, true, &mounting);
+        assert_eq!(attitude, calc_attitude(&accel.to_ned().to_f32()));
+    }
+
+    /// a sensor where only the accelerometer ever has new data.
+    struct AccelOnly(u32);
+
+    impl MotionSensor for AccelOnly {
+        type Error = ();
+
+        fn accel_ready(&mut self) -> Result<bool, ()> {
+            Ok(true)
+        }
+
+        fn mag_ready(&mut self) -> Result<bool, ()> {
+            Ok(self.0 == 0)
+        }
+
+        fn accel(&mut self) -> Result<BoardReading, ()> {
+            Ok(FLAT)
+        }
+
+        fn mag(&mut self) -> Result<BoardReading, ()> {
+            self.0 += 1;
+            Ok(flat_mag(PI / 2.0))
+        }
+    }
+
+    #[test]
+    fn poll_matches_read() {
+        let expected = [0.0, PI / 2.0, -PI / 2.0, 3.0 * PI / 4.0];
+        let samples = expected.map(|h| Sample {
+            accel: FLAT,
+            mag: flat_mag(h),
+        });
+        let mut sensor = ScriptedSensor::new(&samples);
+        let mut latest = LatestReadings::new();
+        let mut auto_calibrator = AutoCalibrator::new();
+        for expected in expected {
+            let (heading, _) = poll_heading(
+                &mut sensor,
+                &mut latest,
+                &mut Calibration::Uncalibrated,
+                &mut auto_calibrator,
+                true,
+                &Mounting::default(),
+            )
+            .unwrap()
+            .unwrap();
+            assert!((heading.0 - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn poll_reuses_latest_reading() {
+        let mut sensor = AccelOnly(0);
+        let mut latest = LatestReadings::new();
+        let mut auto_calibrator = AutoCalibrator::new();
+        for _ in 0..3 {
+            let (heading, _) = poll_heading(
+                &mut sensor,
+                &mut latest,
+                &mut Calibration::Uncalibrated,
+                &mut auto_calibrator,
+                true,
+                &Mounting::default(),
+            )
+            .unwrap()
+            .unwrap();
+            assert!((heading.0 - PI / 2.0).abs() < 1e-3);
+        }
+        assert_eq!(sensor.0, 1);
+    }
+
+    #[test]
+    fn poll_waits_for_both_sensors() {
+        // the magnetometer has not been read yet.
+        let mut sensor = AccelOnly(1);
+        let result = poll_heading(
+            &mut sensor,
+            &mut LatestReadings::new(),
+            &mut Calibration::Uncalibrated,
+            &mut AutoCalibrator::new(),
+            true,
+            &Mounting::default(),
+        );
+        assert!(matches!(result, Ok(None)));
+    }
+}
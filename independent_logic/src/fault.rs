@@ -0,0 +1,456 @@
+//! Keeps the sensors going when the i2c bus acts up, and shows an error code when they can't be
+//! brought back.
+//!
+//! A failed read is retried a few times, waiting longer before each retry, as bus errors usually
+//! come from a glitch. If it still fails the sensor is set up again from scratch. If that fails
+//! too, the sensor stays stopped until [`Supervisor::restart`] is called again, and the display
+//! shows [`ERROR_GLYPH`] followed by the errors [`Fault::code`].
+
+use core::fmt::{Debug, Write};
+
+use crate::font::{ScrollingText, Text};
+use crate::frames::BoardReading;
+use crate::menu::Frame;
+use crate::sensor::{MotionSensor, Restart};
+
+/// An error the firmware can try to recover from, and show if that doesn't work.
+pub trait Fault: Debug {
+    /// true if trying again might work, like after a glitch on the bus.
+    fn is_transient(&self) -> bool;
+
+    /// the number shown on the display. Each kind of error has its own.
+    fn code(&self) -> u8;
+}
+
+/// How often something is tried, and how long to wait before the first retry. The wait doubles
+/// with every retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    attempts: u32,
+    first_delay: u32,
+}
+
+impl Backoff {
+    /// `attempts` includes the first one, so 1 never retries. `first_delay` is in microseconds.
+    pub const fn new(attempts: u32, first_delay: u32) -> Backoff {
+        Backoff {
+            attempts,
+            first_delay,
+        }
+    }
+
+    /// the delays before each retry, in microseconds.
+    pub fn delays(&self) -> impl Iterator<Item = u32> {
+        let first_delay = self.first_delay;
+        (0..self.attempts.saturating_sub(1))
+            .map(move |retry| first_delay.saturating_mul(2u32.saturating_pow(retry)))
+    }
+
+    /// runs `op` until it succeeds, fails with an error that isn't transient, or runs out of
+    /// attempts. `wait` is called with each delay.
+    pub fn retry<T, E: Fault>(
+        &self,
+        wait: &mut impl FnMut(u32),
+        mut op: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut delays = self.delays();
+        loop {
+            match op() {
+                Err(error) if error.is_transient() => match delays.next() {
+                    Some(delay) => wait(delay),
+                    None => return Err(error),
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Why a [`Supervisor`] couldn't read its sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupervisorError<E> {
+    /// the sensor kept failing, so it was restarted.
+    Sensor(E),
+    /// the sensor couldn't be restarted, [`Supervisor::error`] says why.
+    Stopped,
+}
+
+enum Slot<S: Restart> {
+    Running(S),
+    /// the bus, and why the sensor couldn't be started on it.
+    Stopped(S::Bus, S::Error),
+}
+
+/// Wraps a sensor, retrying failed reads with a [`Backoff`] and restarting the sensor when that
+/// doesn't help. It is a [`MotionSensor`] itself, so it can be used in place of the sensor.
+/// `wait` waits the given number of microseconds.
+pub struct Supervisor<S: Restart, W> {
+    /// only empty while the sensor is being restarted.
+    slot: Option<Slot<S>>,
+    backoff: Backoff,
+    wait: W,
+}
+
+impl<S, W> Supervisor<S, W>
+where
+    S: Restart,
+    S::Error: Fault,
+    W: FnMut(u32),
+{
+    /// starts the sensor, retrying transient errors. Check [`error`](Self::error) to see whether
+    /// that worked.
+    pub fn new(bus: S::Bus, backoff: Backoff, wait: W) -> Supervisor<S, W> {
+        let mut supervisor = Supervisor {
+            slot: None,
+            backoff,
+            wait,
+        };
+        supervisor.start(bus);
+        supervisor
+    }
+
+    /// why the sensor couldn't be started, if it is stopped.
+    pub fn error(&self) -> Option<&S::Error> {
+        match &self.slot {
+            Some(Slot::Stopped(_, error)) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// sets the sensor up again from scratch, whether it is running or not.
+    pub fn restart(&mut self) {
+        let bus = match self.slot.take() {
+            Some(Slot::Running(sensor)) => sensor.stop(),
+            Some(Slot::Stopped(bus, _)) => bus,
+            None => return,
+        };
+        self.start(bus);
+    }
+
+    fn start(&mut self, mut bus: S::Bus) {
+        let mut delays = self.backoff.delays();
+        let slot = loop {
+            match S::start(bus) {
+                Ok(sensor) => break Slot::Running(sensor),
+                Err((error, returned)) => match delays.next() {
+                    Some(delay) if error.is_transient() => {
+                        (self.wait)(delay);
+                        bus = returned;
+                    }
+                    _ => break Slot::Stopped(returned, error),
+                },
+            }
+        };
+        self.slot = Some(slot);
+    }
+
+    /// reads the sensor, retrying transient errors. If it still fails the sensor is restarted.
+    fn read<T>(
+        &mut self,
+        mut op: impl FnMut(&mut S) -> Result<T, S::Error>,
+    ) -> Result<T, SupervisorError<S::Error>> {
+        let sensor = match self.slot.as_mut() {
+            Some(Slot::Running(sensor)) => sensor,
+            _ => return Err(SupervisorError::Stopped),
+        };
+        match self.backoff.retry(&mut self.wait, || op(sensor)) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                self.restart();
+                Err(SupervisorError::Sensor(error))
+            }
+        }
+    }
+}
+
+impl<S, W> MotionSensor for Supervisor<S, W>
+where
+    S: Restart,
+    S::Error: Fault,
+    W: FnMut(u32),
+{
+    type Error = SupervisorError<S::Error>;
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        self.read(S::accel_ready)
+    }
+
+    fn mag_ready(&mut self) -> Result<bool, Self::Error> {
+        self.read(S::mag_ready)
+    }
+
+    fn accel(&mut self) -> Result<BoardReading, Self::Error> {
+        self.read(S::accel)
+    }
+
+    fn mag(&mut self) -> Result<BoardReading, Self::Error> {
+        self.read(S::mag)
+    }
+}
+
+/// An exclamation mark with dim corners, which looks like neither a heading nor a menu icon.
+pub const ERROR_GLYPH: Frame = [
+    [2, 0, 9, 0, 2],
+    [0, 0, 9, 0, 0],
+    [0, 0, 9, 0, 0],
+    [0, 0, 0, 0, 0],
+    [2, 0, 9, 0, 2],
+];
+
+/// how many frames [`ERROR_GLYPH`] is shown before the code scrolls past.
+const GLYPH_FRAMES: usize = 8;
+
+/// the text shown for an error code, like `E3`.
+pub fn fault_text(code: u8) -> Text {
+    let mut text = Text::new();
+    let _ = write!(text, "E{}", code);
+    text
+}
+
+/// Shows an error code over and over: [`ERROR_GLYPH`] for a while, then the [`fault_text`]
+/// scrolling past. Never runs out of frames.
+#[derive(Debug, Clone)]
+pub struct FaultScreen {
+    code: u8,
+    glyph_frames: usize,
+    text: ScrollingText<5, 5>,
+}
+
+impl FaultScreen {
+    pub fn new(code: u8) -> FaultScreen {
+        FaultScreen {
+            code,
+            glyph_frames: 0,
+            text: ScrollingText::new(fault_text(code)),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+}
+
+impl Iterator for FaultScreen {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.glyph_frames < GLYPH_FRAMES {
+            self.glyph_frames += 1;
+            return Some(ERROR_GLYPH);
+        }
+        match self.text.next() {
+            Some(matrix) => Some(matrix.into()),
+            None => {
+                *self = FaultScreen::new(self.code);
+                self.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum TestError {
+        Glitch,
+        Missing,
+    }
+
+    impl Fault for TestError {
+        fn is_transient(&self) -> bool {
+            *self == TestError::Glitch
+        }
+
+        fn code(&self) -> u8 {
+            match self {
+                TestError::Glitch => 1,
+                TestError::Missing => 2,
+            }
+        }
+    }
+
+    /// what the fake sensor is connected to, with its failures scripted.
+    #[derive(Debug, Default)]
+    struct Bus {
+        /// how many transfers fail before the next one works.
+        glitches: u32,
+        missing: bool,
+        starts: u32,
+    }
+
+    impl Bus {
+        fn transfer(&mut self) -> Result<(), TestError> {
+            if self.missing {
+                Err(TestError::Missing)
+            } else if self.glitches > 0 {
+                self.glitches -= 1;
+                Err(TestError::Glitch)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    struct FakeSensor(Bus);
+
+    impl MotionSensor for FakeSensor {
+        type Error = TestError;
+
+        fn accel_ready(&mut self) -> Result<bool, TestError> {
+            self.0.transfer().map(|()| true)
+        }
+
+        fn mag_ready(&mut self) -> Result<bool, TestError> {
+            self.0.transfer().map(|()| true)
+        }
+
+        fn accel(&mut self) -> Result<BoardReading, TestError> {
+            self.0.transfer().map(|()| BoardReading::new(0, 0, 1))
+        }
+
+        fn mag(&mut self) -> Result<BoardReading, TestError> {
+            self.0.transfer().map(|()| BoardReading::new(1, 0, 0))
+        }
+    }
+
+    impl Restart for FakeSensor {
+        type Bus = Bus;
+
+        fn start(mut bus: Bus) -> Result<FakeSensor, (TestError, Bus)> {
+            bus.starts += 1;
+            match bus.transfer() {
+                Ok(()) => Ok(FakeSensor(bus)),
+                Err(error) => Err((error, bus)),
+            }
+        }
+
+        fn stop(self) -> Bus {
+            self.0
+        }
+    }
+
+    const BACKOFF: Backoff = Backoff::new(4, 1000);
+
+    fn new_bus(glitches: u32, missing: bool) -> Bus {
+        Bus {
+            glitches,
+            missing,
+            starts: 0,
+        }
+    }
+
+    /// the bus of a supervised sensor, to script its failures.
+    fn bus<W>(supervisor: &mut Supervisor<FakeSensor, W>) -> &mut Bus {
+        match supervisor.slot.as_mut().unwrap() {
+            Slot::Running(sensor) => &mut sensor.0,
+            Slot::Stopped(bus, _) => bus,
+        }
+    }
+
+    #[test]
+    fn delays_double() {
+        assert_eq!(BACKOFF.delays().collect::<Vec<_>>(), [1000, 2000, 4000]);
+        assert_eq!(Backoff::new(1, 1000).delays().count(), 0);
+        assert_eq!(Backoff::new(0, 1000).delays().count(), 0);
+        assert_eq!(Backoff::new(40, 1).delays().last(), Some(u32::MAX));
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let mut waited = Vec::new();
+        let mut bus = new_bus(2, false);
+        let result = BACKOFF.retry(&mut |delay| waited.push(delay), || bus.transfer());
+        assert_eq!(result, Ok(()));
+        assert_eq!(waited, [1000, 2000]);
+
+        // gives up once it runs out of attempts.
+        let mut waited = Vec::new();
+        let mut bus = new_bus(10, false);
+        let result = BACKOFF.retry(&mut |delay| waited.push(delay), || bus.transfer());
+        assert_eq!(result, Err(TestError::Glitch));
+        assert_eq!(waited, [1000, 2000, 4000]);
+        assert_eq!(bus.glitches, 6);
+
+        // a missing chip won't come back by waiting.
+        let mut waited = Vec::new();
+        let mut bus = new_bus(0, true);
+        let result = BACKOFF.retry(&mut |delay| waited.push(delay), || bus.transfer());
+        assert_eq!(result, Err(TestError::Missing));
+        assert!(waited.is_empty());
+    }
+
+    #[test]
+    fn starts_through_glitches() {
+        let mut waited = 0;
+        {
+            let wait = |delay| waited += delay;
+            let mut supervisor = Supervisor::<FakeSensor, _>::new(new_bus(3, false), BACKOFF, wait);
+            assert_eq!(supervisor.error(), None);
+            assert_eq!(bus(&mut supervisor).starts, 4);
+            assert_eq!(supervisor.accel(), Ok(BoardReading::new(0, 0, 1)));
+        }
+        assert_eq!(waited, 7000);
+
+        let mut supervisor = Supervisor::<FakeSensor, _>::new(new_bus(0, true), BACKOFF, |_| {});
+        assert_eq!(supervisor.error(), Some(&TestError::Missing));
+        assert_eq!(bus(&mut supervisor).starts, 1);
+        assert_eq!(supervisor.mag(), Err(SupervisorError::Stopped));
+    }
+
+    #[test]
+    fn restarts_a_failing_sensor() {
+        let mut supervisor = Supervisor::<FakeSensor, _>::new(new_bus(0, false), BACKOFF, |_| {});
+        // a few glitches are retried without anyone noticing.
+        bus(&mut supervisor).glitches = 3;
+        assert_eq!(supervisor.mag_ready(), Ok(true));
+        assert_eq!(bus(&mut supervisor).starts, 1);
+
+        // one more and the sensor is started again, which gets past the last one.
+        bus(&mut supervisor).glitches = 5;
+        assert_eq!(
+            supervisor.mag(),
+            Err(SupervisorError::Sensor(TestError::Glitch))
+        );
+        assert_eq!(supervisor.error(), None);
+        assert_eq!(bus(&mut supervisor).starts, 3);
+        assert_eq!(supervisor.mag(), Ok(BoardReading::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn stays_stopped_until_restarted() {
+        let mut supervisor = Supervisor::<FakeSensor, _>::new(new_bus(0, false), BACKOFF, |_| {});
+        bus(&mut supervisor).missing = true;
+        assert_eq!(
+            supervisor.accel_ready(),
+            Err(SupervisorError::Sensor(TestError::Missing))
+        );
+        assert_eq!(supervisor.error(), Some(&TestError::Missing));
+        assert_eq!(supervisor.accel(), Err(SupervisorError::Stopped));
+        assert_eq!(supervisor.data_ready(), Err(SupervisorError::Stopped));
+
+        bus(&mut supervisor).missing = false;
+        supervisor.restart();
+        assert_eq!(supervisor.error(), None);
+        assert_eq!(supervisor.data_ready(), Ok(true));
+    }
+
+    #[test]
+    fn shows_the_code() {
+        let mut screen = FaultScreen::new(3);
+        for _ in 0..GLYPH_FRAMES {
+            assert_eq!(screen.next(), Some(ERROR_GLYPH));
+        }
+        let text: Vec<Frame> = ScrollingText::<5, 5>::new(Text::from("E3"))
+            .map(|matrix| matrix.into())
+            .collect();
+        for frame in &text {
+            assert_eq!(screen.next().as_ref(), Some(frame));
+        }
+        // and starts over.
+        assert_eq!(screen.next(), Some(ERROR_GLYPH));
+        assert_eq!(screen.code(), 3);
+        assert_eq!(fault_text(12).as_str(), "E12");
+    }
+}
@@ -0,0 +1,727 @@
+//! The heading pipeline in fixed point, for the micro:bit v1s Cortex-M0, which has no FPU. Built
+//! with the `fixed-point` feature, [`heading_from_samples`](crate::compass::heading_from_samples),
+//! [`needle_tip`](crate::heading_drawing::needle_tip) and
+//! [`antialiased_needle_tip`](crate::heading_drawing::antialiased_needle_tip) use it instead of
+//! their libm versions.
+//!
+//! Angles are radians in Q16, sines and cosines are Q15, and atan2, sin and cos are calculated
+//! with [CORDIC](https://en.wikipedia.org/wiki/CORDIC), which only needs shifts and additions.
+//! Headings and attitudes come out within [`TOLERANCE`] of the floating point pipeline.
+//!
+//! The plain [`calc_tilt_calibrated_measurement`] gets unreliable when the board is held more than
+//! about 60° from flat. So past [`STEEP_PITCH`](crate::tilt_compensation::STEEP_PITCH),
+//! [`heading_from_samples`] uses [`orientation_heading`], which blends in the upright heading like
+//! [`Orientation::heading`](crate::tilt_compensation::Orientation::heading) does.
+
+use crate::calibration::Matrix3;
+use crate::frames::{BoardReading, FrameVector, Ned};
+use crate::line_drawing::{draw_line, FPoint, FourQuadrantMatrix, Line, Point};
+use crate::mounting::Mounting;
+use crate::tilt_compensation::{Attitude, Heading};
+
+/// a reading in the axes tilt compensation works in, see [`Ned`].
+pub type NedReading = FrameVector<Ned, i32>;
+
+/// how far, in radians, headings and attitudes can be from the floating point pipelines.
+pub const TOLERANCE: f32 = 0.002;
+
+/// An angle in radians, as Q16: 65536 is one radian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Angle(pub i32);
+
+const ONE: i32 = 1 << 16;
+
+/// [`STEEP_PITCH`](crate::tilt_compensation::STEEP_PITCH) in Q16.
+const STEEP_PITCH: Angle = Angle((crate::tilt_compensation::STEEP_PITCH * ONE as f32) as i32);
+/// [`VERTICAL_PITCH`](crate::tilt_compensation::VERTICAL_PITCH) in Q16.
+const VERTICAL_PITCH: Angle = Angle((crate::tilt_compensation::VERTICAL_PITCH * ONE as f32) as i32);
+
+impl Angle {
+    pub const PI: Angle = Angle(205887);
+    pub const FRAC_PI_2: Angle = Angle(102944);
+
+    pub fn from_radians(radians: f32) -> Angle {
+        Angle((radians * ONE as f32) as i32)
+    }
+
+    pub fn to_radians(self) -> f32 {
+        self.0 as f32 / ONE as f32
+    }
+
+    /// the same angle, between -pi and pi.
+    pub fn wrapped(self) -> Angle {
+        let turn = 2 * Angle::PI.0;
+        let mut angle = self.0 % turn;
+        if angle > Angle::PI.0 {
+            angle -= turn;
+        } else if angle <= -Angle::PI.0 {
+            angle += turn;
+        }
+        Angle(angle)
+    }
+}
+
+/// Q15 sines and cosines, 32768 being 1.
+const Q15: u32 = 15;
+
+/// each CORDIC iteration adds about one bit of precision.
+const ITERATIONS: usize = 16;
+
+/// `atan(2^-i)` in Q16 radians.
+const ATAN_TABLE: [i32; ITERATIONS] = [
+    51472, 30386, 16055, 8150, 4091, 2047, 1024, 512, 256, 128, 64, 32, 16, 8, 4, 2,
+];
+
+/// `1 / prod(sqrt(1 + 2^-2i))`, the inverse of how much CORDIC lengthens a vector, in Q30.
+const INV_GAIN_Q30: i32 = 652032874;
+
+/// how many bits vectors are shifted left by to leave room for the CORDIC gain without losing
+/// precision. Negative values are shifted right.
+fn headroom(values: &[i32]) -> i32 {
+    let largest = values.iter().map(|v| v.unsigned_abs()).max().unwrap_or(0);
+    // the largest component ends up just below 2^29, so the gain and the rotation by up to 45°
+    // stay below 2^31.
+    largest.leading_zeros() as i32 - 3
+}
+
+fn shift(value: i32, bits: i32) -> i32 {
+    if bits >= 0 {
+        value << bits
+    } else {
+        value >> -bits
+    }
+}
+
+/// rotates `(x, y)` onto the positive x axis. Returns the angle it was rotated by, and its length
+/// multiplied by the CORDIC gain.
+fn vectoring(x: i32, y: i32) -> (Angle, i32) {
+    // the rotations only reach +-99°, so vectors on the left are turned around first.
+    let (mut x, mut y, mut angle) = if x < 0 {
+        let half_turn = if y < 0 { -Angle::PI.0 } else { Angle::PI.0 };
+        (-x, -y, half_turn)
+    } else {
+        (x, y, 0)
+    };
+    for (i, atan) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if y > 0 {
+            x += dx;
+            y -= dy;
+            angle += atan;
+        } else {
+            x -= dx;
+            y += dy;
+            angle -= atan;
+        }
+    }
+    (Angle(angle), x)
+}
+
+/// like `atan2f`: the angle of `(x, y)` from the positive x axis, between -pi and pi.
+pub fn atan2(y: i32, x: i32) -> Angle {
+    if x == 0 && y == 0 {
+        return Angle(0);
+    }
+    let bits = headroom(&[x, y]);
+    vectoring(shift(x, bits), shift(y, bits)).0
+}
+
+/// the sine and cosine of an angle, in Q15.
+pub fn sin_cos(angle: Angle) -> (i32, i32) {
+    // the rotations only reach +-99°, so angles past +-90° are turned around first.
+    let mut angle = angle.wrapped().0;
+    let sign = if angle > Angle::FRAC_PI_2.0 {
+        angle -= Angle::PI.0;
+        -1
+    } else if angle < -Angle::FRAC_PI_2.0 {
+        angle += Angle::PI.0;
+        -1
+    } else {
+        1
+    };
+    // Q30, starting out shortened so the gain brings it back to 1.
+    let (mut x, mut y) = (INV_GAIN_Q30, 0);
+    for (i, atan) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if angle > 0 {
+            x -= dx;
+            y += dy;
+            angle -= atan;
+        } else {
+            x += dx;
+            y -= dy;
+            angle += atan;
+        }
+    }
+    let round = |value: i32| sign * ((value + (1 << (29 - Q15))) >> (30 - Q15));
+    (round(y), round(x))
+}
+
+/// like `sinf` and `cosf`, for code that otherwise works in floats.
+pub fn sin_cos_f32(radians: f32) -> (f32, f32) {
+    let (sin, cos) = sin_cos(Angle::from_radians(radians));
+    let scale = (1 << Q15) as f32;
+    (sin as f32 / scale, cos as f32 / scale)
+}
+
+/// like `atan2f`, for code that otherwise works in floats. `y` and `x` are turned into Q15, so
+/// they have to be below 65536 in size.
+pub fn atan2_f32(y: f32, x: f32) -> f32 {
+    let scale = (1 << Q15) as f32;
+    atan2((y * scale) as i32, (x * scale) as i32).to_radians()
+}
+
+/// `value * q15`, rounded.
+fn mul_q15(value: i32, q15: i32) -> i64 {
+    (value as i64 * q15 as i64 + (1 << (Q15 - 1))) >> Q15
+}
+
+/// `value` in Q15, rounded. It is taken apart from its bits, so no float instructions are needed.
+/// Values past the range of Q15 in an i32 saturate.
+fn q15_from_f32(value: f32) -> i32 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    if exponent == 0 {
+        // zero, or too small to show up in Q15.
+        return 0;
+    }
+    // value = mantissa * 2^(exponent - 127 - 23), with the mantissas implicit leading one.
+    let mantissa = ((bits & 0x7F_FFFF) | 0x80_0000) as i64;
+    let shift = exponent - 127 - 23 + Q15 as i32;
+    let magnitude = if shift >= 0 {
+        mantissa << shift.min(31)
+    } else if shift > -40 {
+        (mantissa + (1 << (-shift - 1))) >> -shift
+    } else {
+        0
+    };
+    let magnitude = magnitude.min(i32::MAX as i64) as i32;
+    if bits >> 31 == 1 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// `matrix` times `measurement`, with the matrix taken as Q15, for custom mountings.
+pub fn rotate(matrix: &Matrix3, measurement: NedReading) -> NedReading {
+    let [x, y, z] = matrix.map(|row| {
+        let sum = mul_q15(measurement.x, q15_from_f32(row[0]))
+            + mul_q15(measurement.y, q15_from_f32(row[1]))
+            + mul_q15(measurement.z, q15_from_f32(row[2]));
+        sum.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    });
+    NedReading::new(x, y, z)
+}
+
+fn cross(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// the vector scaled to a length of 1 in Q15, or `None` if it is zero.
+fn normalized(vector: [i64; 3]) -> Option<[i64; 3]> {
+    let largest = vector.iter().map(|v| v.unsigned_abs()).max().unwrap_or(0);
+    if largest == 0 {
+        return None;
+    }
+    // the largest component ends up just below 2^29, like `headroom` leaves it.
+    let bits = largest.leading_zeros() as i32 - 35;
+    let [x, y, z] = vector.map(|v| if bits >= 0 { v << bits } else { v >> -bits } as i32);
+    let (_, length) = vectoring(z, y);
+    let length = ((length as i64 * INV_GAIN_Q30 as i64) >> 30) as i32;
+    let (_, length) = vectoring(length, x);
+    let length = (length as i64 * INV_GAIN_Q30 as i64) >> 30;
+    Some([x, y, z].map(|v| ((v as i64) << Q15) / length))
+}
+
+/// [`Attitude`] in Q16.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FixedAttitude {
+    pub pitch: Angle,
+    pub roll: Angle,
+}
+
+impl From<FixedAttitude> for Attitude {
+    fn from(attitude: FixedAttitude) -> Attitude {
+        Attitude {
+            pitch: attitude.pitch.to_radians(),
+            roll: attitude.roll.to_radians(),
+        }
+    }
+}
+
+/// like [`tilt_compensation::calc_attitude`](crate::tilt_compensation::calc_attitude).
+pub fn calc_attitude(measurement: &NedReading) -> FixedAttitude {
+    let (x, y, z) = (measurement.x, measurement.y, measurement.z);
+    if y == 0 && z == 0 {
+        return FixedAttitude {
+            pitch: atan2(x.saturating_neg(), 0),
+            roll: Angle(0),
+        };
+    }
+    // all three axes are scaled alike, so the length of `(y, z)` keeps its fractional bits for
+    // the pitch.
+    let bits = headroom(&[x, y, z]);
+    let (roll, length) = vectoring(shift(z, bits), shift(y, bits));
+    let length = ((length as i64 * INV_GAIN_Q30 as i64) >> 30) as i32;
+    FixedAttitude {
+        pitch: vectoring(length, shift(x, bits).saturating_neg()).0,
+        roll,
+    }
+}
+
+/// like [`tilt_compensation::calc_tilt_calibrated_measurement`](
+/// crate::tilt_compensation::calc_tilt_calibrated_measurement).
+pub fn calc_tilt_calibrated_measurement(
+    mag_measurement: NedReading,
+    attitude: &FixedAttitude,
+) -> NedReading {
+    let (sin_pitch, cos_pitch) = sin_cos(attitude.pitch);
+    let (sin_roll, cos_roll) = sin_cos(attitude.roll);
+
+    let corrected_mag_y =
+        mul_q15(mag_measurement.z, sin_roll) - mul_q15(mag_measurement.y, cos_roll);
+
+    // the length of the field in the plane of y and z, turned into the vertical plane.
+    let vertical = mul_q15(mag_measurement.y, sin_roll) + mul_q15(mag_measurement.z, cos_roll);
+    let corrected_mag_x = mul_q15(mag_measurement.x, cos_pitch)
+        + ((vertical * sin_pitch as i64 + (1 << (Q15 - 1))) >> Q15);
+
+    let clamp = |value: i64| value.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+    NedReading::new(clamp(corrected_mag_x), clamp(corrected_mag_y), 0)
+}
+
+/// like [`tilt_compensation::heading_from_measurement`](
+/// crate::tilt_compensation::heading_from_measurement).
+pub fn heading_from_measurement(measurement: NedReading) -> Angle {
+    atan2(measurement.y.saturating_neg(), measurement.x)
+}
+
+/// like [`Orientation::heading`](crate::tilt_compensation::Orientation::heading), for a board
+/// with the given pitch. The axes of the earth are found in the boards axes with TRIAD, like
+/// [`Orientation::from_measurements`](crate::tilt_compensation::Orientation::from_measurements)
+/// does, but are used as they are instead of being turned into a quaternion. Returns `None` if
+/// either reading is zero, or if they point the same way.
+pub fn orientation_heading(accel: &NedReading, mag: &NedReading, pitch: Angle) -> Option<Angle> {
+    let down = normalized([accel.x, accel.y, accel.z].map(i64::from))?;
+    let east = normalized(cross(down, [mag.x, mag.y, mag.z].map(i64::from)))?;
+    let north = cross(east, down).map(|v| v >> Q15);
+    // the boards forward and down axes, in north, east and down.
+    let forward = [north[0], east[0], down[0]];
+    let board_down = [north[2], east[2], down[2]];
+    let yaw_heading = atan2(-forward[1] as i32, forward[0] as i32);
+
+    let span = (VERTICAL_PITCH.0 - STEEP_PITCH.0) as i64;
+    let blend = (((pitch.0.abs() - STEEP_PITCH.0) as i64) << Q15) / span;
+    let blend = blend.clamp(0, 1 << Q15);
+    // the shortest rotation that takes the boards down axis to the earths down axis can't be
+    // found when the board is upside down.
+    let one_plus_down = (1 << Q15) + board_down[2];
+    if blend == 0 || one_plus_down <= 0 {
+        return Some(yaw_heading);
+    }
+
+    // forward, turned by that rotation, in Q30. See Rodrigues' rotation formula.
+    let [bx, by, bz] = board_down;
+    let [fx, fy, fz] = forward;
+    let along = (by * fx - bx * fy) / one_plus_down;
+    let tipped_x = bz * fx - bx * fz + by * along;
+    let tipped_y = bz * fy - by * fz - bx * along;
+    let tipped_heading = atan2(-(tipped_y >> Q15) as i32, (tipped_x >> Q15) as i32);
+
+    // smoothstep, so the heading doesn't kink at either end.
+    let blend = (((blend * blend) >> Q15) * ((3 << Q15) - 2 * blend)) >> Q15;
+    let difference = Angle(tipped_heading.0 - yaw_heading.0).wrapped().0 as i64;
+    Some(Angle(yaw_heading.0 + ((difference * blend) >> Q15) as i32).wrapped())
+}
+
+/// like [`compass::heading_from_samples`](crate::compass::heading_from_samples), using the plain
+/// tilt correction up to [`STEEP_PITCH`](crate::tilt_compensation::STEEP_PITCH) and
+/// [`orientation_heading`] past it.
+pub fn heading_from_samples(
+    accel: BoardReading,
+    mag: BoardReading,
+    tilt_correction_enabled: bool,
+    mounting: &Mounting,
+) -> (Heading, Attitude) {
+    let mut ned_mag_data = mounting.apply_fixed(mag.to_ned());
+    let ned_accel_data = mounting.apply_fixed(accel.to_ned());
+
+    let attitude = calc_attitude(&ned_accel_data);
+    if tilt_correction_enabled {
+        // below it, the orientations heading is the same as the plain tilt correction.
+        if attitude.pitch.0.abs() > STEEP_PITCH.0 {
+            if let Some(heading) =
+                orientation_heading(&ned_accel_data, &ned_mag_data, attitude.pitch)
+            {
+                return (Heading(heading.to_radians()), attitude.into());
+            }
+        }
+        ned_mag_data = calc_tilt_calibrated_measurement(ned_mag_data, &attitude);
+    }
+    (
+        Heading(heading_from_measurement(ned_mag_data).to_radians()),
+        attitude.into(),
+    )
+}
+
+/// like [`heading_drawing::needle_tip`](crate::heading_drawing::needle_tip).
+pub fn needle_tip(heading: Angle, square_size: usize) -> Point {
+    let (sin, cos) = sin_cos(heading);
+    let scale = |q15: i32| {
+        let scaled = square_size as i64 * q15 as i64;
+        // rounds halfway cases away from zero, like roundf.
+        let half = 1 << (Q15 - 1);
+        let rounded = if scaled < 0 {
+            -((-scaled + half) >> Q15)
+        } else {
+            (scaled + half) >> Q15
+        };
+        rounded as isize
+    };
+    Point {
+        x: scale(sin),
+        y: scale(cos),
+    }
+}
+
+/// like [`heading_drawing::antialiased_needle_tip`](
+/// crate::heading_drawing::antialiased_needle_tip).
+pub fn antialiased_needle_tip(heading: Angle, length: f32) -> FPoint {
+    let (sin, cos) = sin_cos(heading);
+    let scale = length / (1 << Q15) as f32;
+    FPoint {
+        x: sin as f32 * scale,
+        y: cos as f32 * scale,
+    }
+}
+
+/// like [`heading_drawing::draw_heading`](crate::heading_drawing::draw_heading).
+pub fn draw_heading<const X: usize, const Y: usize>(
+    heading: Angle,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let line = Line(Point { x: 0, y: 0 }, needle_tip(heading, X.min(Y)));
+    draw_line::<X, Y>(&line, matrix);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compass::float_heading_from_samples;
+    use crate::heading_drawing;
+    use crate::line_drawing::UPoint;
+    use crate::tilt_compensation::{self, NedMeasurement, Orientation};
+    use core::f32::consts::PI;
+    use libm::{atan2f, cosf, fabsf, hypotf, roundf, sinf};
+
+    /// the difference between two angles, going the short way around.
+    fn angle_error(a: f32, b: f32) -> f32 {
+        let difference = fabsf(a - b) % (2.0 * PI);
+        difference.min(2.0 * PI - difference)
+    }
+
+    /// a spread of vectors of all sizes the sensors read.
+    fn vectors() -> Vec<(i32, i32, i32)> {
+        let mut vectors = Vec::new();
+        for scale in [1, 7, 100, 1000, 55_000, 1_000_000] {
+            for i in 0..37 {
+                let a = i as f32 * PI / 18.0 - PI;
+                let b = i as f32 * 0.7;
+                vectors.push((
+                    (scale as f32 * cosf(a) * cosf(b)) as i32,
+                    (scale as f32 * sinf(a) * cosf(b)) as i32,
+                    (scale as f32 * sinf(b)) as i32,
+                ));
+            }
+        }
+        vectors
+    }
+
+    #[test]
+    fn atan2_matches_float() {
+        for (x, y, _) in vectors() {
+            let fixed = atan2(y, x).to_radians();
+            let float = atan2f(y as f32, x as f32);
+            assert!(
+                angle_error(fixed, float) < 0.0002,
+                "{} {} {} {}",
+                x,
+                y,
+                fixed,
+                float
+            );
+        }
+        assert_eq!(atan2(0, 0), Angle(0));
+        // the table is rounded, so exact angles can be off by a few units.
+        assert!((atan2(0, -5).0 - Angle::PI.0).abs() <= 2);
+        assert!((atan2(i32::MAX, i32::MIN).to_radians() - 0.75 * PI).abs() < 0.0002);
+        assert!(fabsf(atan2(i32::MIN, i32::MIN).to_radians() + 0.75 * PI) < 0.0002);
+    }
+
+    #[test]
+    fn sin_cos_match_float() {
+        for i in -400..=400 {
+            let radians = i as f32 * 0.01;
+            let (sin, cos) = sin_cos(Angle::from_radians(radians));
+            let scale = (1 << Q15) as f32;
+            assert!(
+                fabsf(sin as f32 / scale - sinf(radians)) < 0.0001,
+                "{}",
+                radians
+            );
+            assert!(
+                fabsf(cos as f32 / scale - cosf(radians)) < 0.0001,
+                "{}",
+                radians
+            );
+        }
+        let close = |(sin, cos): (i32, i32), expected: (i32, i32)| {
+            (sin - expected.0).abs() <= 1 && (cos - expected.1).abs() <= 1
+        };
+        assert!(close(sin_cos(Angle(0)), (0, 1 << Q15)));
+        assert!(close(sin_cos(Angle::FRAC_PI_2), (1 << Q15, 0)));
+        assert!(close(sin_cos(Angle::PI), (0, -(1 << Q15))));
+    }
+
+    #[test]
+    fn wraps_angles() {
+        assert_eq!(Angle(3 * Angle::PI.0).wrapped(), Angle::PI);
+        assert_eq!(Angle(-Angle::PI.0).wrapped(), Angle::PI);
+        assert_eq!(
+            Angle(-Angle::PI.0 - Angle::FRAC_PI_2.0).wrapped(),
+            Angle(Angle::PI.0 - Angle::FRAC_PI_2.0)
+        );
+        assert!(fabsf(Angle::PI.to_radians() - PI) < 0.00001);
+    }
+
+    #[test]
+    fn attitude_matches_float() {
+        for (x, y, z) in vectors() {
+            let fixed = calc_attitude(&NedReading::new(x, y, z));
+            let float = tilt_compensation::calc_attitude(&NedMeasurement::new(
+                x as f32, y as f32, z as f32,
+            ));
+            let (pitch, roll) = (fixed.pitch.to_radians(), fixed.roll.to_radians());
+            assert!(
+                angle_error(pitch, float.pitch) < TOLERANCE,
+                "{:?}",
+                (x, y, z)
+            );
+            assert!(angle_error(roll, float.roll) < TOLERANCE, "{:?}", (x, y, z));
+        }
+    }
+
+    #[test]
+    fn tilt_correction_matches_float() {
+        for (x, y, z) in vectors() {
+            for (pitch, roll) in [(0.0, 0.0), (0.3, -0.2), (-1.0, 2.5), (1.2, -3.0)] {
+                let attitude = FixedAttitude {
+                    pitch: Angle::from_radians(pitch),
+                    roll: Angle::from_radians(roll),
+                };
+                let fixed = calc_tilt_calibrated_measurement(NedReading::new(x, y, z), &attitude);
+                let float = tilt_compensation::calc_tilt_calibrated_measurement(
+                    NedMeasurement::new(x as f32, y as f32, z as f32),
+                    &attitude.into(),
+                );
+                let length = hypotf(x as f32, hypotf(y as f32, z as f32));
+                assert!(fabsf(fixed.x as f32 - float.x) <= 2.0 + length * 0.0002);
+                assert!(fabsf(fixed.y as f32 - float.y) <= 2.0 + length * 0.0002);
+            }
+        }
+    }
+
+    #[test]
+    fn heading_matches_float() {
+        for (x, y, z) in vectors() {
+            let fixed = heading_from_measurement(NedReading::new(x, y, z));
+            let float = tilt_compensation::heading_from_measurement(NedMeasurement::new(
+                x as f32, y as f32, z as f32,
+            ));
+            assert!(angle_error(fixed.to_radians(), float.0) < TOLERANCE);
+        }
+    }
+
+    /// the readings of a board with the given heading, pitch and roll, rotated like
+    /// [`tilt_compensation`]s tests do.
+    fn readings(heading: f32, pitch: f32, roll: f32) -> (BoardReading, BoardReading) {
+        let yaw = -heading;
+        let rotate = |v: [f32; 3]| {
+            let v = [
+                cosf(yaw) * v[0] + sinf(yaw) * v[1],
+                -sinf(yaw) * v[0] + cosf(yaw) * v[1],
+                v[2],
+            ];
+            let v = [
+                cosf(pitch) * v[0] - sinf(pitch) * v[2],
+                v[1],
+                sinf(pitch) * v[0] + cosf(pitch) * v[2],
+            ];
+            let v = [
+                v[0],
+                cosf(roll) * v[1] + sinf(roll) * v[2],
+                -sinf(roll) * v[1] + cosf(roll) * v[2],
+            ];
+            NedReading::new(v[0] as i32, v[1] as i32, v[2] as i32).to_board()
+        };
+        (rotate([0.0, 0.0, 1000.0]), rotate([20000.0, 0.0, 45000.0]))
+    }
+
+    #[test]
+    fn pipeline_matches_float() {
+        // headings all around, at tilts where the plain tilt correction and the orientation
+        // agree.
+        for i in 0..72 {
+            let heading = i as f32 * PI / 36.0 - PI;
+            for (pitch, roll) in [(0.0, 0.0), (0.4, 0.0), (-0.3, 0.5), (0.2, -0.6)] {
+                let (accel, mag) = readings(heading, pitch, roll);
+                for tilt_correction in [false, true] {
+                    let mounting = Mounting::default();
+                    let (fixed, fixed_attitude) =
+                        heading_from_samples(accel, mag, tilt_correction, &mounting);
+                    let (float, float_attitude) =
+                        float_heading_from_samples(accel, mag, tilt_correction, &mounting);
+                    assert!(
+                        angle_error(fixed.0, float.0) < TOLERANCE,
+                        "{:?}",
+                        (heading, pitch, roll, tilt_correction, fixed, float)
+                    );
+                    assert!(angle_error(fixed_attitude.pitch, float_attitude.pitch) < TOLERANCE);
+                    assert!(angle_error(fixed_attitude.roll, float_attitude.roll) < TOLERANCE);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn orientation_heading_matches_float() {
+        // every pitch, so the blend is covered from both ends.
+        for i in -36..=36 {
+            let pitch = i as f32 * PI / 72.0;
+            for j in 0..12 {
+                let heading = j as f32 * PI / 6.0 - PI;
+                for roll in [0.0, 0.5, -1.0, 2.5] {
+                    let (accel, mag) = readings(heading, pitch, roll);
+                    let (accel, mag) = (accel.to_ned(), mag.to_ned());
+                    let fixed =
+                        orientation_heading(&accel, &mag, calc_attitude(&accel).pitch).unwrap();
+                    let float = Orientation::from_measurements(&accel.to_f32(), &mag.to_f32())
+                        .unwrap()
+                        .heading();
+                    assert!(
+                        angle_error(fixed.to_radians(), float.0) < TOLERANCE,
+                        "{:?}",
+                        (heading, pitch, roll, fixed.to_radians(), float)
+                    );
+                }
+            }
+        }
+        let zero = NedReading::new(0, 0, 0);
+        assert_eq!(orientation_heading(&zero, &zero, Angle(0)), None);
+    }
+
+    #[test]
+    fn steep_headings() {
+        // about half a degree, the readings are rounded to whole milli-g and nanotesla.
+        const STEEP_TOLERANCE: f32 = 0.01;
+        for degrees in [70.0, 75.0, 80.0, 85.0, 89.0, 90.0] {
+            for pitch in [degrees, -degrees].map(f32::to_radians) {
+                for i in 0..12 {
+                    let heading = i as f32 * PI / 6.0 - PI;
+                    // without roll, the board faces the heading the whole way up.
+                    let (accel, mag) = readings(heading, pitch, 0.0);
+                    let (fixed, _) = heading_from_samples(accel, mag, true, &Mounting::default());
+                    assert!(
+                        angle_error(fixed.0, heading) < STEEP_TOLERANCE,
+                        "{:?}",
+                        (heading, pitch, fixed)
+                    );
+                    if degrees < 80.0 {
+                        continue;
+                    }
+                    // past the vertical pitch it is where the top of the display points once
+                    // the board is tipped flat, and upright that is where its back faces.
+                    for roll in [0.5, -1.0, 3.0] {
+                        let (accel, mag) = readings(heading, pitch, roll);
+                        let (fixed, _) =
+                            heading_from_samples(accel, mag, true, &Mounting::default());
+                        let expected =
+                            heading + atan2f(sinf(roll) * sinf(pitch), cosf(roll) + cosf(pitch));
+                        assert!(
+                            angle_error(fixed.0, expected) < STEEP_TOLERANCE,
+                            "{:?}",
+                            (heading, pitch, roll, fixed, expected)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mounted_pipeline_matches_float() {
+        let (accel, mag) = readings(1.0, 0.2, -0.3);
+        let (s, c) = (sinf(0.7), cosf(0.7));
+        let custom = [
+            Mounting::custom([[c, 0.0, -s], [0.0, 1.0, 0.0], [s, 0.0, c]]).unwrap(),
+            Mounting::custom([[c, s, 0.0], [0.0, 0.0, 1.0], [s, -c, 0.0]]).unwrap(),
+        ];
+        for mounting in (0..24).map(Mounting::Aligned).chain(custom) {
+            for tilt_correction in [false, true] {
+                let (fixed, _) = heading_from_samples(accel, mag, tilt_correction, &mounting);
+                let (float, _) = float_heading_from_samples(accel, mag, tilt_correction, &mounting);
+                assert!(
+                    angle_error(fixed.0, float.0) < TOLERANCE,
+                    "{:?}",
+                    (mounting, tilt_correction, fixed, float)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn q15_from_bits() {
+        for value in [
+            0.0, -0.0, 1.0, -1.0, 0.5, 0.6, -0.8, 0.001, 1e-9, 3.5, -7000.0,
+        ] {
+            let expected = roundf(value * (1 << Q15) as f32) as i32;
+            assert_eq!(q15_from_f32(value), expected, "{}", value);
+        }
+        assert_eq!(q15_from_f32(1e12), i32::MAX);
+    }
+
+    #[test]
+    fn draws_like_float() {
+        for i in 0..720 {
+            let heading = i as f32 * PI / 360.0 - PI;
+            // where the tip lies halfway between two LEDs either one is right.
+            let tie = |v: f32| fabsf(fabsf(5.0 * v % 1.0) - 0.5) < 0.001;
+            if tie(sinf(heading)) || tie(cosf(heading)) {
+                continue;
+            }
+            let mut fixed: FourQuadrantMatrix<5, 5, u8> =
+                FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+            let mut float = fixed;
+            draw_heading::<5, 5>(Angle::from_radians(heading), &mut fixed);
+            heading_drawing::draw_heading::<5, 5>(heading, &mut float);
+            let (fixed, float): ([[u8; 5]; 5], [[u8; 5]; 5]) = (fixed.into(), float.into());
+            assert_eq!(fixed, float, "{}", heading);
+        }
+    }
+
+    #[test]
+    fn antialiased_tip_matches_float() {
+        for i in 0..720 {
+            let heading = i as f32 * PI / 360.0 - PI;
+            let tip = antialiased_needle_tip(Angle::from_radians(heading), 5.0);
+            // a hundredth of a pixel changes the brightness of a pixel by less than a level.
+            assert!(fabsf(tip.x - 5.0 * sinf(heading)) < 0.01, "{}", heading);
+            assert!(fabsf(tip.y - 5.0 * cosf(heading)) < 0.01, "{}", heading);
+        }
+    }
+}
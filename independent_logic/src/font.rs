@@ -0,0 +1,272 @@
+//! A small font for scrolling text across the display.
+
+use core::fmt;
+
+use crate::line_drawing::{FourQuadrantMatrix, UPoint, MAX_BRIGHTNESS};
+
+/// A character 5 pixels high and up to 5 wide. Each row is a bit mask, with the leftmost pixel
+/// in the highest of the `width` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    pub width: usize,
+    pub rows: [u8; 5],
+}
+
+impl Glyph {
+    pub const HEIGHT: usize = 5;
+
+    /// whether the pixel in a column, counted from the left, and a row, counted from the top, is
+    /// lit.
+    pub fn is_lit(&self, column: usize, row: usize) -> bool {
+        column < self.width && self.rows[row] & (1 << (self.width - 1 - column)) != 0
+    }
+}
+
+const fn glyph(width: usize, rows: [u8; 5]) -> Glyph {
+    Glyph { width, rows }
+}
+
+/// the glyph for a character, or `None` if the font doesn't have one. Letters are upper case
+/// only.
+pub fn glyph_for(character: char) -> Option<Glyph> {
+    Some(match character {
+        '0' => glyph(3, [0b010, 0b101, 0b101, 0b101, 0b010]),
+        '1' => glyph(3, [0b010, 0b110, 0b010, 0b010, 0b111]),
+        '2' => glyph(3, [0b110, 0b001, 0b010, 0b100, 0b111]),
+        '3' => glyph(3, [0b110, 0b001, 0b010, 0b001, 0b110]),
+        '4' => glyph(3, [0b101, 0b101, 0b111, 0b001, 0b001]),
+        '5' => glyph(3, [0b111, 0b100, 0b110, 0b001, 0b110]),
+        '6' => glyph(3, [0b011, 0b100, 0b110, 0b101, 0b010]),
+        '7' => glyph(3, [0b111, 0b001, 0b001, 0b010, 0b010]),
+        '8' => glyph(3, [0b010, 0b101, 0b010, 0b101, 0b010]),
+        '9' => glyph(3, [0b010, 0b101, 0b011, 0b001, 0b110]),
+        'A' => glyph(3, [0b010, 0b101, 0b111, 0b101, 0b101]),
+        'B' => glyph(3, [0b110, 0b101, 0b110, 0b101, 0b110]),
+        'C' => glyph(3, [0b011, 0b100, 0b100, 0b100, 0b011]),
+        'D' => glyph(3, [0b110, 0b101, 0b101, 0b101, 0b110]),
+        'E' => glyph(3, [0b111, 0b100, 0b110, 0b100, 0b111]),
+        'F' => glyph(3, [0b111, 0b100, 0b110, 0b100, 0b100]),
+        'G' => glyph(4, [0b0111, 0b1000, 0b1011, 0b1001, 0b0111]),
+        'H' => glyph(3, [0b101, 0b101, 0b111, 0b101, 0b101]),
+        'I' => glyph(3, [0b111, 0b010, 0b010, 0b010, 0b111]),
+        'J' => glyph(3, [0b001, 0b001, 0b001, 0b101, 0b010]),
+        'K' => glyph(3, [0b101, 0b101, 0b110, 0b101, 0b101]),
+        'L' => glyph(3, [0b100, 0b100, 0b100, 0b100, 0b111]),
+        'M' => glyph(5, [0b10001, 0b11011, 0b10101, 0b10001, 0b10001]),
+        'N' => glyph(4, [0b1001, 0b1101, 0b1011, 0b1001, 0b1001]),
+        'O' => glyph(4, [0b0110, 0b1001, 0b1001, 0b1001, 0b0110]),
+        'P' => glyph(3, [0b110, 0b101, 0b110, 0b100, 0b100]),
+        'Q' => glyph(4, [0b0110, 0b1001, 0b1001, 0b1011, 0b0111]),
+        'R' => glyph(3, [0b110, 0b101, 0b110, 0b101, 0b101]),
+        'S' => glyph(3, [0b011, 0b100, 0b010, 0b001, 0b110]),
+        'T' => glyph(3, [0b111, 0b010, 0b010, 0b010, 0b010]),
+        'U' => glyph(3, [0b101, 0b101, 0b101, 0b101, 0b111]),
+        'V' => glyph(3, [0b101, 0b101, 0b101, 0b101, 0b010]),
+        'W' => glyph(5, [0b10001, 0b10001, 0b10101, 0b11011, 0b10001]),
+        'X' => glyph(3, [0b101, 0b101, 0b010, 0b101, 0b101]),
+        'Y' => glyph(3, [0b101, 0b101, 0b010, 0b010, 0b010]),
+        'Z' => glyph(3, [0b111, 0b001, 0b010, 0b100, 0b111]),
+        '-' => glyph(3, [0b000, 0b000, 0b111, 0b000, 0b000]),
+        '.' => glyph(1, [0, 0, 0, 0, 1]),
+        ' ' => glyph(2, [0; 5]),
+        _ => return None,
+    })
+}
+
+/// the most characters a [`Text`] holds.
+pub const TEXT_LEN: usize = 24;
+
+/// A short string that doesn't need an allocator, filled in with `write!`. A write that would
+/// take it past [`TEXT_LEN`] bytes fails and leaves it as it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Text {
+    bytes: [u8; TEXT_LEN],
+    len: usize,
+}
+
+impl Text {
+    pub fn new() -> Text {
+        Text {
+            bytes: [0; TEXT_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole strs are ever copied in, so this can't fail.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Text::new()
+    }
+}
+
+impl fmt::Write for Text {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.len + s.len() > TEXT_LEN {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+impl From<&str> for Text {
+    fn from(value: &str) -> Self {
+        let mut text = Text::new();
+        let _ = fmt::Write::write_str(&mut text, value);
+        text
+    }
+}
+
+/// the glyphs of a text, with characters the font doesn't have shown as spaces.
+fn glyphs(text: &str) -> impl Iterator<Item = Glyph> + '_ {
+    text.chars()
+        .map(|character| glyph_for(character).unwrap_or(glyph_for(' ').unwrap()))
+}
+
+/// the columns the text takes up, with one blank column between characters.
+fn text_width(text: &str) -> usize {
+    glyphs(text).map(|glyph| glyph.width + 1).sum::<usize>()
+}
+
+/// Scrolls a text from right to left across an X by Y matrix, one column per frame. The text is
+/// centered vertically. The first frame shows the first column of the text at the right edge,
+/// the last one is blank.
+#[derive(Debug, Clone)]
+pub struct ScrollingText<const X: usize, const Y: usize> {
+    text: Text,
+    /// the column of the text at the left edge of the matrix. Starts out negative.
+    offset: isize,
+    width: isize,
+}
+
+impl<const X: usize, const Y: usize> ScrollingText<X, Y> {
+    pub fn new(text: Text) -> ScrollingText<X, Y> {
+        ScrollingText {
+            width: text_width(text.as_str()) as isize,
+            text,
+            offset: 1 - X as isize,
+        }
+    }
+
+    /// the text being scrolled.
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    /// draws the text with its column `offset` at the left edge of the matrix.
+    fn draw(&self, matrix: &mut FourQuadrantMatrix<X, Y, u8>) {
+        let top = (Y.saturating_sub(Glyph::HEIGHT) / 2) as isize;
+        let mut left = -self.offset;
+        for glyph in glyphs(self.text.as_str()) {
+            for column in 0..glyph.width {
+                let x = left + column as isize;
+                if x < 0 || x >= X as isize {
+                    continue;
+                }
+                for row in 0..Glyph::HEIGHT {
+                    let y = top + row as isize;
+                    if glyph.is_lit(column, row) && y < Y as isize {
+                        let point = UPoint {
+                            x: x as usize,
+                            y: y as usize,
+                        }
+                        .to_point(&matrix.zero_coord());
+                        matrix[point] = MAX_BRIGHTNESS;
+                    }
+                }
+            }
+            left += glyph.width as isize + 1;
+        }
+    }
+}
+
+impl<const X: usize, const Y: usize> Iterator for ScrollingText<X, Y> {
+    type Item = FourQuadrantMatrix<X, Y, u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset > self.width {
+            return None;
+        }
+        let mut matrix = FourQuadrantMatrix::new(UPoint { x: X / 2, y: Y / 2 });
+        self.draw(&mut matrix);
+        self.offset += 1;
+        Some(matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    fn frames(text: &str) -> Vec<[[u8; 5]; 5]> {
+        ScrollingText::<5, 5>::new(Text::from(text))
+            .map(|matrix| matrix.into())
+            .collect()
+    }
+
+    #[test]
+    fn every_character_has_a_glyph() {
+        for character in "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ-. ".chars() {
+            let glyph = glyph_for(character).unwrap();
+            for row in glyph.rows {
+                assert!(row < 1 << glyph.width, "{:?}", character);
+            }
+        }
+        assert_eq!(glyph_for('~'), None);
+        assert_eq!(glyph_for('a'), None);
+    }
+
+    #[test]
+    fn text_formats() {
+        let mut text = Text::new();
+        write!(text, "{} {}", -12, 3).unwrap();
+        assert_eq!(text.as_str(), "-12 3");
+        assert!(write!(text, "{}", [0; 10].map(|_| "12").concat()).is_err());
+        assert_eq!(text.as_str(), "-12 3");
+    }
+
+    #[test]
+    fn scrolls_in_from_the_right() {
+        let frames = frames("1");
+        // the glyph plus its gap, and 4 frames while it enters.
+        assert_eq!(frames.len(), 4 + 4 + 1);
+        assert_eq!(
+            frames[0],
+            [
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 9],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 9],
+            ]
+        );
+        assert_eq!(
+            frames[4],
+            [
+                [0, 9, 0, 0, 0],
+                [9, 9, 0, 0, 0],
+                [0, 9, 0, 0, 0],
+                [0, 9, 0, 0, 0],
+                [9, 9, 9, 0, 0],
+            ]
+        );
+        assert_eq!(*frames.last().unwrap(), [[0; 5]; 5]);
+    }
+
+    #[test]
+    fn each_frame_moves_one_column() {
+        let frames = frames("-42 NW");
+        for pair in frames.windows(2) {
+            for (before, after) in pair[0].iter().zip(pair[1].iter()) {
+                assert_eq!(before[1..], after[..4]);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! The coordinate frames readings pass through on the way to a heading. Each vector carries its
+//! frame in its type, so handing a reading to something that expects another frame doesn't
+//! compile, and the only way between frames is the conversions here.
+//!
+//! ```
+//! use independent_logic::auto_calibration::AutoCalibrator;
+//! use independent_logic::calibration::{checked_calibration, Calibration};
+//! use independent_logic::frames::{BoardReading, FrameVector};
+//! use independent_logic::tilt_compensation::calc_attitude;
+//!
+//! let reading = BoardReading::new(0, 0, -1000);
+//! calc_attitude(&reading.to_ned().to_f32());
+//! let _ = checked_calibration(&[reading.to_enu(); 9]);
+//! AutoCalibrator::new().update(reading.to_enu(), &Calibration::Uncalibrated);
+//! ```
+//!
+//! ```compile_fail
+//! use independent_logic::frames::{BoardFrame, FrameVector};
+//! use independent_logic::tilt_compensation::calc_attitude;
+//!
+//! // board readings have to be converted with `to_ned` first.
+//! calc_attitude(&FrameVector::<BoardFrame>::new(0.0, 0.0, -1000.0));
+//! ```
+//!
+//! ```compile_fail
+//! use independent_logic::calibration::checked_calibration;
+//! use independent_logic::frames::BoardReading;
+//!
+//! // calibrations are calculated from enu readings.
+//! let _ = checked_calibration(&[BoardReading::new(0, 0, -1000); 9]);
+//! ```
+//!
+//! ```compile_fail
+//! use independent_logic::calibration::{calibrated_measurement, Calibration};
+//! use independent_logic::frames::BoardReading;
+//!
+//! calibrated_measurement(BoardReading::new(0, 0, -1000), &Calibration::Uncalibrated);
+//! ```
+//!
+//! ```compile_fail
+//! use independent_logic::auto_calibration::AutoCalibrator;
+//! use independent_logic::calibration::Calibration;
+//! use independent_logic::frames::BoardReading;
+//!
+//! // not even through the ned frame, which shares x and y with enu.
+//! let reading = BoardReading::new(0, 0, -1000).to_ned();
+//! AutoCalibrator::new().update(reading, &Calibration::Uncalibrated);
+//! ```
+//!
+//! ```compile_fail
+//! use independent_logic::calibration::Vector3;
+//! use independent_logic::frames::EnuReading;
+//!
+//! // untyped vectors don't pick up a frame on their own.
+//! let reading: EnuReading = Vector3 { x: 0, y: 0, z: -1000 }.into();
+//! ```
+
+use core::marker::PhantomData;
+use core::ops::Neg;
+
+use crate::calibration::{calibrated_measurement, Calibration};
+
+/// The boards native axes, as the [`MotionSensor`](crate::sensor::MotionSensor) reads them once
+/// each chips [`AxisMap`](crate::sensor::AxisMap) has been applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BoardFrame;
+
+/// The axes magnetometer calibrations are calculated and stored in. It shares x and y with
+/// [`Ned`], with z pointing up instead of down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Enu;
+
+/// The axes tilt compensation works in, with x towards the top of the display and z down when
+/// the board lies flat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ned;
+
+/// A vector in the frame `F`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameVector<F, T = f32> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    frame: PhantomData<F>,
+}
+
+/// a raw reading in the boards native axes.
+pub type BoardReading = FrameVector<BoardFrame, i32>;
+/// a raw magnetometer reading in the axes of the calibration.
+pub type EnuReading = FrameVector<Enu, i32>;
+
+impl<F, T> FrameVector<F, T> {
+    pub const fn new(x: T, y: T, z: T) -> FrameVector<F, T> {
+        FrameVector {
+            x,
+            y,
+            z,
+            frame: PhantomData,
+        }
+    }
+}
+
+impl<F> FrameVector<F, i32> {
+    /// also converts to f32
+    pub fn to_f32(self) -> FrameVector<F, f32> {
+        FrameVector::new(self.x as f32, self.y as f32, self.z as f32)
+    }
+}
+
+impl<T: Copy + Neg<Output = T>> FrameVector<BoardFrame, T> {
+    pub fn to_enu(self) -> FrameVector<Enu, T> {
+        FrameVector::new(-self.y, -self.x, self.z)
+    }
+
+    pub fn to_ned(self) -> FrameVector<Ned, T> {
+        FrameVector::new(-self.y, -self.x, -self.z)
+    }
+}
+
+impl<T: Copy + Neg<Output = T>> FrameVector<Enu, T> {
+    pub fn to_board(self) -> FrameVector<BoardFrame, T> {
+        FrameVector::new(-self.y, -self.x, self.z)
+    }
+
+    pub fn to_ned(self) -> FrameVector<Ned, T> {
+        FrameVector::new(self.x, self.y, -self.z)
+    }
+}
+
+impl<T: Copy + Neg<Output = T>> FrameVector<Ned, T> {
+    pub fn to_board(self) -> FrameVector<BoardFrame, T> {
+        FrameVector::new(-self.y, -self.x, -self.z)
+    }
+
+    pub fn to_enu(self) -> FrameVector<Enu, T> {
+        FrameVector::new(self.x, self.y, -self.z)
+    }
+}
+
+impl EnuReading {
+    /// removes the hard iron offset and applies the soft iron correction.
+    pub fn calibrated(self, calibration: &Calibration) -> EnuReading {
+        calibrated_measurement(self, calibration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::Vector3;
+
+    const READING: BoardReading = FrameVector::new(1, -2, 3);
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(READING.to_enu().to_board(), READING);
+        assert_eq!(READING.to_ned().to_board(), READING);
+        assert_eq!(READING.to_enu().to_ned().to_enu(), READING.to_enu());
+    }
+
+    #[test]
+    fn conversions_agree() {
+        // going through the calibration axes is the same as going to ned directly.
+        assert_eq!(READING.to_enu().to_ned(), READING.to_ned());
+        assert_eq!(READING.to_ned().to_enu().to_board(), READING);
+        assert_eq!(READING.to_ned(), FrameVector::new(2, -1, -3));
+        assert_eq!(READING.to_enu(), FrameVector::new(2, -1, 3));
+    }
+
+    #[test]
+    fn flat_board() {
+        // a flat board at rest reads gravity along -z, which is down in ned.
+        let accel = BoardReading::new(0, 0, -1000);
+        assert_eq!(accel.to_ned().to_f32(), FrameVector::new(0.0, 0.0, 1000.0));
+    }
+
+    #[test]
+    fn calibrates_in_enu() {
+        let calibration = Calibration::AxisScale {
+            center: Vector3 {
+                x: 10,
+                y: 20,
+                z: 30,
+            },
+            scale: Vector3 {
+                x: 1024,
+                y: 1024,
+                z: 1024,
+            },
+            radius: 0,
+        };
+        let reading = BoardReading::new(-20, -10, 30);
+        // the center is in enu, so it matches the reading only after converting it.
+        assert_eq!(
+            reading.to_enu().calibrated(&calibration),
+            FrameVector::new(0, 0, 0)
+        );
+    }
+}
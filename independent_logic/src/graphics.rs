@@ -0,0 +1,184 @@
+//! [embedded-graphics](https://docs.rs/embedded-graphics) support, with the `embedded-graphics`
+//! feature: the LED matrix is a [`DrawTarget`], so text, shapes and images can be drawn on it, and
+//! [`draw_heading_on`] draws the compass needle onto any other display.
+//!
+//! The origin stays at the matrix's zero point, but like everywhere in embedded-graphics y points
+//! down, so `(1, -2)` is the LED at [`Point`] `(1, 2)`. Colors are [`Gray8`], scaled to the 10
+//! brightness levels.
+
+use core::convert::Infallible;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{self, Dimensions, Size};
+use embedded_graphics::pixelcolor::{Gray8, GrayColor};
+use embedded_graphics::primitives::{Line, Primitive, PrimitiveStyle, Rectangle};
+use embedded_graphics::{Drawable, Pixel};
+
+use crate::heading_drawing::needle_tip;
+use crate::line_drawing::{FourQuadrantMatrix, Point, MAX_BRIGHTNESS};
+
+/// the brightness a color is shown at, from 0 to [`MAX_BRIGHTNESS`].
+pub fn brightness(color: Gray8) -> u8 {
+    ((color.luma() as u16 * MAX_BRIGHTNESS as u16 + 127) / 255) as u8
+}
+
+fn to_matrix(point: geometry::Point) -> Point {
+    Point {
+        x: point.x as isize,
+        y: -(point.y as isize),
+    }
+}
+
+impl<const X: usize, const Y: usize> Dimensions for FourQuadrantMatrix<{ X }, { Y }, u8> {
+    fn bounding_box(&self) -> Rectangle {
+        let top_left = geometry::Point::new(self.min_point().x as i32, -self.max_point().y as i32);
+        Rectangle::new(top_left, Size::new(X as u32, Y as u32))
+    }
+}
+
+impl<const X: usize, const Y: usize> DrawTarget for FourQuadrantMatrix<{ X }, { Y }, u8> {
+    type Color = Gray8;
+    type Error = Infallible;
+
+    /// sets the LEDs the pixels fall on. Pixels off the matrix are skipped.
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let point = to_matrix(point);
+            if self.is_in_bounds(&point) {
+                self[point] = brightness(color);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// draws the needle of [`draw_heading`](crate::heading_drawing::draw_heading) onto any display,
+/// `length` pixels out from `center`. A heading of 0 points up the display.
+pub fn draw_heading_on<D: DrawTarget>(
+    heading: f32,
+    center: geometry::Point,
+    length: usize,
+    color: D::Color,
+    target: &mut D,
+) -> Result<(), D::Error> {
+    let tip = needle_tip(heading, length);
+    let tip = center + geometry::Point::new(tip.x as i32, -tip.y as i32);
+    Line::new(center, tip)
+        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::{ascii::FONT_4X6, MonoTextStyle};
+    use embedded_graphics::pixelcolor::BinaryColor;
+    use embedded_graphics::primitives::Circle;
+    use embedded_graphics::text::{Baseline, Text};
+
+    use crate::heading_drawing::draw_heading;
+    use crate::line_drawing::UPoint;
+
+    fn matrix() -> FourQuadrantMatrix<5, 5, u8> {
+        FourQuadrantMatrix::new(UPoint { x: 2, y: 2 })
+    }
+
+    #[test]
+    fn bounding_box_is_centered() {
+        assert_eq!(
+            matrix().bounding_box(),
+            Rectangle::new(geometry::Point::new(-2, -2), Size::new(5, 5))
+        );
+        let corner: FourQuadrantMatrix<5, 5, u8> = FourQuadrantMatrix::new(UPoint { x: 0, y: 4 });
+        assert_eq!(
+            corner.bounding_box(),
+            Rectangle::new(geometry::Point::new(0, -4), Size::new(5, 5))
+        );
+    }
+
+    #[test]
+    fn draws_pixels_with_y_down() {
+        let mut matrix = matrix();
+        let pixels = [
+            Pixel(geometry::Point::new(1, -2), Gray8::WHITE),
+            Pixel(geometry::Point::new(-2, 1), Gray8::new(128)),
+            Pixel(geometry::Point::new(3, 0), Gray8::WHITE),
+        ];
+        matrix.draw_iter(pixels).unwrap();
+        assert_eq!(matrix[Point { x: 1, y: 2 }], MAX_BRIGHTNESS);
+        assert_eq!(matrix[Point { x: -2, y: -1 }], 5);
+        let lit = <[[u8; 5]; 5]>::from(matrix)
+            .iter()
+            .flatten()
+            .filter(|&&value| value > 0)
+            .count();
+        assert_eq!(lit, 2);
+    }
+
+    #[test]
+    fn draws_primitives() {
+        let mut matrix = matrix();
+        matrix.clear(Gray8::WHITE).unwrap();
+        assert_eq!(<[[u8; 5]; 5]>::from(matrix), [[MAX_BRIGHTNESS; 5]; 5]);
+
+        let mut matrix = self::matrix();
+        Circle::with_center(geometry::Point::zero(), 5)
+            .into_styled(PrimitiveStyle::with_stroke(Gray8::WHITE, 1))
+            .draw(&mut matrix)
+            .unwrap();
+        assert_eq!(matrix[Point { x: 0, y: 0 }], 0);
+        assert_eq!(matrix[Point { x: 0, y: 2 }], MAX_BRIGHTNESS);
+        assert_eq!(matrix.rotated(1), matrix);
+
+        let mut matrix = self::matrix();
+        let style = MonoTextStyle::new(&FONT_4X6, Gray8::WHITE);
+        Text::with_baseline("+", geometry::Point::new(-2, -2), style, Baseline::Top)
+            .draw(&mut matrix)
+            .unwrap();
+        assert_eq!(matrix[Point { x: -1, y: 0 }], MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn draws_heading_like_the_leds() {
+        for i in 0..8 {
+            let heading = i as f32 * PI / 4.0 - PI;
+            let mut leds = matrix();
+            draw_heading::<5, 5>(heading, &mut leds);
+            let mut drawn = matrix();
+            draw_heading_on(
+                heading,
+                geometry::Point::zero(),
+                5,
+                Gray8::new(255 / 9),
+                &mut drawn,
+            )
+            .unwrap();
+            assert_eq!(drawn, leds, "{}", heading);
+        }
+    }
+
+    #[test]
+    fn draws_heading_on_other_displays() {
+        let mut display: MockDisplay<BinaryColor> = MockDisplay::new();
+        let center = geometry::Point::new(8, 8);
+        draw_heading_on(0.0, center, 7, BinaryColor::On, &mut display).unwrap();
+        assert_eq!(display.get_pixel(center), Some(BinaryColor::On));
+        assert_eq!(
+            display.get_pixel(geometry::Point::new(8, 1)),
+            Some(BinaryColor::On)
+        );
+        assert_eq!(display.get_pixel(geometry::Point::new(8, 9)), None);
+
+        let mut display: MockDisplay<BinaryColor> = MockDisplay::new();
+        draw_heading_on(PI / 2.0, center, 7, BinaryColor::On, &mut display).unwrap();
+        assert_eq!(
+            display.get_pixel(geometry::Point::new(15, 8)),
+            Some(BinaryColor::On)
+        );
+    }
+}
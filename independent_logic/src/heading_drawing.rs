@@ -1,15 +1,28 @@
+use core::f32::consts::PI;
+use core::fmt::Write;
 use libm::{cosf, roundf, sinf};
 
-use crate::line_drawing::{draw_line, FourQuadrantMatrix, Line, Point};
+use crate::fixed_point::{self, Angle};
+use crate::font::Text;
+use crate::line_drawing::{
+    draw_line, draw_line_antialiased, FLine, FPoint, FourQuadrantMatrix, Line, Point,
+    MAX_BRIGHTNESS,
+};
+
+/// the point the needle is drawn to, before it is clipped to the display. Uses
+/// [`fixed_point::needle_tip`] with the `fixed-point` feature.
+pub fn needle_tip(heading: f32, square_size: usize) -> Point {
+    if cfg!(feature = "fixed-point") {
+        return fixed_point::needle_tip(Angle::from_radians(heading), square_size);
+    }
+    Point {
+        x: roundf((square_size as f32) * sinf(heading)) as isize,
+        y: roundf((square_size as f32) * cosf(heading)) as isize,
+    }
+}
 
 fn heading_to_line(heading: f32, square_size: usize) -> Line {
-    Line(
-        Point { x: 0, y: 0 },
-        Point {
-            x: roundf((square_size as f32) * sinf(heading)) as isize,
-            y: roundf((square_size as f32) * cosf(heading)) as isize,
-        },
-    )
+    Line(Point { x: 0, y: 0 }, needle_tip(heading, square_size))
 }
 
 pub fn draw_heading<const X: usize, const Y: usize>(
@@ -18,3 +31,506 @@ pub fn draw_heading<const X: usize, const Y: usize>(
 ) {
     draw_line::<X, Y>(&heading_to_line(heading, X.min(Y)), matrix);
 }
+
+/// the point the anti-aliased needle is drawn to, `length` pixels from the center. Uses
+/// [`fixed_point::antialiased_needle_tip`] with the `fixed-point` feature.
+pub fn antialiased_needle_tip(heading: f32, length: f32) -> FPoint {
+    if cfg!(feature = "fixed-point") {
+        return fixed_point::antialiased_needle_tip(Angle::from_radians(heading), length);
+    }
+    FPoint {
+        x: length * sinf(heading),
+        y: length * cosf(heading),
+    }
+}
+
+/// like [`draw_heading`], but with an anti-aliased needle in brightness levels from 0 to 9, so
+/// headings between two pixels can be told apart.
+pub fn draw_heading_antialiased<const X: usize, const Y: usize>(
+    heading: f32,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let tip = antialiased_needle_tip(heading, X.min(Y) as f32);
+    draw_line_antialiased::<X, Y>(
+        &FLine(FPoint { x: 0.0, y: 0.0 }, tip),
+        matrix,
+        MAX_BRIGHTNESS,
+    );
+}
+
+/// the brightness of the tail drawn by [`DisplayStyle::NeedleWithTail`].
+pub const TAIL_BRIGHTNESS: u8 = 3;
+
+/// The ways a heading can be shown on the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayStyle {
+    /// an anti-aliased needle from the center to the edge.
+    #[default]
+    Needle,
+    /// the needle, with a dim tail the other way.
+    NeedleWithTail,
+    /// a single dot on the outermost ring of LEDs.
+    PerimeterDot,
+    /// an upright letter N, pushed against the edge closest to north.
+    NorthLetter,
+    /// an arrow across the whole display, in one of 8 directions.
+    Arrow,
+}
+
+impl DisplayStyle {
+    pub const ALL: [DisplayStyle; 5] = [
+        DisplayStyle::Needle,
+        DisplayStyle::NeedleWithTail,
+        DisplayStyle::PerimeterDot,
+        DisplayStyle::NorthLetter,
+        DisplayStyle::Arrow,
+    ];
+
+    /// the style after this one, going back to the first after the last.
+    pub fn next(self) -> DisplayStyle {
+        let index = DisplayStyle::ALL
+            .iter()
+            .position(|style| *style == self)
+            .unwrap_or(0);
+        DisplayStyle::ALL[(index + 1) % DisplayStyle::ALL.len()]
+    }
+
+    /// whether the style is drawn in brightness levels that change smoothly with the heading,
+    /// rather than in whole pixels.
+    pub fn is_antialiased(self) -> bool {
+        matches!(self, DisplayStyle::Needle | DisplayStyle::NeedleWithTail)
+    }
+
+    /// draws the heading into a matrix centered on its zero point. The matrix isn't cleared first.
+    pub fn draw<const X: usize, const Y: usize>(
+        self,
+        heading: f32,
+        matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+    ) {
+        match self {
+            DisplayStyle::Needle => draw_heading_antialiased::<X, Y>(heading, matrix),
+            DisplayStyle::NeedleWithTail => draw_needle_with_tail::<X, Y>(heading, matrix),
+            DisplayStyle::PerimeterDot => draw_perimeter_dot::<X, Y>(heading, matrix),
+            DisplayStyle::NorthLetter => draw_north_letter::<X, Y>(heading, matrix),
+            DisplayStyle::Arrow => draw_arrow::<X, Y>(heading, matrix),
+        }
+    }
+}
+
+/// like [`draw_heading_antialiased`], with a dim line drawn from the center the opposite way.
+pub fn draw_needle_with_tail<const X: usize, const Y: usize>(
+    heading: f32,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let tip = antialiased_needle_tip(heading, X.min(Y) as f32);
+    let tail = FPoint {
+        x: -tip.x,
+        y: -tip.y,
+    };
+    draw_line_antialiased::<X, Y>(
+        &FLine(FPoint { x: 0.0, y: 0.0 }, tail),
+        matrix,
+        TAIL_BRIGHTNESS,
+    );
+    draw_heading_antialiased::<X, Y>(heading, matrix);
+}
+
+/// half the width of the largest square around the zero point, 2 on a 5 by 5 display.
+fn radius<const X: usize, const Y: usize>() -> isize {
+    (X.min(Y) as isize - 1) / 2
+}
+
+/// the `index`th pixel of the square ring `radius` pixels out from the center, going clockwise
+/// from the top middle. The ring has `8 * radius` pixels.
+fn ring_point(index: isize, radius: isize) -> Point {
+    let r = radius;
+    let p = index.rem_euclid(8 * r);
+    match p {
+        p if p < r => Point { x: p, y: r },
+        p if p < 3 * r => Point { x: r, y: 2 * r - p },
+        p if p < 5 * r => Point {
+            x: 4 * r - p,
+            y: -r,
+        },
+        p if p < 7 * r => Point {
+            x: -r,
+            y: p - 6 * r,
+        },
+        p => Point { x: p - 8 * r, y: r },
+    }
+}
+
+/// lights the pixel on the outer ring closest to the heading. On a 5 by 5 display the ring has
+/// 16 pixels, so every pixel covers 22.5°.
+pub fn draw_perimeter_dot<const X: usize, const Y: usize>(
+    heading: f32,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let radius = radius::<X, Y>();
+    if radius == 0 {
+        matrix[Point { x: 0, y: 0 }] = MAX_BRIGHTNESS;
+        return;
+    }
+    let steps = 8 * radius;
+    let index = roundf(heading / (2.0 * PI) * steps as f32) as isize;
+    matrix[ring_point(index, radius)] = MAX_BRIGHTNESS;
+}
+
+/// the heading rounded to the closest of the 8 compass points, as a step of 0 (north) to 7
+/// (north-west).
+fn octant(heading: f32) -> usize {
+    (roundf(heading / (PI / 4.0)) as isize).rem_euclid(8) as usize
+}
+
+/// turns a point a number of quarter turns clockwise around the center.
+fn rotate_quarters(point: Point, quarters: usize) -> Point {
+    (0..quarters % 4).fold(point, |point, _| Point {
+        x: point.y,
+        y: -point.x,
+    })
+}
+
+fn plot_glyph<const X: usize, const Y: usize>(
+    glyph: &[Point],
+    offset: Point,
+    quarters: usize,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    for point in glyph {
+        let point = rotate_quarters(*point, quarters);
+        let point = Point {
+            x: point.x + offset.x,
+            y: point.y + offset.y,
+        };
+        if matrix.is_in_bounds(&point) {
+            matrix[point] = MAX_BRIGHTNESS;
+        }
+    }
+}
+
+/// draws an upper case N between two corners: a bar down each side, and a diagonal going down a
+/// row for every column across. At 4 by 5 it is the N of the [font](crate::font).
+fn plot_letter_n<const X: usize, const Y: usize>(
+    top_left: Point,
+    bottom_right: Point,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let mut plot = |point: Point| {
+        if matrix.is_in_bounds(&point) {
+            matrix[point] = MAX_BRIGHTNESS;
+        }
+    };
+    for y in bottom_right.y..=top_left.y {
+        plot(Point { x: top_left.x, y });
+        plot(Point {
+            x: bottom_right.x,
+            y,
+        });
+    }
+    for x in top_left.x + 1..bottom_right.x {
+        let y = (top_left.y - (x - top_left.x)).max(bottom_right.y);
+        plot(Point { x, y });
+    }
+}
+
+/// draws an upright N against the edge or corner closest to the heading, by leaving a row or
+/// column free on the sides away from it. A 5 wide N can't be centered on a 5 by 5 display, so it
+/// is as wide or as tall as the display where it isn't pushed to one side.
+pub fn draw_north_letter<const X: usize, const Y: usize>(
+    heading: f32,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let radius = radius::<X, Y>();
+    let direction = ring_point(octant(heading) as isize, 1);
+    let top_left = Point {
+        x: -radius + (direction.x > 0) as isize,
+        y: radius - (direction.y < 0) as isize,
+    };
+    let bottom_right = Point {
+        x: radius - (direction.x < 0) as isize,
+        y: -radius + (direction.y > 0) as isize,
+    };
+    plot_letter_n::<X, Y>(top_left, bottom_right, matrix);
+}
+
+/// an arrow pointing north, from the bottom edge to the top edge.
+const ARROW_NORTH: [Point; 9] = [
+    Point { x: 0, y: -2 },
+    Point { x: 0, y: -1 },
+    Point { x: 0, y: 0 },
+    Point { x: 0, y: 1 },
+    Point { x: 0, y: 2 },
+    Point { x: -1, y: 1 },
+    Point { x: 1, y: 1 },
+    Point { x: -2, y: 0 },
+    Point { x: 2, y: 0 },
+];
+
+/// an arrow pointing north-east, from the bottom left corner to the top right corner.
+const ARROW_NORTH_EAST: [Point; 9] = [
+    Point { x: -2, y: -2 },
+    Point { x: -1, y: -1 },
+    Point { x: 0, y: 0 },
+    Point { x: 1, y: 1 },
+    Point { x: 2, y: 2 },
+    Point { x: 1, y: 2 },
+    Point { x: 0, y: 2 },
+    Point { x: 2, y: 1 },
+    Point { x: 2, y: 0 },
+];
+
+/// draws a 5 by 5 arrow pointing at the closest of the 8 compass points.
+pub fn draw_arrow<const X: usize, const Y: usize>(
+    heading: f32,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+) {
+    let octant = octant(heading);
+    let glyph = if octant.is_multiple_of(2) {
+        &ARROW_NORTH
+    } else {
+        &ARROW_NORTH_EAST
+    };
+    plot_glyph::<X, Y>(glyph, Point { x: 0, y: 0 }, octant / 2, matrix);
+}
+
+/// the 16 points of the compass, clockwise from north.
+const COMPASS_POINTS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+/// the heading in whole degrees, from 0 to 359 going clockwise from north.
+pub fn heading_degrees(heading: f32) -> u16 {
+    (roundf(heading * (180.0 / PI)) as i32).rem_euclid(360) as u16
+}
+
+/// the closest of the 16 points of the compass, like "W" or "NNE".
+pub fn compass_point(heading: f32) -> &'static str {
+    let index = (roundf(heading / (PI / 8.0)) as isize).rem_euclid(16);
+    COMPASS_POINTS[index as usize]
+}
+
+/// the heading as degrees and compass point, like "273 W", for scrolling across the display.
+pub fn heading_text(heading: f32) -> Text {
+    let mut text = Text::new();
+    // at most 3 digits and 3 letters.
+    let _ = write!(
+        text,
+        "{} {}",
+        heading_degrees(heading),
+        compass_point(heading)
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_drawing::UPoint;
+    use core::f32::consts::PI;
+
+    fn render(heading: f32) -> [[u8; 5]; 5] {
+        let mut matrix: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        draw_heading_antialiased::<5, 5>(heading, &mut matrix);
+        dbg!(matrix.into())
+    }
+
+    #[test]
+    fn antialiased_north() {
+        assert_eq!(
+            render(0.0),
+            [
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn antialiased_center_always_lit() {
+        for i in 0..64 {
+            let matrix = render(i as f32 * PI / 32.0 - PI);
+            assert_eq!(matrix[2][2], MAX_BRIGHTNESS);
+        }
+    }
+
+    #[test]
+    fn antialiased_distinguishes_nearby_headings() {
+        // all of these light the same pixels with the plain needle.
+        let headings = [0.05, 0.1, 0.15];
+        let mut plain = [[[0; 5]; 5]; 3];
+        for (i, heading) in headings.iter().enumerate() {
+            let mut matrix: FourQuadrantMatrix<5, 5, u8> =
+                FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+            draw_heading::<5, 5>(*heading, &mut matrix);
+            plain[i] = matrix.into();
+        }
+        assert_eq!(plain[0], plain[1]);
+        assert_eq!(plain[1], plain[2]);
+
+        assert_ne!(render(headings[0]), render(headings[1]));
+        assert_ne!(render(headings[1]), render(headings[2]));
+    }
+
+    #[test]
+    fn antialiased_is_symmetric() {
+        let east = render(PI / 2.0 - 0.2);
+        let west = render(-PI / 2.0 + 0.2);
+        for row in 0..5 {
+            for column in 0..5 {
+                assert_eq!(east[row][column], west[row][4 - column]);
+            }
+        }
+    }
+
+    fn render_style(style: DisplayStyle, heading: f32) -> [[u8; 5]; 5] {
+        let mut matrix: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        style.draw::<5, 5>(heading, &mut matrix);
+        dbg!(matrix.into())
+    }
+
+    #[test]
+    fn styles_cycle() {
+        let mut style = DisplayStyle::default();
+        for expected in DisplayStyle::ALL.iter().skip(1) {
+            style = style.next();
+            assert_eq!(style, *expected);
+        }
+        assert_eq!(style.next(), DisplayStyle::default());
+    }
+
+    #[test]
+    fn needle_with_tail_north() {
+        assert_eq!(
+            render_style(DisplayStyle::NeedleWithTail, 0.0),
+            [
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 3, 0, 0],
+                [0, 0, 3, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn perimeter_dot_lights_one_ring_pixel() {
+        for i in 0..64 {
+            let matrix = render_style(DisplayStyle::PerimeterDot, i as f32 * PI / 32.0 - PI);
+            let lit: Vec<(usize, usize)> = (0..5)
+                .flat_map(|row| (0..5).map(move |column| (row, column)))
+                .filter(|(row, column)| matrix[*row][*column] != 0)
+                .collect();
+            assert_eq!(lit.len(), 1);
+            let (row, column) = lit[0];
+            assert!(row == 0 || row == 4 || column == 0 || column == 4);
+        }
+    }
+
+    #[test]
+    fn perimeter_dot_visits_all_16() {
+        let mut seen = [[false; 5]; 5];
+        for i in 0..16 {
+            let matrix = render_style(DisplayStyle::PerimeterDot, i as f32 * PI / 8.0);
+            for row in 0..5 {
+                for column in 0..5 {
+                    seen[row][column] |= matrix[row][column] != 0;
+                }
+            }
+        }
+        let count = seen.iter().flatten().filter(|seen| **seen).count();
+        assert_eq!(count, 16);
+        // east is the middle of the right hand column.
+        assert_eq!(render_style(DisplayStyle::PerimeterDot, PI / 2.0)[2][4], 9);
+    }
+
+    #[test]
+    fn north_letter_at_the_edge() {
+        assert_eq!(
+            render_style(DisplayStyle::NorthLetter, 0.0),
+            [
+                [9, 0, 0, 0, 9],
+                [9, 9, 0, 0, 9],
+                [9, 0, 9, 0, 9],
+                [9, 0, 0, 9, 9],
+                [0, 0, 0, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_style(DisplayStyle::NorthLetter, -3.0 * PI / 4.0),
+            [
+                [0, 0, 0, 0, 0],
+                [9, 0, 0, 9, 0],
+                [9, 9, 0, 9, 0],
+                [9, 0, 9, 9, 0],
+                [9, 0, 0, 9, 0],
+            ]
+        );
+        // against a side it is the font's N.
+        let glyph = crate::font::glyph_for('N').unwrap();
+        let east = render_style(DisplayStyle::NorthLetter, PI / 2.0);
+        for (row, pixels) in east.iter().enumerate() {
+            assert_eq!(pixels[0], 0);
+            for column in 0..glyph.width {
+                assert_eq!(pixels[column + 1] != 0, glyph.is_lit(column, row));
+            }
+        }
+    }
+
+    #[test]
+    fn arrow_points_at_compass_points() {
+        assert_eq!(
+            render_style(DisplayStyle::Arrow, 0.1),
+            [
+                [0, 0, 9, 0, 0],
+                [0, 9, 9, 9, 0],
+                [9, 0, 9, 0, 9],
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_style(DisplayStyle::Arrow, PI / 4.0),
+            [
+                [0, 0, 9, 9, 9],
+                [0, 0, 0, 9, 9],
+                [0, 0, 9, 0, 9],
+                [0, 9, 0, 0, 0],
+                [9, 0, 0, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_style(DisplayStyle::Arrow, PI / 2.0),
+            [
+                [0, 0, 9, 0, 0],
+                [0, 0, 0, 9, 0],
+                [9, 9, 9, 9, 9],
+                [0, 0, 0, 9, 0],
+                [0, 0, 9, 0, 0],
+            ]
+        );
+        assert_eq!(
+            render_style(DisplayStyle::Arrow, PI),
+            render_style(DisplayStyle::Arrow, -PI)
+        );
+    }
+
+    #[test]
+    fn heading_readout() {
+        assert_eq!(heading_text(0.0).as_str(), "0 N");
+        assert_eq!(heading_text(-87.0 * PI / 180.0).as_str(), "273 W");
+        assert_eq!(heading_text(PI).as_str(), "180 S");
+        assert_eq!(heading_text(-PI).as_str(), "180 S");
+        assert_eq!(heading_text(22.0 * PI / 180.0).as_str(), "22 NNE");
+        // rounds up to a full circle.
+        assert_eq!(heading_degrees(-0.1 * PI / 180.0), 0);
+        // each point covers 22.5°.
+        assert_eq!(compass_point(-11.0 * PI / 180.0), "N");
+        assert_eq!(compass_point(-12.0 * PI / 180.0), "NNW");
+    }
+}
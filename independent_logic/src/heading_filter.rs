@@ -0,0 +1,318 @@
+//! Smooths the heading so the needle doesn't jitter between neighbouring LEDs.
+//!
+//! Headings wrap around at +-pi, so they can't be averaged directly: the mean of -pi + 0.1 and
+//! pi - 0.1 is south, not north. The filters here work on the heading as a unit vector instead.
+
+use libm::{atan2f, cosf, fabsf, sinf};
+
+use crate::fixed_point;
+use crate::heading_drawing::DisplayStyle;
+use crate::line_drawing::{FourQuadrantMatrix, UPoint};
+use crate::tilt_compensation::Heading;
+
+/// How [`HeadingFilter`] smooths the heading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    /// every heading is passed through as is.
+    None,
+    /// exponential low-pass. `alpha` is between 0 and 1, the weight given to each new heading.
+    LowPass { alpha: f32 },
+    /// the circular mean of the last N headings.
+    Mean,
+    /// the circular median of the last N headings. Slower than the mean, but ignores single
+    /// outliers.
+    Median,
+}
+
+/// Smooths a stream of headings. `N` is the window size used by [`FilterKind::Mean`] and
+/// [`FilterKind::Median`].
+#[derive(Debug, Clone)]
+pub struct HeadingFilter<const N: usize> {
+    kind: FilterKind,
+    /// low-pass state as (sin, cos) of the heading.
+    vector: Option<(f32, f32)>,
+    window: [f32; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> HeadingFilter<N> {
+    pub fn new(kind: FilterKind) -> HeadingFilter<N> {
+        HeadingFilter {
+            kind,
+            vector: None,
+            window: [0.0; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    /// switches to another kind of filter, starting over from the next heading.
+    pub fn set_kind(&mut self, kind: FilterKind) {
+        self.kind = kind;
+        self.reset();
+    }
+
+    /// forgets all previous headings, so the next one is passed through as is.
+    pub fn reset(&mut self) {
+        self.vector = None;
+        self.len = 0;
+        self.next = 0;
+    }
+
+    /// adds a new heading and returns the smoothed one.
+    pub fn update(&mut self, heading: Heading) -> Heading {
+        match self.kind {
+            FilterKind::None => heading,
+            FilterKind::LowPass { alpha } => {
+                let (sin, cos) = sin_cos(heading.0);
+                let Some((old_sin, old_cos)) = self.vector else {
+                    self.vector = Some((sin, cos));
+                    return heading;
+                };
+                let (sin, cos) = (
+                    old_sin + alpha * (sin - old_sin),
+                    old_cos + alpha * (cos - old_cos),
+                );
+                self.vector = Some((sin, cos));
+                Heading(atan2(sin, cos))
+            }
+            FilterKind::Mean => {
+                self.push(heading.0);
+                if self.len == 0 {
+                    return heading;
+                }
+                let (sin, cos) = self.headings().iter().fold((0.0, 0.0), |(s, c), h| {
+                    let (sin, cos) = sin_cos(*h);
+                    (s + sin, c + cos)
+                });
+                Heading(atan2(sin, cos))
+            }
+            FilterKind::Median => {
+                self.push(heading.0);
+                // the heading closest to all of the others.
+                let headings = self.headings();
+                let spread = |a: f32| headings.iter().map(|b| angle_between(a, *b)).sum::<f32>();
+                let median = headings
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| spread(*a).total_cmp(&spread(*b)))
+                    .unwrap_or(heading.0);
+                Heading(median)
+            }
+        }
+    }
+
+    fn push(&mut self, heading: f32) {
+        if N == 0 {
+            return;
+        }
+        self.window[self.next] = heading;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    fn headings(&self) -> &[f32] {
+        &self.window[..self.len]
+    }
+}
+
+/// `sinf` and `cosf`, with CORDIC when built with the `fixed-point` feature.
+fn sin_cos(heading: f32) -> (f32, f32) {
+    if cfg!(feature = "fixed-point") {
+        return fixed_point::sin_cos_f32(heading);
+    }
+    (sinf(heading), cosf(heading))
+}
+
+/// `atan2f`, with CORDIC when built with the `fixed-point` feature. The headings summed up by
+/// the mean are well within the range [`fixed_point::atan2_f32`] takes.
+fn atan2(y: f32, x: f32) -> f32 {
+    if cfg!(feature = "fixed-point") {
+        return fixed_point::atan2_f32(y, x);
+    }
+    atan2f(y, x)
+}
+
+/// the smallest angle between two headings, from 0 to pi.
+pub fn angle_between(a: f32, b: f32) -> f32 {
+    let difference = fabsf(a - b) % (2.0 * core::f32::consts::PI);
+    difference.min(2.0 * core::f32::consts::PI - difference)
+}
+
+/// Holds the display on what it shows until the heading is at least `margin` radians past where
+/// the drawing changes, so a heading right on the edge between two pixels doesn't flicker between
+/// them. The anti-aliased needles change smoothly instead of in steps, so they aren't held.
+#[derive(Debug, Clone)]
+pub struct NeedleHysteresis {
+    margin: f32,
+    shown: Option<Heading>,
+}
+
+impl NeedleHysteresis {
+    pub fn new(margin: f32) -> NeedleHysteresis {
+        NeedleHysteresis {
+            margin,
+            shown: None,
+        }
+    }
+
+    /// returns the heading to draw in `style` on an X by Y display.
+    pub fn update<const X: usize, const Y: usize>(
+        &mut self,
+        style: DisplayStyle,
+        heading: Heading,
+    ) -> Heading {
+        if style.is_antialiased() {
+            self.shown = Some(heading);
+            return heading;
+        }
+        let draw = |heading: f32| {
+            let mut matrix: FourQuadrantMatrix<X, Y, u8> = FourQuadrantMatrix::new(UPoint {
+                x: (X - 1) / 2,
+                y: (Y - 1) / 2,
+            });
+            style.draw::<X, Y>(heading, &mut matrix);
+            matrix
+        };
+        let frame = draw(heading.0);
+        if let Some(shown) = self.shown {
+            let moved = draw(shown.0) != frame;
+            let clearly_inside =
+                draw(heading.0 - self.margin) == frame && draw(heading.0 + self.margin) == frame;
+            if moved && !clearly_inside {
+                return shown;
+            }
+        }
+        self.shown = Some(heading);
+        heading
+    }
+
+    pub fn reset(&mut self) {
+        self.shown = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    fn run<const N: usize>(filter: &mut HeadingFilter<N>, headings: &[f32]) -> f32 {
+        let mut out = Heading(0.0);
+        for heading in headings {
+            out = filter.update(Heading(*heading));
+        }
+        dbg!(out.0)
+    }
+
+    #[test]
+    fn none_passes_through() {
+        let mut filter: HeadingFilter<4> = HeadingFilter::new(FilterKind::None);
+        assert_eq!(run(&mut filter, &[0.1, 2.0, -1.5]), -1.5);
+    }
+
+    #[test]
+    fn angle_between_wraps() {
+        assert!(fabsf(angle_between(PI - 0.1, -PI + 0.1) - 0.2) < 1e-5);
+        assert!(fabsf(angle_between(0.5, -0.5) - 1.0) < 1e-6);
+        assert!(fabsf(angle_between(0.0, PI) - PI) < 1e-6);
+    }
+
+    #[test]
+    fn filters_handle_the_seam() {
+        let headings = [PI - 0.1, -PI + 0.1, PI - 0.05, -PI + 0.05];
+        for kind in [
+            FilterKind::LowPass { alpha: 0.5 },
+            FilterKind::Mean,
+            FilterKind::Median,
+        ] {
+            let mut filter: HeadingFilter<4> = HeadingFilter::new(kind);
+            let heading = run(&mut filter, &headings);
+            assert!(angle_between(heading, PI) < 0.1, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn low_pass_converges() {
+        let mut filter: HeadingFilter<0> = HeadingFilter::new(FilterKind::LowPass { alpha: 0.3 });
+        let first = filter.update(Heading(0.0)).0;
+        assert_eq!(first, 0.0);
+        let step = filter.update(Heading(1.0)).0;
+        assert!(step > 0.0 && step < 1.0);
+        let heading = run(&mut filter, &[1.0; 40]);
+        assert!(fabsf(heading - 1.0) < 1e-3);
+    }
+
+    #[test]
+    fn mean_of_window() {
+        let mut filter: HeadingFilter<3> = HeadingFilter::new(FilterKind::Mean);
+        // the first heading has dropped out of the window.
+        let heading = run(&mut filter, &[2.0, 0.1, 0.2, 0.3]);
+        assert!(fabsf(heading - 0.2) < 1e-5);
+    }
+
+    #[test]
+    fn median_ignores_outlier() {
+        let mut filter: HeadingFilter<5> = HeadingFilter::new(FilterKind::Median);
+        let heading = run(&mut filter, &[0.1, 0.12, 3.0, 0.11, 0.13]);
+        assert!(fabsf(heading - 0.12) < 1e-6);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut filter: HeadingFilter<4> = HeadingFilter::new(FilterKind::Mean);
+        run(&mut filter, &[1.0, 1.0]);
+        filter.set_kind(FilterKind::LowPass { alpha: 0.1 });
+        assert_eq!(filter.update(Heading(-2.0)).0, -2.0);
+    }
+
+    #[test]
+    fn hysteresis_holds_near_the_edge() {
+        for style in [
+            DisplayStyle::PerimeterDot,
+            DisplayStyle::NorthLetter,
+            DisplayStyle::Arrow,
+        ] {
+            let draw = |heading: f32| {
+                let mut matrix: FourQuadrantMatrix<5, 5, u8> =
+                    FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+                style.draw::<5, 5>(heading, &mut matrix);
+                matrix
+            };
+            let mut hysteresis = NeedleHysteresis::new(0.05);
+            // find a heading where the drawing changes.
+            let mut edge = 0.0;
+            while draw(edge) == draw(0.0) {
+                edge += 0.001;
+            }
+            let mut update = |heading: f32| hysteresis.update::<5, 5>(style, Heading(heading)).0;
+            assert_eq!(update(0.0), 0.0);
+            // just over the edge is ignored.
+            assert_eq!(update(edge + 0.01), 0.0, "{:?}", style);
+            // moving within what is shown is fine.
+            assert_eq!(update(edge - 0.01), edge - 0.01);
+            // clearly past the edge moves the display.
+            assert_eq!(update(edge + 0.1), edge + 0.1);
+            // and going back just over the edge keeps it there.
+            assert_eq!(update(edge - 0.01), edge + 0.1);
+        }
+    }
+
+    #[test]
+    fn antialiased_needles_are_not_held() {
+        for style in [DisplayStyle::Needle, DisplayStyle::NeedleWithTail] {
+            let mut hysteresis = NeedleHysteresis::new(0.05);
+            for heading in [0.0, 0.01, -0.01, 0.3, 0.29] {
+                assert_eq!(
+                    hysteresis.update::<5, 5>(style, Heading(heading)).0,
+                    heading
+                );
+            }
+        }
+    }
+}
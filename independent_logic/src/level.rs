@@ -0,0 +1,188 @@
+//! A spirit level: a bubble that floats to the highest side of the board.
+//!
+//! The top of the display is the front of the board (positive pitch lifts it) and the right side
+//! is its right (positive roll lowers it).
+
+use core::f32::consts::PI;
+use core::fmt::Write;
+
+use libm::{floorf, roundf};
+
+use crate::font::Text;
+use crate::line_drawing::{FourQuadrantMatrix, Point, MAX_BRIGHTNESS};
+use crate::tilt_compensation::Attitude;
+
+/// the tilt, in radians, that moves the bubble all the way to the edge.
+pub const FULL_SCALE: f32 = 20.0 * PI / 180.0;
+/// the brightness of the mark at the center of the display.
+pub const CENTER_BRIGHTNESS: u8 = 2;
+
+/// Turns attitudes into bubble positions, relative to a surface that was zeroed against.
+#[derive(Debug, Clone, Default)]
+pub struct Level {
+    zero: Attitude,
+}
+
+impl Level {
+    pub fn new() -> Level {
+        Level {
+            zero: Attitude::default(),
+        }
+    }
+
+    /// makes the current attitude the new level.
+    pub fn set_zero(&mut self, attitude: Attitude) {
+        self.zero = attitude;
+    }
+
+    /// measures against gravity again.
+    pub fn clear_zero(&mut self) {
+        self.zero = Attitude::default();
+    }
+
+    pub fn zero(&self) -> Attitude {
+        self.zero
+    }
+
+    /// the attitude relative to the zeroed surface.
+    pub fn relative(&self, attitude: Attitude) -> Attitude {
+        Attitude {
+            pitch: attitude.pitch - self.zero.pitch,
+            roll: attitude.roll - self.zero.roll,
+        }
+    }
+
+    /// draws a dim mark at the center and the bubble, spread over the pixels around it so it
+    /// moves smoothly. The bubble stops at the edges once the tilt is past [`FULL_SCALE`].
+    pub fn draw_bubble<const X: usize, const Y: usize>(
+        &self,
+        attitude: Attitude,
+        matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+    ) {
+        let center = Point { x: 0, y: 0 };
+        matrix[center] = matrix[center].max(CENTER_BRIGHTNESS);
+
+        let attitude = self.relative(attitude);
+        let (min, max) = (matrix.min_point(), matrix.max_point());
+        let scale = |tilt: f32, min: isize, max: isize| {
+            let position = tilt / FULL_SCALE * max as f32;
+            position.clamp(min as f32, max as f32)
+        };
+        let x = scale(-attitude.roll, min.x, max.x);
+        let y = scale(attitude.pitch, min.y, max.y);
+
+        let (left, bottom) = (floorf(x), floorf(y));
+        let (fx, fy) = (x - left, y - bottom);
+        for (dx, dy, coverage) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let point = Point {
+                x: left as isize + dx,
+                y: bottom as isize + dy,
+            };
+            if matrix.is_in_bounds(&point) {
+                let value = roundf(coverage * MAX_BRIGHTNESS as f32) as u8;
+                matrix[point] = matrix[point].max(value);
+            }
+        }
+    }
+
+    /// the pitch and roll relative to the zeroed surface in whole degrees, as "PITCH ROLL".
+    pub fn angles_text(&self, attitude: Attitude) -> Text {
+        let attitude = self.relative(attitude);
+        let degrees = |angle: f32| roundf(angle * (180.0 / PI)) as i32;
+        let mut text = Text::new();
+        // two i32s always fit.
+        let _ = write!(
+            text,
+            "{} {}",
+            degrees(attitude.pitch),
+            degrees(attitude.roll)
+        );
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_drawing::UPoint;
+
+    fn render(level: &Level, pitch_degrees: f32, roll_degrees: f32) -> [[u8; 5]; 5] {
+        let mut matrix: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        let attitude = Attitude {
+            pitch: pitch_degrees * PI / 180.0,
+            roll: roll_degrees * PI / 180.0,
+        };
+        level.draw_bubble::<5, 5>(attitude, &mut matrix);
+        dbg!(matrix.into())
+    }
+
+    #[test]
+    fn flat_bubble_in_the_middle() {
+        assert_eq!(
+            render(&Level::new(), 0.0, 0.0),
+            [
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn bubble_floats_up() {
+        // front lifted: the bubble moves to the top.
+        let matrix = render(&Level::new(), 10.0, 0.0);
+        assert_eq!(matrix[1][2], 9);
+        assert_eq!(matrix[2][2], CENTER_BRIGHTNESS);
+        // right side lowered: the bubble moves left.
+        let matrix = render(&Level::new(), 0.0, 10.0);
+        assert_eq!(matrix[2][1], 9);
+        // past full scale it stays in the corner.
+        let matrix = render(&Level::new(), -45.0, -45.0);
+        assert_eq!(matrix[4][4], 9);
+    }
+
+    #[test]
+    fn bubble_between_pixels() {
+        let matrix = render(&Level::new(), 5.0, 0.0);
+        assert_eq!(matrix[1][2], 5);
+        assert_eq!(matrix[2][2], 5);
+    }
+
+    #[test]
+    fn zeroing() {
+        let mut level = Level::new();
+        let surface = Attitude {
+            pitch: 0.1,
+            roll: -0.2,
+        };
+        level.set_zero(surface);
+        assert_eq!(level.relative(surface), Attitude::default());
+        assert_eq!(
+            render(&level, 0.1 * 180.0 / PI, -0.2 * 180.0 / PI),
+            render(&Level::new(), 0.0, 0.0)
+        );
+        level.clear_zero();
+        assert_eq!(level.relative(surface), surface);
+    }
+
+    #[test]
+    fn angles_text() {
+        let mut level = Level::new();
+        let attitude = Attitude {
+            pitch: 12.4 * PI / 180.0,
+            roll: -3.6 * PI / 180.0,
+        };
+        assert_eq!(level.angles_text(attitude).as_str(), "12 -4");
+        level.set_zero(attitude);
+        assert_eq!(level.angles_text(attitude).as_str(), "0 0");
+    }
+}
@@ -1,5 +1,27 @@
 //to help debug failed tests wiht dbg!()
 #![cfg_attr(not(test), no_std)]
+pub mod app;
+pub mod auto_calibration;
+pub mod buttons;
+pub mod calibration;
+pub mod calibration_record;
+pub mod compass;
+pub mod fault;
+pub mod fixed_point;
+pub mod font;
+pub mod frames;
+#[cfg(feature = "embedded-graphics")]
+pub mod graphics;
 pub mod heading_drawing;
+pub mod heading_filter;
+pub mod level;
 pub mod line_drawing;
+pub mod menu;
+pub mod mounting;
+pub mod nmea;
+pub mod protocol;
+pub mod record_store;
+pub mod recording;
+pub mod sensor;
 pub mod tilt_compensation;
+pub mod wmm;
@@ -2,6 +2,7 @@ use core::{
     mem::swap,
     ops::{Index, IndexMut},
 };
+use libm::{floorf, roundf};
 #[cfg(test)]
 use std::dbg;
 
@@ -69,6 +70,39 @@ where
         }
     }
 
+    /// wraps a plain 2d array, with the given zero point.
+    pub fn from_array(
+        matrix: [[T; X]; Y],
+        zero_coord: UPoint,
+    ) -> FourQuadrantMatrix<{ X }, { Y }, T> {
+        FourQuadrantMatrix {
+            matrix,
+            ..FourQuadrantMatrix::new(zero_coord)
+        }
+    }
+
+    /// the matrix turned clockwise about its zero point by a number of quarter turns. Values that
+    /// would end up out of bounds are dropped, and pixels nothing is turned onto are left at the
+    /// default value.
+    pub fn rotated(&self, quarter_turns: u8) -> FourQuadrantMatrix<{ X }, { Y }, T> {
+        let mut rotated = FourQuadrantMatrix::new(self.zero_coord);
+        for y in self.min_point.y..=self.max_point.y {
+            for x in self.min_point.x..=self.max_point.x {
+                let mut point = Point { x, y };
+                for _ in 0..quarter_turns % 4 {
+                    point = Point {
+                        x: point.y,
+                        y: -point.x,
+                    };
+                }
+                if rotated.is_in_bounds(&point) {
+                    rotated[point] = self[Point { x, y }];
+                }
+            }
+        }
+        rotated
+    }
+
     pub fn zero_coord(&self) -> UPoint {
         self.zero_coord
     }
@@ -224,6 +258,68 @@ pub fn draw_line<const X: usize, const Y: usize>(
     }
 }
 
+/// the brightest value [`draw_line_antialiased`] writes, matching the micro:bits 10 brightness
+/// levels.
+pub const MAX_BRIGHTNESS: u8 = 9;
+
+/// a point in 2d space that doesn't have to lie on a pixel. Pixel centers are at whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// a line segment between two [`FPoint`]s
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FLine(pub FPoint, pub FPoint);
+
+/// Renders a line with Xiaolin Wus algorithm: each column (or row, if the line is steep) lights
+/// the two pixels the line passes between, in proportion to how close it passes to each of them.
+/// Brightness goes from 0 to `brightness`, and a pixel already brighter than the line is kept.
+/// Like [`draw_line`], anything outside of the matrix is skipped.
+pub fn draw_line_antialiased<const X: usize, const Y: usize>(
+    line: &FLine,
+    matrix: &mut FourQuadrantMatrix<{ X }, { Y }, u8>,
+    brightness: u8,
+) {
+    let FLine(mut start, mut end) = *line;
+    let steep = (end.y - start.y).abs() > (end.x - start.x).abs();
+    if steep {
+        swap(&mut start.x, &mut start.y);
+        swap(&mut end.x, &mut end.y);
+    }
+    if start.x > end.x {
+        swap(&mut start, &mut end);
+    }
+
+    let dx = end.x - start.x;
+    let gradient = if dx == 0.0 {
+        0.0
+    } else {
+        (end.y - start.y) / dx
+    };
+
+    let mut plot = |x: isize, y: isize, coverage: f32| {
+        let point = if steep {
+            Point { x: y, y: x }
+        } else {
+            Point { x, y }
+        };
+        if matrix.is_in_bounds(&point) {
+            let value = roundf(coverage * brightness as f32) as u8;
+            matrix[point] = matrix[point].max(value);
+        }
+    };
+
+    for x in roundf(start.x) as isize..=roundf(end.x) as isize {
+        let y = start.y + gradient * (x as f32 - start.x);
+        let below = floorf(y);
+        let fraction = y - below;
+        plot(x, below as isize, 1.0 - fraction);
+        plot(x, below as isize + 1, fraction);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -458,4 +554,69 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn antialiased_straight_line() {
+        let mut canvas: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        draw_line_antialiased(
+            &FLine(FPoint { x: 0.0, y: 0.0 }, FPoint { x: 0.0, y: 5.0 }),
+            &mut canvas,
+            MAX_BRIGHTNESS,
+        );
+        assert_eq!(
+            <FourQuadrantMatrix<5, 5, u8> as Into<[[u8; 5]; 5]>>::into(canvas),
+            [
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        )
+    }
+
+    #[test]
+    fn antialiased_between_pixels() {
+        let mut canvas: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        // a quarter of the way from one column to the next.
+        draw_line_antialiased(
+            &FLine(FPoint { x: -2.0, y: 0.25 }, FPoint { x: 2.0, y: 0.25 }),
+            &mut canvas,
+            8,
+        );
+        assert_eq!(
+            <FourQuadrantMatrix<5, 5, u8> as Into<[[u8; 5]; 5]>>::into(canvas),
+            [
+                [0, 0, 0, 0, 0],
+                [2, 2, 2, 2, 2],
+                [6, 6, 6, 6, 6],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        )
+    }
+
+    #[test]
+    fn antialiased_keeps_brighter_pixels() {
+        let mut canvas: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+        canvas[Point { x: 0, y: 1 }] = 9;
+        draw_line_antialiased(
+            &FLine(FPoint { x: -2.0, y: 0.5 }, FPoint { x: 2.0, y: 0.5 }),
+            &mut canvas,
+            MAX_BRIGHTNESS,
+        );
+        assert_eq!(
+            <FourQuadrantMatrix<5, 5, u8> as Into<[[u8; 5]; 5]>>::into(canvas),
+            [
+                [0, 0, 0, 0, 0],
+                [5, 5, 9, 5, 5],
+                [5, 5, 5, 5, 5],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        )
+    }
 }
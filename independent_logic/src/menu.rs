@@ -0,0 +1,195 @@
+//! Draws the menus: one 5 by 5 icon per entry, scrolled sideways from one entry to the next.
+
+use crate::app::{App, MenuItem, Setting};
+use crate::line_drawing::{
+    draw_line_antialiased, FLine, FPoint, FourQuadrantMatrix, UPoint, MAX_BRIGHTNESS,
+};
+use crate::mounting::Mounting;
+
+pub type Frame = [[u8; 5]; 5];
+
+const COMPASS_ICON: Frame = [
+    [0, 0, 9, 0, 0],
+    [0, 9, 9, 9, 0],
+    [9, 0, 9, 0, 9],
+    [0, 0, 9, 0, 0],
+    [0, 0, 9, 0, 0],
+];
+
+const LEVEL_ICON: Frame = [
+    [0, 0, 0, 0, 0],
+    [9, 0, 0, 0, 9],
+    [9, 0, 9, 0, 9],
+    [9, 9, 9, 9, 9],
+    [0, 0, 0, 0, 0],
+];
+
+const CALIBRATE_ICON: Frame = [
+    [0, 9, 9, 9, 0],
+    [9, 0, 0, 0, 9],
+    [9, 0, 9, 0, 9],
+    [9, 0, 0, 0, 9],
+    [0, 9, 9, 9, 0],
+];
+
+const SETTINGS_ICON: Frame = [
+    [0, 0, 9, 0, 0],
+    [0, 9, 9, 9, 0],
+    [9, 9, 0, 9, 9],
+    [0, 9, 9, 9, 0],
+    [0, 0, 9, 0, 0],
+];
+
+/// the brightness of a setting that is turned off.
+const OFF_BRIGHTNESS: u8 = 2;
+
+pub fn menu_icon(item: MenuItem) -> Frame {
+    match item {
+        MenuItem::Compass => COMPASS_ICON,
+        MenuItem::Level => LEVEL_ICON,
+        MenuItem::Calibrate => CALIBRATE_ICON,
+        MenuItem::Settings => SETTINGS_ICON,
+    }
+}
+
+/// an icon that also shows the current value of the setting.
+pub fn setting_icon(setting: Setting, app: &App) -> Frame {
+    let mut matrix: FourQuadrantMatrix<5, 5, u8> = FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+    match setting {
+        // a tilted line, dim when tilt correction is off.
+        Setting::TiltCorrection => {
+            let brightness = if app.tilt_correction {
+                MAX_BRIGHTNESS
+            } else {
+                OFF_BRIGHTNESS
+            };
+            draw_line_antialiased::<5, 5>(
+                &FLine(FPoint { x: -2.0, y: -1.0 }, FPoint { x: 2.0, y: 1.0 }),
+                &mut matrix,
+                brightness,
+            );
+        }
+        // the style itself, pointing north.
+        Setting::DisplayStyle => app.display_style.draw::<5, 5>(0.0, &mut matrix),
+        // the number of the mounting as that many LEDs, which reads the same however the display
+        // is turned. Custom mountings light all of them dimly.
+        Setting::Mounting => {
+            let (count, brightness) = match app.mounting {
+                Mounting::Aligned(index) => (index as usize + 1, MAX_BRIGHTNESS),
+                Mounting::Custom(_) => (25, OFF_BRIGHTNESS),
+            };
+            let mut frame = [[0; 5]; 5];
+            for led in frame.iter_mut().flatten().take(count) {
+                *led = brightness;
+            }
+            return frame;
+        }
+    }
+    matrix.into()
+}
+
+/// the width of a frame plus the blank column after it.
+const STRIDE: usize = 6;
+
+/// Slides between frames laid out from left to right, one column per step.
+#[derive(Debug, Clone, Default)]
+pub struct Scroller {
+    /// the leftmost column shown.
+    offset: usize,
+}
+
+impl Scroller {
+    pub fn new() -> Scroller {
+        Scroller { offset: 0 }
+    }
+
+    /// shows a frame straight away, without scrolling to it.
+    pub fn jump_to(&mut self, index: usize) {
+        self.offset = index * STRIDE;
+    }
+
+    /// moves one column closer to a frame. Returns false once it is already shown.
+    pub fn step(&mut self, index: usize) -> bool {
+        let target = index * STRIDE;
+        if self.offset < target {
+            self.offset += 1;
+        } else if self.offset > target {
+            self.offset -= 1;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// what is visible of the frames at the current position.
+    pub fn frame(&self, frames: &[Frame]) -> Frame {
+        let mut out = [[0; 5]; 5];
+        for (row, leds) in out.iter_mut().enumerate() {
+            for (column, led) in leds.iter_mut().enumerate() {
+                let strip_column = self.offset + column;
+                let (index, frame_column) = (strip_column / STRIDE, strip_column % STRIDE);
+                if let (Some(frame), true) = (frames.get(index), frame_column < 5) {
+                    *led = frame[row][frame_column];
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrolls_between_frames() {
+        let frames = [COMPASS_ICON, CALIBRATE_ICON];
+        let mut scroller = Scroller::new();
+        assert_eq!(scroller.frame(&frames), COMPASS_ICON);
+        assert!(!scroller.step(0));
+
+        assert!(scroller.step(1));
+        // the first frame moved one column to the left.
+        let frame = scroller.frame(&frames);
+        for row in 0..5 {
+            assert_eq!(frame[row][..4], COMPASS_ICON[row][1..]);
+            assert_eq!(frame[row][4], 0);
+        }
+
+        let mut steps = 1;
+        while scroller.step(1) {
+            steps += 1;
+        }
+        assert_eq!(steps, STRIDE);
+        assert_eq!(scroller.frame(&frames), CALIBRATE_ICON);
+
+        scroller.jump_to(0);
+        assert_eq!(scroller.frame(&frames), COMPASS_ICON);
+    }
+
+    #[test]
+    fn missing_frames_are_blank() {
+        let mut scroller = Scroller::new();
+        scroller.jump_to(3);
+        assert_eq!(scroller.frame(&[COMPASS_ICON]), [[0; 5]; 5]);
+    }
+
+    #[test]
+    fn setting_icons_show_values() {
+        let mut app = App::new();
+        let on = setting_icon(Setting::TiltCorrection, &app);
+        app.tilt_correction = false;
+        let off = setting_icon(Setting::TiltCorrection, &app);
+        dbg!((on, off));
+        assert_eq!(on.iter().flatten().max(), Some(&MAX_BRIGHTNESS));
+        assert_eq!(off.iter().flatten().max(), Some(&OFF_BRIGHTNESS));
+
+        let needle = setting_icon(Setting::DisplayStyle, &app);
+        app.display_style = app.display_style.next();
+        assert_ne!(needle, setting_icon(Setting::DisplayStyle, &app));
+
+        app.mounting = Mounting::Aligned(12);
+        let lit = |frame: Frame| frame.iter().flatten().filter(|led| **led > 0).count();
+        assert_eq!(lit(setting_icon(Setting::Mounting, &app)), 13);
+    }
+}
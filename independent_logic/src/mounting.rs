@@ -0,0 +1,342 @@
+//! How the board is mounted in its enclosure. Readings are rotated from the boards axes into the
+//! enclosures before the attitude is calculated, so the heading is where the enclosure points,
+//! and the display is turned so up on the LEDs is still forward.
+//!
+//! Besides any rotation given as a matrix, there are the 24 mountings that keep the edges of the
+//! board along the edges of the enclosure, numbered from 1 in groups of four. Within a group the
+//! board is turned a quarter at a time about the axis that points down:
+//!
+//! | number | mounting                                                           |
+//! |--------|--------------------------------------------------------------------|
+//! | 1-4    | flat, display up. 1 is the default, with the USB connector forward |
+//! | 5-8    | flat, display down                                                 |
+//! | 9-12   | upright, USB connector down                                        |
+//! | 13-16  | upright, USB connector up. 13 has the display facing backwards     |
+//! | 17-20  | upright, resting on one side edge                                  |
+//! | 21-24  | upright, resting on the other side edge                            |
+//!
+//! The mounting is stored in flash next to the calibration, as a record laid out like
+//! [`calibration_record`](crate::calibration_record):
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic, `b"MNTG"`                              |
+//! | 4      | 2    | format version                                |
+//! | 6      | 1    | mounting kind                                 |
+//! | 7      | 1    | the aligned mountings index, 0 otherwise      |
+//! | 8      | 36   | the matrix of a custom mounting, zero padded  |
+//! | 44     | 4    | CRC-32 (IEEE) of all preceding bytes          |
+
+use core::f32::consts::PI;
+use core::ops::{Mul, Sub};
+
+use libm::{atan2f, fabsf, roundf};
+
+use crate::calibration::{mat_vec_mul, Matrix3};
+use crate::calibration_record::{crc32, RecordError};
+use crate::fixed_point::{self, NedReading};
+use crate::line_drawing::{FourQuadrantMatrix, UPoint};
+use crate::tilt_compensation::NedMeasurement;
+
+/// the number of mountings along the boards edges.
+pub const ALIGNED_COUNT: usize = 24;
+/// how far a custom matrix may be from a rotation, per element.
+const ROTATION_TOLERANCE: f32 = 1e-3;
+
+pub const RECORD_LEN: usize = 48;
+pub const MAGIC: [u8; 4] = *b"MNTG";
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 8;
+const CRC_OFFSET: usize = RECORD_LEN - 4;
+
+const KIND_ALIGNED: u8 = 0;
+const KIND_CUSTOM: u8 = 1;
+
+/// the axes of the boards [`Ned`](crate::frames::Ned) frame, with their sign, in the order the
+/// aligned mountings use them.
+const AXES: [(usize, i32); 6] = [(2, 1), (2, -1), (0, 1), (0, -1), (1, 1), (1, -1)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mounting {
+    /// one of the [`ALIGNED_COUNT`] mountings along the boards edges, counting from 0.
+    Aligned(u8),
+    /// any rotation, as a matrix taking readings from the boards axes into the enclosures. Make it
+    /// with [`Mounting::custom`].
+    Custom(Matrix3),
+}
+
+impl Default for Mounting {
+    fn default() -> Self {
+        Mounting::Aligned(0)
+    }
+}
+
+impl Mounting {
+    /// a custom mounting, if the matrix is a rotation.
+    pub fn custom(matrix: Matrix3) -> Option<Mounting> {
+        is_rotation(&matrix).then_some(Mounting::Custom(matrix))
+    }
+
+    /// the next aligned mounting, for stepping through them in the settings. Custom mountings go
+    /// back to the first one.
+    pub fn next(self) -> Mounting {
+        match self {
+            Mounting::Aligned(index) => Mounting::Aligned((index + 1) % ALIGNED_COUNT as u8),
+            Mounting::Custom(_) => Mounting::Aligned(0),
+        }
+    }
+
+    /// the rotation from the boards axes into the enclosures. Its rows are the enclosures
+    /// forward, side and down axes, as seen by the board.
+    pub fn matrix(&self) -> Matrix3 {
+        match self {
+            Mounting::Aligned(index) => aligned_matrix(*index as usize % ALIGNED_COUNT),
+            Mounting::Custom(matrix) => *matrix,
+        }
+    }
+
+    /// a reading in the enclosures axes.
+    pub fn apply(&self, measurement: NedMeasurement) -> NedMeasurement {
+        if *self == Mounting::default() {
+            return measurement;
+        }
+        let [x, y, z] = mat_vec_mul(
+            &self.matrix(),
+            &[measurement.x, measurement.y, measurement.z],
+        );
+        NedMeasurement::new(x, y, z)
+    }
+
+    /// like [`apply`](Self::apply), for the fixed point pipeline. The aligned mountings only swap
+    /// and negate axes, and custom ones are rotated in Q15, so neither needs floats.
+    pub fn apply_fixed(&self, measurement: NedReading) -> NedReading {
+        match self {
+            _ if *self == Mounting::default() => measurement,
+            Mounting::Aligned(index) => {
+                let [x, y, z] = aligned_axes(*index as usize % ALIGNED_COUNT).map(|row| {
+                    row[0] * measurement.x + row[1] * measurement.y + row[2] * measurement.z
+                });
+                NedReading::new(x, y, z)
+            }
+            Mounting::Custom(matrix) => fixed_point::rotate(matrix, measurement),
+        }
+    }
+
+    /// The quarter turns, clockwise, that frames drawn for the enclosure have to be turned by so
+    /// up on the display is forward. When the display faces forward or backwards, up on the
+    /// display is up instead.
+    pub fn display_turns(&self) -> u8 {
+        let [forward, _, down] = self.matrix();
+        // the top of the display is the boards x axis, and its right hand side is y.
+        let (x, y) = if fabsf(forward[0]) + fabsf(forward[1]) > 0.5 {
+            (forward[0], forward[1])
+        } else {
+            (-down[0], -down[1])
+        };
+        let angle = if cfg!(feature = "fixed-point") {
+            fixed_point::atan2_f32(y, x)
+        } else {
+            atan2f(y, x)
+        };
+        let turns = roundf(angle / (PI / 2.0)) as i32;
+        turns.rem_euclid(4) as u8
+    }
+
+    /// turns a frame drawn for the enclosure to match the display, see
+    /// [`Mounting::display_turns`].
+    pub fn turn_frame(&self, frame: [[u8; 5]; 5]) -> [[u8; 5]; 5] {
+        let matrix: FourQuadrantMatrix<5, 5, u8> =
+            FourQuadrantMatrix::from_array(frame, UPoint { x: 2, y: 2 });
+        matrix.rotated(self.display_turns()).into()
+    }
+}
+
+/// the aligned mounting with the index, see the table in the module docs.
+fn aligned_matrix(index: usize) -> Matrix3 {
+    aligned_axes(index).map(|row| row.map(|value| value as f32))
+}
+
+/// the rows of [`aligned_matrix`], which are all 0, 1 or -1.
+fn aligned_axes(index: usize) -> [[i32; 3]; 3] {
+    let axis = |(index, sign): (usize, i32)| {
+        let mut axis = [0; 3];
+        axis[index] = sign;
+        axis
+    };
+    let down = AXES[index / 4];
+    let first = AXES.into_iter().find(|(axis, _)| *axis != down.0).unwrap();
+    let (mut forward, down) = (axis(first), axis(down));
+    for _ in 0..index % 4 {
+        forward = cross(down, forward);
+    }
+    [forward, cross(down, forward), down]
+}
+
+fn cross<T: Copy + Mul<Output = T> + Sub<Output = T>>(a: [T; 3], b: [T; 3]) -> [T; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// true if the rows are orthonormal and right handed.
+fn is_rotation(matrix: &Matrix3) -> bool {
+    let dot = |a: &[f32; 3], b: &[f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let orthonormal = (0..3).all(|i| {
+        (0..3).all(|j| {
+            let expected = if i == j { 1.0 } else { 0.0 };
+            fabsf(dot(&matrix[i], &matrix[j]) - expected) < ROTATION_TOLERANCE
+        })
+    });
+    orthonormal && dot(&cross(matrix[0], matrix[1]), &matrix[2]) > 0.0
+}
+
+/// serializes a mounting into a record ready to be written to flash.
+pub fn encode(mounting: &Mounting) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[0..4].copy_from_slice(&MAGIC);
+    record[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    match mounting {
+        Mounting::Aligned(index) => {
+            record[6] = KIND_ALIGNED;
+            record[7] = *index;
+        }
+        Mounting::Custom(matrix) => {
+            record[6] = KIND_CUSTOM;
+            for (value, bytes) in matrix
+                .iter()
+                .flatten()
+                .zip(record[HEADER_LEN..CRC_OFFSET].chunks_exact_mut(4))
+            {
+                bytes.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    let crc = crc32(&record[..CRC_OFFSET]);
+    record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// validates and deserializes a record read back from flash.
+pub fn decode(record: &[u8]) -> Result<Mounting, RecordError> {
+    if record.len() < RECORD_LEN {
+        return Err(RecordError::TooShort);
+    }
+    if record[0..4] != MAGIC {
+        return Err(RecordError::BadMagic);
+    }
+    let version = u16::from_le_bytes([record[4], record[5]]);
+    if version != VERSION {
+        return Err(RecordError::UnsupportedVersion(version));
+    }
+    let crc = u32::from_le_bytes(record[CRC_OFFSET..RECORD_LEN].try_into().unwrap());
+    if crc != crc32(&record[..CRC_OFFSET]) {
+        return Err(RecordError::BadChecksum);
+    }
+
+    match record[6] {
+        KIND_ALIGNED if (record[7] as usize) < ALIGNED_COUNT => Ok(Mounting::Aligned(record[7])),
+        KIND_ALIGNED => Err(RecordError::InvalidValue),
+        KIND_CUSTOM => {
+            let mut matrix: Matrix3 = [[0.0; 3]; 3];
+            for (value, bytes) in matrix
+                .iter_mut()
+                .flatten()
+                .zip(record[HEADER_LEN..CRC_OFFSET].chunks_exact(4))
+            {
+                *value = f32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            Mounting::custom(matrix).ok_or(RecordError::InvalidValue)
+        }
+        kind => Err(RecordError::UnknownKind(kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn angle() -> Mounting {
+        let (s, c) = (0.6, 0.8);
+        Mounting::custom([[c, 0.0, -s], [0.0, 1.0, 0.0], [s, 0.0, c]]).unwrap()
+    }
+
+    #[test]
+    fn aligned_mountings_are_distinct_rotations() {
+        let matrices: Vec<Matrix3> = (0..ALIGNED_COUNT).map(aligned_matrix).collect();
+        for (i, matrix) in matrices.iter().enumerate() {
+            assert!(is_rotation(matrix), "{} {:?}", i, matrix);
+            assert!(!matrices[..i].contains(matrix), "{}", i);
+        }
+        assert_eq!(
+            Mounting::default().matrix(),
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        );
+    }
+
+    #[test]
+    fn rejects_other_matrices() {
+        let mirrored = [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(Mounting::custom(mirrored), None);
+        let scaled = [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]];
+        assert_eq!(Mounting::custom(scaled), None);
+        assert!(Mounting::custom(aligned_matrix(13)).is_some());
+    }
+
+    #[test]
+    fn upright_mounting() {
+        // upright with the USB connector up and the display facing backwards, gravity runs along
+        // the boards x axis.
+        let mounting = Mounting::Aligned(12);
+        let gravity = NedMeasurement::new(-1000.0, 0.0, 0.0);
+        assert_eq!(
+            mounting.apply(gravity),
+            NedMeasurement::new(0.0, 0.0, 1000.0)
+        );
+        // and the top of the display is up.
+        assert_eq!(mounting.display_turns(), 0);
+    }
+
+    #[test]
+    fn display_turns() {
+        assert_eq!(Mounting::default().display_turns(), 0);
+        // flat, turned a quarter at a time.
+        let turns: Vec<u8> = (0..4)
+            .map(|i| Mounting::Aligned(i).display_turns())
+            .collect();
+        assert_eq!(turns, [0, 1, 2, 3]);
+        assert_eq!(angle().display_turns(), 0);
+    }
+
+    #[test]
+    fn turns_frames() {
+        let mut frame = [[0; 5]; 5];
+        // a dot at the top of the display.
+        frame[0][2] = 9;
+        let turned = Mounting::Aligned(1).turn_frame(frame);
+        assert_eq!(turned[2][4], 9);
+        assert_eq!(Mounting::default().turn_frame(frame), frame);
+    }
+
+    #[test]
+    fn steps_through_aligned() {
+        assert_eq!(Mounting::Aligned(0).next(), Mounting::Aligned(1));
+        assert_eq!(Mounting::Aligned(23).next(), Mounting::Aligned(0));
+        assert_eq!(angle().next(), Mounting::Aligned(0));
+    }
+
+    #[test]
+    fn record_round_trip() {
+        for mounting in [Mounting::default(), Mounting::Aligned(17), angle()] {
+            assert_eq!(decode(&encode(&mounting)), Ok(mounting));
+        }
+        assert_eq!(RECORD_LEN % 4, 0);
+        assert_eq!(decode(&[0xFF; RECORD_LEN]), Err(RecordError::BadMagic));
+
+        let mut record = encode(&angle());
+        record[12] ^= 0x01;
+        assert_eq!(decode(&record), Err(RecordError::BadChecksum));
+    }
+}
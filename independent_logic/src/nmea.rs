@@ -0,0 +1,207 @@
+//! NMEA 0183 heading sentences, so boats and rovers can use the compass as a heading sensor.
+//!
+//! Three sentences are supported, all with the `HC` (magnetic compass) talker:
+//!
+//! - `$HCHDM,<heading>,M*hh`: magnetic heading.
+//! - `$HCHDT,<heading>,T*hh`: true heading.
+//! - `$HCHDG,<heading>,<deviation>,<E|W>,<variation>,<E|W>*hh`: magnetic sensor heading with the
+//!   deviation and variation needed to correct it. Unknown fields are left empty.
+//!
+//! Angles are in degrees with one decimal. `hh` is the XOR of every character between `$` and
+//! `*`, in upper case hex.
+
+use core::f32::consts::PI;
+use core::fmt::{self, Write};
+
+use libm::{fabsf, roundf};
+
+use crate::tilt_compensation::{true_heading, Declination, Heading};
+
+/// the longest sentence the standard allows, including the `$` and the line ending.
+pub const MAX_SENTENCE_LEN: usize = 82;
+
+/// which sentence to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SentenceKind {
+    Hdm,
+    Hdg,
+    Hdt,
+}
+
+/// everything the sentences report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadingData {
+    /// the heading relative to magnetic north.
+    pub magnetic: Heading,
+    /// the remaining error of the magnetic heading in radians, positive east. `None` if it isn't
+    /// known, for example because the compass isn't calibrated.
+    pub deviation: Option<f32>,
+    /// the angle from true north to magnetic north.
+    pub variation: Declination,
+}
+
+/// A complete sentence, including the checksum and line ending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sentence {
+    bytes: [u8; MAX_SENTENCE_LEN],
+    len: usize,
+}
+
+impl Sentence {
+    fn new() -> Sentence {
+        Sentence {
+            bytes: [0; MAX_SENTENCE_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole strs are ever copied in, so this can't fail.
+        core::str::from_utf8(self.as_bytes()).unwrap_or("")
+    }
+}
+
+impl Write for Sentence {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.len + s.len() > MAX_SENTENCE_LEN {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// the XOR of all bytes between the `$` and the `*`.
+pub fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// an angle in tenths of a degree, rounded.
+fn tenths(radians: f32) -> i32 {
+    roundf(radians * (1800.0 / PI)) as i32
+}
+
+/// writes a heading from 0.0 to 359.9.
+fn write_heading(out: &mut Sentence, heading: Heading) -> fmt::Result {
+    let tenths = tenths(heading.0).rem_euclid(3600);
+    write!(out, "{}.{}", tenths / 10, tenths % 10)
+}
+
+/// writes the size of an angle and the direction it goes in, as two fields.
+fn write_east_west(out: &mut Sentence, radians: Option<f32>) -> fmt::Result {
+    match radians {
+        Some(radians) => {
+            let size = tenths(fabsf(radians));
+            let direction = if radians < 0.0 && size != 0 { 'W' } else { 'E' };
+            write!(out, "{}.{},{}", size / 10, size % 10, direction)
+        }
+        None => out.write_str(","),
+    }
+}
+
+fn write_body(out: &mut Sentence, kind: SentenceKind, data: &HeadingData) -> fmt::Result {
+    match kind {
+        SentenceKind::Hdm => {
+            out.write_str("HCHDM,")?;
+            write_heading(out, data.magnetic)?;
+            out.write_str(",M")
+        }
+        SentenceKind::Hdt => {
+            out.write_str("HCHDT,")?;
+            write_heading(out, true_heading(data.magnetic, data.variation))?;
+            out.write_str(",T")
+        }
+        SentenceKind::Hdg => {
+            out.write_str("HCHDG,")?;
+            write_heading(out, data.magnetic)?;
+            out.write_str(",")?;
+            write_east_west(out, data.deviation)?;
+            out.write_str(",")?;
+            write_east_west(out, Some(data.variation.0))
+        }
+    }
+}
+
+/// formats one sentence.
+pub fn sentence(kind: SentenceKind, data: &HeadingData) -> Sentence {
+    let mut out = Sentence::new();
+    out.write_str("$").unwrap();
+    // the longest body is about 30 characters, well below the limit.
+    write_body(&mut out, kind, data).unwrap();
+    let checksum = checksum(&out.as_str()[1..]);
+    write!(out, "*{:02X}\r\n", checksum).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(magnetic: f32, deviation: Option<f32>, variation: f32) -> HeadingData {
+        HeadingData {
+            magnetic: Heading(magnetic.to_radians()),
+            deviation: deviation.map(f32::to_radians),
+            variation: Declination::from_degrees(variation),
+        }
+    }
+
+    #[test]
+    fn reference_checksum() {
+        // the HDG example from the gpsd NMEA documentation.
+        assert_eq!(checksum("HCHDG,101.1,,,7.1,W"), 0x3C);
+    }
+
+    #[test]
+    fn hdg() {
+        assert_eq!(
+            sentence(SentenceKind::Hdg, &data(101.1, None, -7.1)).as_str(),
+            "$HCHDG,101.1,,,7.1,W*3C\r\n"
+        );
+        assert_eq!(
+            sentence(SentenceKind::Hdg, &data(238.5, Some(0.0), -7.1)).as_str(),
+            "$HCHDG,238.5,0.0,E,7.1,W*5A\r\n"
+        );
+    }
+
+    #[test]
+    fn hdm_and_hdt() {
+        let data = data(238.5, None, -7.1);
+        assert_eq!(
+            sentence(SentenceKind::Hdm, &data).as_str(),
+            "$HCHDM,238.5,M*25\r\n"
+        );
+        assert_eq!(
+            sentence(SentenceKind::Hdt, &data).as_str(),
+            "$HCHDT,231.4,T*2D\r\n"
+        );
+    }
+
+    #[test]
+    fn headings_wrap() {
+        // headings are -pi to pi internally, but 0 to 359.9 in sentences.
+        assert_eq!(
+            sentence(SentenceKind::Hdt, &data(-0.1, None, 0.0)).as_str(),
+            "$HCHDT,359.9,T*2F\r\n"
+        );
+        // rounding up to 360 wraps to 0.
+        assert_eq!(
+            sentence(SentenceKind::Hdm, &data(-0.01, None, 0.0)).as_str(),
+            "$HCHDM,0.0,M*29\r\n"
+        );
+    }
+
+    #[test]
+    fn fits_the_limit() {
+        let data = data(-179.99, Some(-179.99), -179.99);
+        for kind in [SentenceKind::Hdm, SentenceKind::Hdg, SentenceKind::Hdt] {
+            let sentence = sentence(kind, &data);
+            assert!(sentence.as_str().ends_with("\r\n"));
+            assert!(sentence.as_bytes().len() <= MAX_SENTENCE_LEN);
+        }
+    }
+}
@@ -0,0 +1,827 @@
+//! Binary protocol for configuring the compass over the USB serial port.
+//!
+//! Every message is sent as a frame: the payload followed by its CRC-32 (little endian), COBS
+//! encoded so it contains no zero bytes, with a zero before and after it. The zero in front ends
+//! anything else that was sent on the port, like NMEA sentences, which then fails its checksum
+//! and is dropped.
+//!
+//! Payloads are encoded like postcard: a varint tag saying which message it is, followed by its
+//! fields in order. Unsigned integers are LEB128 varints, signed ones are zigzag encoded first,
+//! floats are 4 bytes little endian and bools are a single 0 or 1. Calibrations are sent as the
+//! same record that is stored in flash.
+//!
+//! The host sends a [`Request`] and the compass answers every one with a [`Response`]. While
+//! streaming, the compass also sends a [`Response::Sample`] for every new reading.
+
+use core::f32::consts::PI;
+
+use crate::calibration::Calibration;
+use crate::calibration_record::{self, crc32, RecordError, RECORD_LEN};
+use crate::frames::{BoardReading, FrameVector};
+use crate::heading_drawing::DisplayStyle;
+use crate::mounting::{Mounting, ALIGNED_COUNT};
+use crate::recording::Entry;
+use crate::sensor::Sample;
+use crate::tilt_compensation::Declination;
+
+/// the longest payload a frame can carry.
+pub const MAX_PAYLOAD_LEN: usize = 96;
+/// the longest frame, including the checksum, the COBS overhead and both delimiters.
+pub const MAX_FRAME_LEN: usize = MAX_PAYLOAD_LEN + 4 + 1 + 2;
+
+const CRC_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// the output buffer is too small for the message.
+    BufferFull,
+    /// a frame was longer than the receive buffer.
+    FrameTooLong,
+    /// a frame isn't valid COBS.
+    BadEncoding,
+    /// a frame failed its CRC, or is too short to have one.
+    BadChecksum,
+    /// the payload ended in the middle of a message.
+    UnexpectedEnd,
+    /// the payload continues after the end of the message.
+    TrailingBytes,
+    /// the payload starts with a message tag this side doesn't know about.
+    UnknownTag(u32),
+    /// a field has a value outside of its range.
+    InvalidValue,
+    /// an uploaded calibration record could not be decoded.
+    BadCalibration(RecordError),
+}
+
+/// The settings that can be changed without reflashing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub tilt_correction: bool,
+    /// at most pi either way.
+    pub declination: Declination,
+    pub display_style: DisplayStyle,
+    /// milliseconds between NMEA sentences, 0 to turn them off.
+    pub nmea_interval: u32,
+    pub mounting: Mounting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    GetConfig,
+    SetConfig(Config),
+    /// starts the calibration game, as if it was picked from the menu.
+    StartCalibration,
+    GetCalibration,
+    /// replaces the calibration and stores it in flash.
+    SetCalibration(Calibration),
+    /// turns streaming raw samples on or off.
+    StreamSamples(bool),
+    /// turns streaming a [recording](crate::recording) on or off.
+    StreamRecording(bool),
+}
+
+/// Why the compass couldn't carry out a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// the request couldn't be decoded.
+    Malformed,
+    /// the calibration couldn't be stored in flash.
+    Storage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response {
+    Ok,
+    Error(ErrorCode),
+    Config(Config),
+    Calibration(Calibration),
+    Sample(Sample),
+    Recording(Entry),
+}
+
+/// Writes the fields of a payload into a buffer.
+pub struct Encoder<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Encoder<'a> {
+        Encoder { buf, pos: 0 }
+    }
+
+    /// how many bytes have been written.
+    pub fn len(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == 0
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> Result<(), ProtocolError> {
+        let end = self.pos + bytes.len();
+        if end > self.buf.len() {
+            return Err(ProtocolError::BufferFull);
+        }
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub fn u8(&mut self, value: u8) -> Result<(), ProtocolError> {
+        self.bytes(&[value])
+    }
+
+    pub fn bool(&mut self, value: bool) -> Result<(), ProtocolError> {
+        self.u8(value as u8)
+    }
+
+    pub fn varint(&mut self, mut value: u32) -> Result<(), ProtocolError> {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.u8(byte);
+            }
+            self.u8(byte | 0x80)?;
+        }
+    }
+
+    pub fn i32(&mut self, value: i32) -> Result<(), ProtocolError> {
+        self.varint(((value << 1) ^ (value >> 31)) as u32)
+    }
+
+    pub fn f32(&mut self, value: f32) -> Result<(), ProtocolError> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// a raw reading, in the boards native axes.
+    pub fn reading(&mut self, reading: &BoardReading) -> Result<(), ProtocolError> {
+        self.i32(reading.x)?;
+        self.i32(reading.y)?;
+        self.i32(reading.z)
+    }
+}
+
+/// Reads the fields of a payload back.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Decoder<'a> {
+        Decoder { buf, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        let end = self.pos + len;
+        let bytes = self
+            .buf
+            .get(self.pos..end)
+            .ok_or(ProtocolError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, ProtocolError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProtocolError::InvalidValue),
+        }
+    }
+
+    pub fn varint(&mut self) -> Result<u32, ProtocolError> {
+        let mut value: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            let bits = (byte & 0x7F) as u32;
+            if shift == 28 && bits > 0x0F {
+                return Err(ProtocolError::InvalidValue);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ProtocolError::InvalidValue)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, ProtocolError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, ProtocolError> {
+        let bytes = self.bytes(4)?;
+        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// a raw reading, in the boards native axes.
+    pub fn reading(&mut self) -> Result<BoardReading, ProtocolError> {
+        Ok(FrameVector::new(self.i32()?, self.i32()?, self.i32()?))
+    }
+
+    /// fails if there is anything left over.
+    pub fn finish(&self) -> Result<(), ProtocolError> {
+        if self.pos == self.buf.len() {
+            Ok(())
+        } else {
+            Err(ProtocolError::TrailingBytes)
+        }
+    }
+}
+
+/// Something that can be sent as the payload of a frame.
+pub trait Message: Sized {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError>;
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError>;
+
+    /// decodes a whole payload.
+    fn from_payload(payload: &[u8]) -> Result<Self, ProtocolError> {
+        let mut decoder = Decoder::new(payload);
+        let message = Self::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(message)
+    }
+}
+
+impl Message for Config {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        encoder.bool(self.tilt_correction)?;
+        encoder.f32(self.declination.0)?;
+        let style = DisplayStyle::ALL
+            .iter()
+            .position(|style| *style == self.display_style)
+            .unwrap_or(0);
+        encoder.varint(style as u32)?;
+        encoder.varint(self.nmea_interval)?;
+        self.mounting.encode(encoder)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        Ok(Config {
+            tilt_correction: decoder.bool()?,
+            // also rejects NaN and infinities, which would end up in every heading.
+            declination: Some(Declination(decoder.f32()?))
+                .filter(|declination| (-PI..=PI).contains(&declination.0))
+                .ok_or(ProtocolError::InvalidValue)?,
+            display_style: *DisplayStyle::ALL
+                .get(decoder.varint()? as usize)
+                .ok_or(ProtocolError::InvalidValue)?,
+            nmea_interval: decoder.varint()?,
+            mounting: Mounting::decode(decoder)?,
+        })
+    }
+}
+
+/// a varint kind, followed by the index of an aligned mounting or the rows of a custom one.
+impl Message for Mounting {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        match self {
+            Mounting::Aligned(index) => {
+                encoder.varint(0)?;
+                encoder.varint(*index as u32)
+            }
+            Mounting::Custom(matrix) => {
+                encoder.varint(1)?;
+                for value in matrix.iter().flatten() {
+                    encoder.f32(*value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        match decoder.varint()? {
+            0 => match decoder.varint()? {
+                index if (index as usize) < ALIGNED_COUNT => Ok(Mounting::Aligned(index as u8)),
+                _ => Err(ProtocolError::InvalidValue),
+            },
+            1 => {
+                let mut matrix = [[0.0; 3]; 3];
+                for value in matrix.iter_mut().flatten() {
+                    *value = decoder.f32()?;
+                }
+                Mounting::custom(matrix).ok_or(ProtocolError::InvalidValue)
+            }
+            _ => Err(ProtocolError::InvalidValue),
+        }
+    }
+}
+
+impl Message for Calibration {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        encoder.bytes(&calibration_record::encode(self))
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        calibration_record::decode(decoder.bytes(RECORD_LEN)?)
+            .map_err(ProtocolError::BadCalibration)
+    }
+}
+
+impl Message for Sample {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        encoder.reading(&self.accel)?;
+        encoder.reading(&self.mag)
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        Ok(Sample {
+            accel: decoder.reading()?,
+            mag: decoder.reading()?,
+        })
+    }
+}
+
+impl Message for Request {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        match self {
+            Request::GetConfig => encoder.varint(0),
+            Request::SetConfig(config) => {
+                encoder.varint(1)?;
+                config.encode(encoder)
+            }
+            Request::StartCalibration => encoder.varint(2),
+            Request::GetCalibration => encoder.varint(3),
+            Request::SetCalibration(calibration) => {
+                encoder.varint(4)?;
+                calibration.encode(encoder)
+            }
+            Request::StreamSamples(enabled) => {
+                encoder.varint(5)?;
+                encoder.bool(*enabled)
+            }
+            Request::StreamRecording(enabled) => {
+                encoder.varint(6)?;
+                encoder.bool(*enabled)
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        Ok(match decoder.varint()? {
+            0 => Request::GetConfig,
+            1 => Request::SetConfig(Config::decode(decoder)?),
+            2 => Request::StartCalibration,
+            3 => Request::GetCalibration,
+            4 => Request::SetCalibration(Calibration::decode(decoder)?),
+            5 => Request::StreamSamples(decoder.bool()?),
+            6 => Request::StreamRecording(decoder.bool()?),
+            tag => return Err(ProtocolError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl Message for ErrorCode {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        encoder.varint(match self {
+            ErrorCode::Malformed => 0,
+            ErrorCode::Storage => 1,
+        })
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        match decoder.varint()? {
+            0 => Ok(ErrorCode::Malformed),
+            1 => Ok(ErrorCode::Storage),
+            _ => Err(ProtocolError::InvalidValue),
+        }
+    }
+}
+
+impl Message for Response {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        match self {
+            Response::Ok => encoder.varint(0),
+            Response::Error(code) => {
+                encoder.varint(1)?;
+                code.encode(encoder)
+            }
+            Response::Config(config) => {
+                encoder.varint(2)?;
+                config.encode(encoder)
+            }
+            Response::Calibration(calibration) => {
+                encoder.varint(3)?;
+                calibration.encode(encoder)
+            }
+            Response::Sample(sample) => {
+                encoder.varint(4)?;
+                sample.encode(encoder)
+            }
+            Response::Recording(entry) => {
+                encoder.varint(5)?;
+                entry.encode(encoder)
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        Ok(match decoder.varint()? {
+            0 => Response::Ok,
+            1 => Response::Error(ErrorCode::decode(decoder)?),
+            2 => Response::Config(Config::decode(decoder)?),
+            3 => Response::Calibration(Calibration::decode(decoder)?),
+            4 => Response::Sample(Sample::decode(decoder)?),
+            5 => Response::Recording(Entry::decode(decoder)?),
+            tag => return Err(ProtocolError::UnknownTag(tag)),
+        })
+    }
+}
+
+/// COBS encodes `input` into `out`, which must have room for one byte more than the input plus
+/// one for every 254 bytes. Returns the encoded length.
+fn cobs_encode(input: impl Iterator<Item = u8>, out: &mut [u8]) -> Result<usize, ProtocolError> {
+    let mut put = |pos: usize, byte: u8| -> Result<(), ProtocolError> {
+        *out.get_mut(pos).ok_or(ProtocolError::BufferFull)? = byte;
+        Ok(())
+    };
+    let (mut code_pos, mut pos, mut code) = (0, 1, 1u8);
+    for byte in input {
+        if byte != 0 {
+            put(pos, byte)?;
+            pos += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            put(code_pos, code)?;
+            code_pos = pos;
+            pos += 1;
+            code = 1;
+        }
+    }
+    put(code_pos, code)?;
+    Ok(pos)
+}
+
+/// COBS decodes a buffer in place, returning the decoded length.
+fn cobs_decode(buf: &mut [u8]) -> Result<usize, ProtocolError> {
+    let (mut read, mut write) = (0, 0);
+    while read < buf.len() {
+        let code = buf[read] as usize;
+        if code == 0 || read + code > buf.len() {
+            return Err(ProtocolError::BadEncoding);
+        }
+        read += 1;
+        for _ in 1..code {
+            buf[write] = buf[read];
+            write += 1;
+            read += 1;
+        }
+        if code < 0xFF && read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    Ok(write)
+}
+
+/// encodes a message as a complete frame, ready to send. Returns the part of `out` it used.
+pub fn encode_frame<'a, M: Message>(
+    message: &M,
+    out: &'a mut [u8],
+) -> Result<&'a [u8], ProtocolError> {
+    let mut payload = [0; MAX_PAYLOAD_LEN];
+    let mut encoder = Encoder::new(&mut payload);
+    message.encode(&mut encoder)?;
+    let len = encoder.len();
+    let payload = &payload[..len];
+
+    let crc = crc32(payload).to_le_bytes();
+    if out.is_empty() {
+        return Err(ProtocolError::BufferFull);
+    }
+    out[0] = 0;
+    let encoded = cobs_encode(payload.iter().copied().chain(crc), &mut out[1..])?;
+    let end = 1 + encoded;
+    *out.get_mut(end).ok_or(ProtocolError::BufferFull)? = 0;
+    Ok(&out[..=end])
+}
+
+/// Collects received bytes into frames. `N` is the longest frame it can take, without the
+/// delimiters.
+#[derive(Debug, Clone)]
+pub struct FrameDecoder<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<const N: usize> Default for FrameDecoder<N> {
+    fn default() -> Self {
+        FrameDecoder::new()
+    }
+}
+
+impl<const N: usize> FrameDecoder<N> {
+    pub fn new() -> FrameDecoder<N> {
+        FrameDecoder {
+            buf: [0; N],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// takes the next received byte. Returns the payload once a frame is complete, or why it
+    /// was dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], ProtocolError>> {
+        if byte != 0 {
+            if self.len < N {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflowed = true;
+            }
+            return None;
+        }
+
+        let (len, overflowed) = (self.len, self.overflowed);
+        self.len = 0;
+        self.overflowed = false;
+        if overflowed {
+            return Some(Err(ProtocolError::FrameTooLong));
+        }
+        // back to back delimiters.
+        if len == 0 {
+            return None;
+        }
+        Some(self.payload(len))
+    }
+
+    fn payload(&mut self, len: usize) -> Result<&[u8], ProtocolError> {
+        let len = cobs_decode(&mut self.buf[..len])?;
+        if len < CRC_LEN {
+            return Err(ProtocolError::BadChecksum);
+        }
+        let (payload, crc) = self.buf[..len].split_at(len - CRC_LEN);
+        if crc32(payload).to_le_bytes() != crc {
+            return Err(ProtocolError::BadChecksum);
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::Reading;
+    use crate::tilt_compensation::Heading;
+
+    fn round_trip<M: Message + PartialEq + core::fmt::Debug>(message: M) {
+        let mut out = [0; MAX_FRAME_LEN];
+        let frame = encode_frame(&message, &mut out).unwrap();
+        dbg!(frame);
+        // only the delimiters are zero.
+        assert_eq!(frame[0], 0);
+        assert_eq!(frame[frame.len() - 1], 0);
+        assert!(!frame[1..frame.len() - 1].contains(&0));
+
+        let mut decoder: FrameDecoder<MAX_FRAME_LEN> = FrameDecoder::new();
+        let mut decoded = None;
+        for byte in frame {
+            if let Some(payload) = decoder.push(*byte) {
+                decoded = Some(M::from_payload(payload.unwrap()).unwrap());
+            }
+        }
+        assert_eq!(decoded, Some(message));
+    }
+
+    fn config() -> Config {
+        Config {
+            tilt_correction: false,
+            declination: Declination::from_degrees(-7.5),
+            display_style: DisplayStyle::Arrow,
+            nmea_interval: 250,
+            mounting: Mounting::Aligned(13),
+        }
+    }
+
+    fn ellipsoid() -> Calibration {
+        Calibration::Ellipsoid {
+            center: [-12000.5, 3000.25, 20000.0],
+            soft_iron: [[1.1, 0.05, -0.02], [0.05, 0.9, 0.01], [-0.02, 0.01, 1.0]],
+            radius: 45000.0,
+            residual: 0.012,
+        }
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf = [0; 16];
+        let mut encoder = Encoder::new(&mut buf);
+        for value in [0, 127, 128, 300, u32::MAX] {
+            encoder.varint(value).unwrap();
+        }
+        let len = encoder.len();
+        assert_eq!(&buf[..4], &[0, 127, 0x80, 0x01]);
+
+        let mut decoder = Decoder::new(&buf[..len]);
+        for value in [0, 127, 128, 300, u32::MAX] {
+            assert_eq!(decoder.varint().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn zigzag() {
+        for value in [0, -1, 1, -64, 64, i32::MIN, i32::MAX] {
+            let mut buf = [0; 5];
+            let mut encoder = Encoder::new(&mut buf);
+            encoder.i32(value).unwrap();
+            let len = encoder.len();
+            assert_eq!(Decoder::new(&buf[..len]).i32().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn cobs() {
+        let input = [0x11, 0x00, 0x00, 0x22, 0x33];
+        let mut out = [0; 8];
+        let len = cobs_encode(input.iter().copied(), &mut out).unwrap();
+        assert_eq!(&out[..len], &[0x02, 0x11, 0x01, 0x03, 0x22, 0x33]);
+        assert_eq!(cobs_decode(&mut out[..len]), Ok(input.len()));
+        assert_eq!(out[..input.len()], input);
+
+        // a run of 254 non-zero bytes needs an extra code byte.
+        let input = [7; 300];
+        let mut out = [0; 310];
+        let len = cobs_encode(input.iter().copied(), &mut out).unwrap();
+        assert_eq!(len, 302);
+        assert!(!out[..len].contains(&0));
+        assert_eq!(cobs_decode(&mut out[..len]), Ok(300));
+        assert_eq!(out[..300], input);
+    }
+
+    #[test]
+    fn requests() {
+        round_trip(Request::GetConfig);
+        round_trip(Request::SetConfig(config()));
+        round_trip(Request::StartCalibration);
+        round_trip(Request::GetCalibration);
+        round_trip(Request::SetCalibration(ellipsoid()));
+        round_trip(Request::SetCalibration(Calibration::Uncalibrated));
+        round_trip(Request::StreamSamples(true));
+        round_trip(Request::StreamRecording(false));
+    }
+
+    #[test]
+    fn declination_must_be_an_angle() {
+        for declination in [PI, -PI] {
+            round_trip(Request::SetConfig(Config {
+                declination: Declination(declination),
+                ..config()
+            }));
+        }
+        for declination in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 10.0, -3.15] {
+            let config = Config {
+                declination: Declination(declination),
+                ..config()
+            };
+            let mut out = [0; MAX_FRAME_LEN];
+            let frame = encode_frame(&Request::SetConfig(config), &mut out).unwrap();
+            let mut decoder: FrameDecoder<MAX_FRAME_LEN> = FrameDecoder::new();
+            let mut decoded = None;
+            for byte in frame {
+                if let Some(payload) = decoder.push(*byte) {
+                    decoded = Some(Request::from_payload(payload.unwrap()));
+                }
+            }
+            assert_eq!(decoded, Some(Err(ProtocolError::InvalidValue)));
+        }
+    }
+
+    #[test]
+    fn responses() {
+        round_trip(Response::Ok);
+        round_trip(Response::Error(ErrorCode::Storage));
+        round_trip(Response::Config(config()));
+        let upright = [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0]];
+        round_trip(Response::Config(Config {
+            mounting: Mounting::custom(upright).unwrap(),
+            ..config()
+        }));
+        round_trip(Response::Calibration(ellipsoid()));
+        round_trip(Response::Sample(Sample {
+            accel: FrameVector::new(-12, 40, -1000),
+            mag: FrameVector::new(20000, -3, -45000),
+        }));
+    }
+
+    #[test]
+    fn largest_messages_fit() {
+        round_trip(Response::Recording(Entry::Calibration(ellipsoid())));
+        let skewed = [[0.0, 0.6, 0.8], [0.0, -0.8, 0.6], [1.0, 0.0, 0.0]];
+        round_trip(Response::Recording(Entry::Mounting(
+            Mounting::custom(skewed).unwrap(),
+        )));
+        let extreme = FrameVector::new(i32::MIN, i32::MAX, i32::MIN);
+        round_trip(Response::Recording(Entry::Reading(Reading {
+            timestamp: u32::MAX,
+            sample: Sample {
+                accel: extreme,
+                mag: extreme,
+            },
+            tilt_correction: true,
+            heading: Heading(-3.0),
+        })));
+    }
+
+    #[test]
+    fn skips_noise_before_a_frame() {
+        let mut out = [0; MAX_FRAME_LEN];
+        let frame = encode_frame(&Request::GetCalibration, &mut out).unwrap();
+        let mut decoder: FrameDecoder<MAX_FRAME_LEN> = FrameDecoder::new();
+        let mut results = Vec::new();
+        for byte in b"$HCHDT,231.4,T*2D\r\n".iter().chain(frame) {
+            if let Some(result) = decoder.push(*byte) {
+                results.push(result.map(Request::from_payload));
+            }
+        }
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert_eq!(results[1], Ok(Ok(Request::GetCalibration)));
+    }
+
+    #[test]
+    fn rejects_corrupt_frames() {
+        let mut out = [0; MAX_FRAME_LEN];
+        let len = encode_frame(&Request::SetConfig(config()), &mut out)
+            .unwrap()
+            .len();
+        // a flipped bit either breaks the COBS structure or the checksum, depending on the byte.
+        for index in 1..len - 1 {
+            let mut frame = out;
+            frame[index] ^= 0x01;
+            if frame[index] == 0 {
+                continue;
+            }
+            let mut decoder: FrameDecoder<MAX_FRAME_LEN> = FrameDecoder::new();
+            let results: Vec<_> = frame[..len]
+                .iter()
+                .filter_map(|byte| decoder.push(*byte).map(|result| result.map(|_| ())))
+                .collect();
+            assert!(
+                results == [Err(ProtocolError::BadChecksum)]
+                    || results == [Err(ProtocolError::BadEncoding)],
+                "{:?}",
+                results
+            );
+        }
+
+        let mut decoder: FrameDecoder<4> = FrameDecoder::new();
+        let results: Vec<_> = [1, 2, 3, 4, 5, 0]
+            .iter()
+            .filter_map(|byte| decoder.push(*byte).map(|result| result.map(|_| ())))
+            .collect();
+        assert_eq!(results, [Err(ProtocolError::FrameTooLong)]);
+    }
+
+    #[test]
+    fn rejects_bad_payloads() {
+        assert_eq!(
+            Request::from_payload(&[9]),
+            Err(ProtocolError::UnknownTag(9))
+        );
+        assert_eq!(
+            Request::from_payload(&[5]),
+            Err(ProtocolError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Request::from_payload(&[5, 1, 0]),
+            Err(ProtocolError::TrailingBytes)
+        );
+        assert_eq!(
+            Request::from_payload(&[5, 2]),
+            Err(ProtocolError::InvalidValue)
+        );
+        // an aligned mounting past the last one, and a custom one that isn't a rotation.
+        let mut payload = [1, 0, 0, 0, 0, 0, 0, 0, 0, 24];
+        assert_eq!(
+            Request::from_payload(&payload),
+            Err(ProtocolError::InvalidValue)
+        );
+        payload[9] = 23;
+        assert!(Request::from_payload(&payload).is_ok());
+        let mut payload = vec![1, 0, 0, 0, 0, 0, 0, 0, 1];
+        payload.extend([2.0_f32; 9].iter().flat_map(|value| value.to_le_bytes()));
+        assert_eq!(
+            Request::from_payload(&payload),
+            Err(ProtocolError::InvalidValue)
+        );
+
+        let mut payload = [0; 1 + RECORD_LEN];
+        payload[0] = 4;
+        assert_eq!(
+            Request::from_payload(&payload),
+            Err(ProtocolError::BadCalibration(RecordError::BadMagic))
+        );
+    }
+}
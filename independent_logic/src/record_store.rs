@@ -0,0 +1,173 @@
+//! Keeps the calibration and the mounting records together in one flash page.
+//!
+//! | offset | size | field                                 |
+//! |--------|------|---------------------------------------|
+//! | 0      | 68   | the [`calibration_record`]            |
+//! | 68     | 48   | the [`mounting`] record               |
+//!
+//! The page can only be erased as a whole, so saving either record writes both back. The flash
+//! is always read and written from the start of the page, as the nrf52 hal indexes the buffer
+//! it is given by the offset into the flash rather than from the start of the buffer.
+
+use crate::calibration::Calibration;
+use crate::calibration_record::{self, RecordError};
+use crate::mounting::{self, Mounting};
+
+/// where the mounting record starts, word aligned.
+const MOUNTING_OFFSET: usize = calibration_record::RECORD_LEN.next_multiple_of(4);
+/// how much of the page the records use, a whole number of words.
+pub const STORED_LEN: usize = MOUNTING_OFFSET + mounting::RECORD_LEN;
+
+/// The flash page the records are kept in.
+pub trait PageStorage {
+    type Error;
+
+    /// reads the first `bytes.len()` bytes of the page.
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// erases the page and writes `bytes` to its start. The length is a multiple of 4.
+    fn rewrite(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Why a record couldn't be loaded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadError<E> {
+    /// the flash couldn't be read.
+    Storage(E),
+    /// nothing was ever saved, or the record is corrupt.
+    Record(RecordError),
+}
+
+impl<E> From<RecordError> for LoadError<E> {
+    fn from(error: RecordError) -> LoadError<E> {
+        LoadError::Record(error)
+    }
+}
+
+pub struct RecordStore<S> {
+    storage: S,
+}
+
+impl<S: PageStorage> RecordStore<S> {
+    pub fn new(storage: S) -> RecordStore<S> {
+        RecordStore { storage }
+    }
+
+    fn read(&mut self) -> Result<[u8; STORED_LEN], S::Error> {
+        let mut page = [0; STORED_LEN];
+        self.storage.read(&mut page)?;
+        Ok(page)
+    }
+
+    /// reads back the stored calibration. Fails if nothing was ever saved or the record is corrupt.
+    pub fn load(&mut self) -> Result<Calibration, LoadError<S::Error>> {
+        let page = self.read().map_err(LoadError::Storage)?;
+        Ok(calibration_record::decode(&page[..MOUNTING_OFFSET])?)
+    }
+
+    pub fn save(&mut self, calibration: &Calibration) -> Result<(), S::Error> {
+        let mut page = self.read()?;
+        page[..calibration_record::RECORD_LEN]
+            .copy_from_slice(&calibration_record::encode(calibration));
+        self.storage.rewrite(&page)
+    }
+
+    /// reads back the stored mounting. Fails if nothing was ever saved or the record is corrupt.
+    pub fn load_mounting(&mut self) -> Result<Mounting, LoadError<S::Error>> {
+        let page = self.read().map_err(LoadError::Storage)?;
+        Ok(mounting::decode(&page[MOUNTING_OFFSET..])?)
+    }
+
+    pub fn save_mounting(&mut self, mounting: &Mounting) -> Result<(), S::Error> {
+        let mut page = self.read()?;
+        page[MOUNTING_OFFSET..].copy_from_slice(&mounting::encode(mounting));
+        self.storage.rewrite(&page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::Vector3;
+
+    /// a 4K page, erased to all ones like the nrf52s flash.
+    struct Page([u8; 4096]);
+
+    impl PageStorage for Page {
+        type Error = ();
+
+        fn read(&mut self, bytes: &mut [u8]) -> Result<(), ()> {
+            bytes.copy_from_slice(&self.0[..bytes.len()]);
+            Ok(())
+        }
+
+        fn rewrite(&mut self, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(bytes.len() % 4, 0);
+            self.0 = [0xFF; 4096];
+            self.0[..bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl PageStorage for Broken {
+        type Error = ();
+
+        fn read(&mut self, _: &mut [u8]) -> Result<(), ()> {
+            Err(())
+        }
+
+        fn rewrite(&mut self, _: &[u8]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    fn calibration() -> Calibration {
+        Calibration::AxisScale {
+            center: Vector3 {
+                x: 2434,
+                y: 5528,
+                z: -40156,
+            },
+            scale: Vector3 {
+                x: 1044,
+                y: 1042,
+                z: 980,
+            },
+            radius: 500,
+        }
+    }
+
+    #[test]
+    fn erased_page_has_no_records() {
+        let mut store = RecordStore::new(Page([0xFF; 4096]));
+        assert_eq!(store.load(), Err(LoadError::Record(RecordError::BadMagic)));
+        assert_eq!(
+            store.load_mounting(),
+            Err(LoadError::Record(RecordError::BadMagic))
+        );
+    }
+
+    #[test]
+    fn saving_one_record_keeps_the_other() {
+        let mut store = RecordStore::new(Page([0xFF; 4096]));
+        store.save_mounting(&Mounting::Aligned(5)).unwrap();
+        store.save(&calibration()).unwrap();
+        assert_eq!(store.load(), Ok(calibration()));
+        assert_eq!(store.load_mounting(), Ok(Mounting::Aligned(5)));
+
+        store.save_mounting(&Mounting::Aligned(9)).unwrap();
+        assert_eq!(store.load(), Ok(calibration()));
+        assert_eq!(store.load_mounting(), Ok(Mounting::Aligned(9)));
+        assert_eq!(&store.storage.0[STORED_LEN..], &[0xFF; 4096 - STORED_LEN]);
+    }
+
+    #[test]
+    fn storage_errors_are_passed_on() {
+        let mut store = RecordStore::new(Broken);
+        assert_eq!(store.load(), Err(LoadError::Storage(())));
+        assert_eq!(store.load_mounting(), Err(LoadError::Storage(())));
+        assert_eq!(store.save(&calibration()), Err(()));
+    }
+}
@@ -0,0 +1,396 @@
+//! Recordings of the raw sensor readings, to reproduce bad headings from the field on the host.
+//!
+//! A recording is a list of entries: a [`Reading`] for every heading the compass calculated, and
+//! the calibration and mounting it used whenever they changed, including refinements by the auto
+//! calibrator.
+//! The compass streams entries as [`Response::Recording`](crate::protocol::Response) frames. On
+//! disk they follow a short header, each prefixed with its length so readers can skip entries a
+//! newer version added:
+//!
+//! ```text
+//! "LCRD" version:u8 (length:varint entry)*
+//! ```
+//!
+//! Entries use the same encoding as the protocol payloads.
+
+use crate::calibration::Calibration;
+use crate::compass::{calibrated_mag, heading_from_samples};
+use crate::mounting::Mounting;
+use crate::protocol::{Decoder, Encoder, Message, ProtocolError, MAX_PAYLOAD_LEN};
+use crate::sensor::Sample;
+use crate::tilt_compensation::{Attitude, Heading};
+
+pub const FILE_MAGIC: [u8; 4] = *b"LCRD";
+pub const FILE_VERSION: u8 = 1;
+/// the magic and the version.
+pub const FILE_HEADER_LEN: usize = 5;
+/// the longest entry in a file, including its length.
+pub const MAX_FILE_ENTRY_LEN: usize = MAX_PAYLOAD_LEN + 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingError {
+    /// the file doesn't start with [`FILE_MAGIC`].
+    NotARecording,
+    /// the file was written by a newer version.
+    UnsupportedVersion(u8),
+    /// an entry couldn't be decoded.
+    BadEntry(ProtocolError),
+}
+
+/// One heading calculation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// milliseconds since the compass started, wrapping.
+    pub timestamp: u32,
+    /// the latest raw reading of each sensor.
+    pub sample: Sample,
+    pub tilt_correction: bool,
+    /// the magnetic heading the compass calculated, to check replays against.
+    pub heading: Heading,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entry {
+    /// the calibration for the readings that follow.
+    Calibration(Calibration),
+    Reading(Reading),
+    /// the mounting for the readings that follow. Recordings without one are of the default
+    /// mounting.
+    Mounting(Mounting),
+}
+
+impl Message for Entry {
+    fn encode(&self, encoder: &mut Encoder) -> Result<(), ProtocolError> {
+        match self {
+            Entry::Calibration(calibration) => {
+                encoder.varint(0)?;
+                calibration.encode(encoder)
+            }
+            Entry::Reading(reading) => {
+                encoder.varint(1)?;
+                encoder.varint(reading.timestamp)?;
+                reading.sample.encode(encoder)?;
+                encoder.bool(reading.tilt_correction)?;
+                encoder.f32(reading.heading.0)
+            }
+            Entry::Mounting(mounting) => {
+                encoder.varint(2)?;
+                mounting.encode(encoder)
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder) -> Result<Self, ProtocolError> {
+        Ok(match decoder.varint()? {
+            0 => Entry::Calibration(Calibration::decode(decoder)?),
+            1 => Entry::Reading(Reading {
+                timestamp: decoder.varint()?,
+                sample: Sample::decode(decoder)?,
+                tilt_correction: decoder.bool()?,
+                heading: Heading(decoder.f32()?),
+            }),
+            2 => Entry::Mounting(Mounting::decode(decoder)?),
+            tag => return Err(ProtocolError::UnknownTag(tag)),
+        })
+    }
+}
+
+/// Turns what the compass does into entries, only repeating the calibration and the mounting
+/// when they changed.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    calibration: Option<Calibration>,
+    mounting: Option<Mounting>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            calibration: None,
+            mounting: None,
+        }
+    }
+
+    /// starts over, so the next reading is preceded by its calibration and mounting again.
+    pub fn reset(&mut self) {
+        self.calibration = None;
+        self.mounting = None;
+    }
+
+    /// the entries for one reading, calculated with `calibration` and `mounting`.
+    pub fn record(
+        &mut self,
+        calibration: &Calibration,
+        mounting: &Mounting,
+        reading: Reading,
+    ) -> impl Iterator<Item = Entry> {
+        let calibration_changed = self.calibration != Some(*calibration);
+        let mounting_changed = self.mounting != Some(*mounting);
+        self.calibration = Some(*calibration);
+        self.mounting = Some(*mounting);
+        calibration_changed
+            .then_some(Entry::Calibration(*calibration))
+            .into_iter()
+            .chain(mounting_changed.then_some(Entry::Mounting(*mounting)))
+            .chain(Some(Entry::Reading(reading)))
+    }
+}
+
+/// Feeds entries back through the heading pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct Player {
+    calibration: Calibration,
+    mounting: Mounting,
+}
+
+impl Player {
+    pub fn new() -> Player {
+        Player {
+            calibration: Calibration::Uncalibrated,
+            mounting: Mounting::default(),
+        }
+    }
+
+    /// the mounting of the readings, as last recorded.
+    pub fn mounting(&self) -> Mounting {
+        self.mounting
+    }
+
+    /// the heading and attitude for a reading, the same bits the compass calculated.
+    pub fn play(&mut self, entry: &Entry) -> Option<(Heading, Attitude)> {
+        match entry {
+            Entry::Calibration(calibration) => {
+                self.calibration = *calibration;
+                None
+            }
+            Entry::Reading(reading) => Some(heading_from_samples(
+                reading.sample.accel,
+                calibrated_mag(reading.sample.mag, &self.calibration),
+                reading.tilt_correction,
+                &self.mounting,
+            )),
+            Entry::Mounting(mounting) => {
+                self.mounting = *mounting;
+                None
+            }
+        }
+    }
+}
+
+pub fn file_header() -> [u8; FILE_HEADER_LEN] {
+    let mut header = [0; FILE_HEADER_LEN];
+    header[..4].copy_from_slice(&FILE_MAGIC);
+    header[4] = FILE_VERSION;
+    header
+}
+
+/// encodes an entry the way it is stored in a file. Returns the part of `out` it used.
+pub fn file_entry<'a>(entry: &Entry, out: &'a mut [u8]) -> Result<&'a [u8], ProtocolError> {
+    let mut payload = [0; MAX_PAYLOAD_LEN];
+    let mut encoder = Encoder::new(&mut payload);
+    entry.encode(&mut encoder)?;
+    let len = encoder.len();
+
+    let mut encoder = Encoder::new(out);
+    encoder.varint(len as u32)?;
+    encoder.bytes(&payload[..len])?;
+    let len = encoder.len();
+    Ok(&out[..len])
+}
+
+/// Reads the entries of a recording file.
+pub struct FileReader<'a> {
+    decoder: Decoder<'a>,
+    /// set after a bad entry, the rest of the file can't be trusted.
+    failed: bool,
+}
+
+impl<'a> FileReader<'a> {
+    pub fn new(file: &'a [u8]) -> Result<FileReader<'a>, RecordingError> {
+        let mut decoder = Decoder::new(file);
+        let header = decoder
+            .bytes(FILE_HEADER_LEN)
+            .map_err(|_| RecordingError::NotARecording)?;
+        if header[..4] != FILE_MAGIC {
+            return Err(RecordingError::NotARecording);
+        }
+        if header[4] > FILE_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header[4]));
+        }
+        Ok(FileReader {
+            decoder,
+            failed: false,
+        })
+    }
+}
+
+impl Iterator for FileReader<'_> {
+    type Item = Result<Entry, RecordingError>;
+
+    /// skips entries this version doesn't know about.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed || self.decoder.finish().is_ok() {
+                return None;
+            }
+            let entry = self
+                .decoder
+                .varint()
+                .and_then(|len| self.decoder.bytes(len as usize))
+                .and_then(Entry::from_payload);
+            match entry {
+                Err(ProtocolError::UnknownTag(_)) => continue,
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(RecordingError::BadEntry(error)));
+                }
+                Ok(entry) => return Some(Ok(entry)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_calibration::AutoCalibrator;
+    use crate::compass::{poll_heading, LatestReadings};
+    use crate::frames::FrameVector;
+    use crate::sensor::ScriptedSensor;
+
+    /// a board turned through every orientation, with a hard iron offset, so the auto calibrator
+    /// has something to refine.
+    fn turning_samples() -> Vec<Sample> {
+        (0..200)
+            .map(|i| {
+                // points spread evenly over the sphere.
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / 200.0;
+                let (r, angle) = ((1.0 - z * z).sqrt(), i as f32 * 2.4);
+                let tilt = (i as f32 * 0.1).sin() * 0.5;
+                Sample {
+                    accel: FrameVector::new((tilt * 300.0) as i32, 0, -1000),
+                    mag: FrameVector::new(
+                        (45000.0 * r * angle.cos()) as i32 + 3000,
+                        (45000.0 * r * angle.sin()) as i32 - 1200,
+                        (45000.0 * z) as i32 + 500,
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// records like the firmwares main loop.
+    fn record(samples: &[Sample], mounting: Mounting) -> Vec<Entry> {
+        let mut sensor = ScriptedSensor::new(samples);
+        let mut latest = LatestReadings::new();
+        let mut calibration = Calibration::Uncalibrated;
+        let mut auto_calibrator = AutoCalibrator::new();
+        let mut recorder = Recorder::new();
+        let mut entries = Vec::new();
+        for timestamp in 0..samples.len() as u32 {
+            let tilt_correction = timestamp % 3 != 0;
+            let (heading, _) = poll_heading(
+                &mut sensor,
+                &mut latest,
+                &mut calibration,
+                &mut auto_calibrator,
+                tilt_correction,
+                &mounting,
+            )
+            .unwrap()
+            .unwrap();
+            let reading = Reading {
+                timestamp: timestamp * 100,
+                sample: latest.sample().unwrap(),
+                tilt_correction,
+                heading,
+            };
+            entries.extend(recorder.record(&calibration, &mounting, reading));
+        }
+        entries
+    }
+
+    #[test]
+    fn only_records_calibration_changes() {
+        let entries = record(&turning_samples(), Mounting::default());
+        let calibrations = entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Calibration(_)))
+            .count();
+        dbg!(calibrations);
+        assert!(calibrations > 1);
+        assert!(calibrations < 20);
+        assert!(matches!(entries[1], Entry::Mounting(_)));
+        assert_eq!(entries.len() - calibrations, 201);
+    }
+
+    #[test]
+    fn replays_bit_for_bit() {
+        let tilted = [[0.8, 0.0, -0.6], [0.0, 1.0, 0.0], [0.6, 0.0, 0.8]];
+        for mounting in [
+            Mounting::default(),
+            Mounting::Aligned(13),
+            Mounting::custom(tilted).unwrap(),
+        ] {
+            let entries = record(&turning_samples(), mounting);
+            let mut file = file_header().to_vec();
+            let mut out = [0; MAX_FILE_ENTRY_LEN];
+            for entry in &entries {
+                file.extend(file_entry(entry, &mut out).unwrap());
+            }
+            dbg!(file.len());
+
+            let mut player = Player::new();
+            let mut readings = 0;
+            for entry in FileReader::new(&file).unwrap() {
+                let entry = entry.unwrap();
+                if let (Some((heading, _)), Entry::Reading(reading)) = (player.play(&entry), entry)
+                {
+                    assert_eq!(heading.0.to_bits(), reading.heading.0.to_bits());
+                    readings += 1;
+                }
+            }
+            assert_eq!(readings, 200);
+        }
+    }
+
+    #[test]
+    fn skips_unknown_entries() {
+        let reading = Entry::Reading(Reading {
+            timestamp: 5,
+            sample: turning_samples()[0],
+            tilt_correction: true,
+            heading: Heading(0.5),
+        });
+        let mut file = file_header().to_vec();
+        // a length prefixed entry with a tag from the future.
+        file.extend([2, 9, 1]);
+        let mut out = [0; MAX_FILE_ENTRY_LEN];
+        file.extend(file_entry(&reading, &mut out).unwrap());
+        let entries: Vec<_> = FileReader::new(&file).unwrap().collect();
+        assert_eq!(entries, [Ok(reading)]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(
+            FileReader::new(b"LCR").err(),
+            Some(RecordingError::NotARecording)
+        );
+        assert_eq!(
+            FileReader::new(b"HDG 1").err(),
+            Some(RecordingError::NotARecording)
+        );
+        assert_eq!(
+            FileReader::new(b"LCRD\x02").err(),
+            Some(RecordingError::UnsupportedVersion(2))
+        );
+        let mut file = file_header().to_vec();
+        file.extend([4, 1]);
+        let entries: Vec<_> = FileReader::new(&file).unwrap().collect();
+        assert_eq!(
+            entries,
+            [Err(RecordingError::BadEntry(ProtocolError::UnexpectedEnd))]
+        );
+    }
+}
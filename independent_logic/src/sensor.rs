@@ -0,0 +1,210 @@
+use core::fmt::Debug;
+
+use crate::calibration::Vector3;
+use crate::frames::{BoardReading, FrameVector};
+
+/// A combined accelerometer and magnetometer. Readings are in the boards native axes, see
+/// [`BoardFrame`](crate::frames::BoardFrame).
+pub trait MotionSensor {
+    type Error: Debug;
+
+    /// true if there is an accelerometer reading that hasn't been read yet.
+    fn accel_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// true if there is a magnetometer reading that hasn't been read yet.
+    fn mag_ready(&mut self) -> Result<bool, Self::Error>;
+
+    /// acceleration, in milli-g.
+    fn accel(&mut self) -> Result<BoardReading, Self::Error>;
+
+    /// magnetic field, in nanotesla.
+    fn mag(&mut self) -> Result<BoardReading, Self::Error>;
+
+    /// true if both sensors have a new reading.
+    fn data_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.mag_ready()? && self.accel_ready()?)
+    }
+}
+
+/// A sensor that can be set up again from scratch, to get it going after it stopped answering.
+/// See [`Supervisor`](crate::fault::Supervisor).
+pub trait Restart: MotionSensor + Sized {
+    /// whatever the sensor is connected through, usually an i2c bus.
+    type Bus;
+
+    /// configures the sensor. Gives the bus back if that fails, so it can be tried again.
+    fn start(bus: Self::Bus) -> Result<Self, (Self::Error, Self::Bus)>;
+
+    /// gives up on the sensor, without talking to it.
+    fn stop(self) -> Self::Bus;
+}
+
+/// Maps a sensor chips own axes onto the boards native axes, as the chips fitted to each board
+/// revision are mounted differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisMap {
+    /// for each board axis, the chip axis it is read from.
+    source: [usize; 3],
+    /// for each board axis, -1 if the chip axis points the other way.
+    sign: [i32; 3],
+}
+
+impl AxisMap {
+    pub const IDENTITY: AxisMap = AxisMap::new([0, 1, 2], [1, 1, 1]);
+
+    /// `source` must be a permutation of the axes 0, 1 and 2, and `sign` must be 1 or -1.
+    pub const fn new(source: [usize; 3], sign: [i32; 3]) -> AxisMap {
+        assert!(source[0] != source[1] && source[1] != source[2] && source[0] != source[2]);
+        assert!(source[0] < 3 && source[1] < 3 && source[2] < 3);
+        assert!(sign[0].abs() == 1 && sign[1].abs() == 1 && sign[2].abs() == 1);
+        AxisMap { source, sign }
+    }
+
+    /// takes a reading in the chips own axes into the boards.
+    pub fn apply(&self, reading: Vector3) -> BoardReading {
+        let axes = [reading.x, reading.y, reading.z];
+        FrameVector::new(
+            axes[self.source[0]] * self.sign[0],
+            axes[self.source[1]] * self.sign[1],
+            axes[self.source[2]] * self.sign[2],
+        )
+    }
+}
+
+/// the micro:bit v1s MMA8653FC accelerometer. microbit-dal reads it as `(y, -x, -z)` in north
+/// east down.
+pub const MMA8653_AXES: AxisMap = AxisMap::new([0, 1, 2], [1, -1, 1]);
+
+/// the micro:bit v1s MAG3110 magnetometer. microbit-dal reads it as `(-y, x, -z)` in north east
+/// down.
+pub const MAG3110_AXES: AxisMap = AxisMap::new([0, 1, 2], [-1, 1, 1]);
+
+/// one reading of both sensors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub accel: BoardReading,
+    pub mag: BoardReading,
+}
+
+/// Returned by [`ScriptedSensor`] once all of its samples have been read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptExhausted;
+
+/// A [`MotionSensor`] that plays back a fixed list of samples, for tests and simulation.
+/// Each sensor advances independently, so the accelerometer can be read more often than the
+/// magnetometer, like the calibration does.
+#[derive(Debug, Clone)]
+pub struct ScriptedSensor<'a> {
+    samples: &'a [Sample],
+    accel_index: usize,
+    mag_index: usize,
+}
+
+impl<'a> ScriptedSensor<'a> {
+    pub fn new(samples: &'a [Sample]) -> ScriptedSensor<'a> {
+        ScriptedSensor {
+            samples,
+            accel_index: 0,
+            mag_index: 0,
+        }
+    }
+
+    /// the number of samples that have not been fully read yet.
+    pub fn remaining(&self) -> usize {
+        self.samples.len() - self.accel_index.min(self.mag_index)
+    }
+}
+
+impl MotionSensor for ScriptedSensor<'_> {
+    type Error = ScriptExhausted;
+
+    fn accel_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.accel_index < self.samples.len() {
+            Ok(true)
+        } else {
+            Err(ScriptExhausted)
+        }
+    }
+
+    fn mag_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.mag_index < self.samples.len() {
+            Ok(true)
+        } else {
+            Err(ScriptExhausted)
+        }
+    }
+
+    fn accel(&mut self) -> Result<BoardReading, Self::Error> {
+        let sample = self.samples.get(self.accel_index).ok_or(ScriptExhausted)?;
+        self.accel_index += 1;
+        Ok(sample.accel)
+    }
+
+    fn mag(&mut self) -> Result<BoardReading, Self::Error> {
+        let sample = self.samples.get(self.mag_index).ok_or(ScriptExhausted)?;
+        self.mag_index += 1;
+        Ok(sample.mag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilt_compensation::NedMeasurement;
+
+    fn sample(i: i32) -> Sample {
+        Sample {
+            accel: FrameVector::new(i, 0, 0),
+            mag: FrameVector::new(0, i, 0),
+        }
+    }
+
+    #[test]
+    fn plays_back_in_order() {
+        let samples = [sample(1), sample(2)];
+        let mut sensor = ScriptedSensor::new(&samples);
+        assert_eq!(sensor.data_ready(), Ok(true));
+        assert_eq!(sensor.accel(), Ok(samples[0].accel));
+        assert_eq!(sensor.mag(), Ok(samples[0].mag));
+        assert_eq!(sensor.remaining(), 1);
+        assert_eq!(sensor.accel(), Ok(samples[1].accel));
+        assert_eq!(sensor.mag(), Ok(samples[1].mag));
+        assert_eq!(sensor.remaining(), 0);
+        assert_eq!(sensor.data_ready(), Err(ScriptExhausted));
+        assert_eq!(sensor.accel(), Err(ScriptExhausted));
+    }
+
+    #[test]
+    fn sensors_advance_independently() {
+        let samples = [sample(1), sample(2), sample(3)];
+        let mut sensor = ScriptedSensor::new(&samples);
+        assert_eq!(sensor.accel(), Ok(samples[0].accel));
+        assert_eq!(sensor.accel(), Ok(samples[1].accel));
+        assert_eq!(sensor.mag(), Ok(samples[0].mag));
+        assert_eq!(sensor.remaining(), 3 - 1);
+        assert_eq!(sensor.accel(), Ok(samples[2].accel));
+        assert_eq!(sensor.accel_ready(), Err(ScriptExhausted));
+        assert_eq!(sensor.mag_ready(), Ok(true));
+    }
+
+    #[test]
+    fn axis_map() {
+        let reading = Vector3 { x: 1, y: 2, z: 3 };
+        assert_eq!(AxisMap::IDENTITY.apply(reading), FrameVector::new(1, 2, 3));
+        assert_eq!(
+            AxisMap::new([2, 0, 1], [1, -1, 1]).apply(reading),
+            FrameVector::new(3, -1, 2)
+        );
+    }
+
+    #[test]
+    fn v1_axes_match_dal() {
+        let (x, y, z) = (100, -250, 980);
+        let reading = Vector3 { x, y, z };
+        let accel = MMA8653_AXES.apply(reading).to_ned().to_f32();
+        let ned = |x: i32, y: i32, z: i32| NedMeasurement::new(x as f32, y as f32, z as f32);
+        assert_eq!(dbg!(accel), ned(y, -x, -z));
+        let mag = MAG3110_AXES.apply(reading).to_ned().to_f32();
+        assert_eq!(dbg!(mag), ned(-y, x, -z));
+    }
+}
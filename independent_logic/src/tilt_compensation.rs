@@ -1,25 +1,32 @@
-use libm::{atan2f, atanf, cosf, sinf};
+use core::f32::consts::PI;
 
-#[derive(Debug)]
+use libm::{asinf, atan2f, cosf, fabsf, sinf, sqrtf};
+
+use crate::frames::{FrameVector, Ned};
+use crate::wmm::{self, Location};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Attitude {
     pub pitch: f32,
     pub roll: f32,
 }
 
-#[derive(Debug)]
-pub struct NedMeasurement {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-}
+/// a reading in the axes tilt compensation works in, see [`Ned`].
+pub type NedMeasurement = FrameVector<Ned>;
 
 ///theta=0 at north, pi/-pi at south, pi/2 at east, and -pi/2 at west
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Heading(pub f32);
 
 pub fn calc_attitude(measurement: &NedMeasurement) -> Attitude {
     //based off of: https://www.nxp.com/docs/en/application-note/AN4248.pdf
+    //y * sin(roll) + z * cos(roll) is the length of (y, z), which atan2 can take as is, so pitch
+    //stays well-defined when the board is held vertically and that length goes to zero.
     let roll = atan2f(measurement.y, measurement.z);
-    let pitch = atanf(-measurement.x / (measurement.y * sinf(roll) + measurement.z * cosf(roll)));
+    let pitch = atan2f(
+        -measurement.x,
+        sqrtf(measurement.y * measurement.y + measurement.z * measurement.z),
+    );
     Attitude { pitch, roll }
 }
 
@@ -36,11 +43,7 @@ pub fn calc_tilt_calibrated_measurement(
         + mag_measurement.y * sinf(attitde.pitch) * sinf(attitde.roll)
         + mag_measurement.z * sinf(attitde.pitch) * cosf(attitde.roll);
 
-    NedMeasurement {
-        x: corrected_mag_x,
-        y: corrected_mag_y,
-        z: 0.0,
-    }
+    NedMeasurement::new(corrected_mag_x, corrected_mag_y, 0.0)
 }
 
 //0 is the top sector and positive is clockwise, negative is counterclockwise.
@@ -48,4 +51,427 @@ pub fn heading_from_measurement(measurement: NedMeasurement) -> Heading {
     Heading(atan2f(-measurement.y, measurement.x))
 }
 
-//I have no freaking clue how to test this...
+/// the pitch, in radians, from which [`Orientation::heading`] starts to follow the back of the
+/// board instead of its front.
+pub const STEEP_PITCH: f32 = 60.0 * PI / 180.0;
+/// the pitch from which it only follows the back of the board.
+pub const VERTICAL_PITCH: f32 = 80.0 * PI / 180.0;
+
+/// A unit quaternion for a rotation, `w` being the real part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// the rotation with the given matrix, using the numerically stable branch of Shepperd's
+    /// method.
+    pub fn from_rotation_matrix(m: &[[f32; 3]; 3]) -> Quaternion {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * sqrtf(1.0 + trace);
+            Quaternion {
+                w: s / 4.0,
+                x: (m[2][1] - m[1][2]) / s,
+                y: (m[0][2] - m[2][0]) / s,
+                z: (m[1][0] - m[0][1]) / s,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * sqrtf(1.0 + m[0][0] - m[1][1] - m[2][2]);
+            Quaternion {
+                w: (m[2][1] - m[1][2]) / s,
+                x: s / 4.0,
+                y: (m[0][1] + m[1][0]) / s,
+                z: (m[0][2] + m[2][0]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * sqrtf(1.0 + m[1][1] - m[0][0] - m[2][2]);
+            Quaternion {
+                w: (m[0][2] - m[2][0]) / s,
+                x: (m[0][1] + m[1][0]) / s,
+                y: s / 4.0,
+                z: (m[1][2] + m[2][1]) / s,
+            }
+        } else {
+            let s = 2.0 * sqrtf(1.0 + m[2][2] - m[0][0] - m[1][1]);
+            Quaternion {
+                w: (m[1][0] - m[0][1]) / s,
+                x: (m[0][2] + m[2][0]) / s,
+                y: (m[1][2] + m[2][1]) / s,
+                z: s / 4.0,
+            }
+        };
+        q.normalized()
+    }
+
+    pub fn norm(&self) -> f32 {
+        sqrtf(self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z)
+    }
+
+    pub fn normalized(&self) -> Quaternion {
+        let norm = self.norm();
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// the rotation `other` followed by `self`.
+    pub fn mul(&self, other: &Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+
+    pub fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        let v = Quaternion {
+            w: 0.0,
+            x: v[0],
+            y: v[1],
+            z: v[2],
+        };
+        let out = self.mul(&v).mul(&self.conjugate());
+        [out.x, out.y, out.z]
+    }
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let len = sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+    if len < 1e-6 || !len.is_finite() {
+        return None;
+    }
+    Some([v[0] / len, v[1] / len, v[2] / len])
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// wraps an angle into -pi..=pi.
+fn wrap(angle: f32) -> f32 {
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+/// The orientation of the board, as the rotation from its [`Ned`] axes into
+/// north, east and down, with north being magnetic north.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orientation(pub Quaternion);
+
+impl Orientation {
+    /// the orientation from the directions of gravity and the magnetic field, with the TRIAD
+    /// method: down is taken straight from the accelerometer, and east is perpendicular to down
+    /// and the field, so the dip of the field doesn't matter. Returns `None` if either reading is
+    /// zero, or if they point the same way.
+    pub fn from_measurements(accel: &NedMeasurement, mag: &NedMeasurement) -> Option<Orientation> {
+        let down = normalize([accel.x, accel.y, accel.z])?;
+        let east = normalize(cross(down, [mag.x, mag.y, mag.z]))?;
+        let north = cross(east, down);
+        // the rows are the earth axes in board coordinates, so it takes board vectors to the earth.
+        Some(Orientation(Quaternion::from_rotation_matrix(&[
+            north, east, down,
+        ])))
+    }
+
+    /// a board axis, in north, east and down.
+    fn earth_axis(&self, axis: [f32; 3]) -> [f32; 3] {
+        self.0.rotate(axis)
+    }
+
+    /// the yaw, pitch and roll, as in the aerospace convention. Yaw can't be told apart from roll
+    /// when the board is vertical, use [`Orientation::heading`] for a heading.
+    pub fn euler_angles(&self) -> (f32, Attitude) {
+        let Quaternion { w, x, y, z } = self.0;
+        let yaw = atan2f(2.0 * (x * y + w * z), 1.0 - 2.0 * (y * y + z * z));
+        let pitch = asinf((2.0 * (w * y - x * z)).clamp(-1.0, 1.0));
+        let roll = atan2f(2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y));
+        (yaw, Attitude { pitch, roll })
+    }
+
+    pub fn attitude(&self) -> Attitude {
+        self.euler_angles().1
+    }
+
+    /// The direction the board faces. Up to [`STEEP_PITCH`] that is where the top of the display
+    /// points, like the yaw. Above [`VERTICAL_PITCH`] it is where the top of the display would
+    /// point if the board was tipped flat the shortest way, which is also where the back of the
+    /// board faces when it is held upright. In between the two are blended, so the heading stays
+    /// smooth and well-defined in every orientation except upside down and vertical at once.
+    pub fn heading(&self) -> Heading {
+        let forward = self.earth_axis([1.0, 0.0, 0.0]);
+        // headings are clockwise when seen from above the display, the opposite way to yaw.
+        let yaw_heading = -atan2f(forward[1], forward[0]);
+        let pitch = asinf((-forward[2]).clamp(-1.0, 1.0));
+        let blend = ((fabsf(pitch) - STEEP_PITCH) / (VERTICAL_PITCH - STEEP_PITCH)).clamp(0.0, 1.0);
+        if blend == 0.0 {
+            return Heading(yaw_heading);
+        }
+
+        // the shortest rotation that takes the boards down axis to the earths down axis.
+        let board_down = self.earth_axis([0.0, 0.0, 1.0]);
+        let tip = Quaternion {
+            w: 1.0 + board_down[2],
+            x: board_down[1],
+            y: -board_down[0],
+            z: 0.0,
+        };
+        let Some(tip) = (tip.norm() > 1e-6).then(|| tip.normalized()) else {
+            return Heading(yaw_heading);
+        };
+        let tipped = tip.rotate(forward);
+        let tipped_heading = -atan2f(tipped[1], tipped[0]);
+
+        // smoothstep, so the heading doesn't kink at either end.
+        let blend = blend * blend * (3.0 - 2.0 * blend);
+        Heading(wrap(
+            yaw_heading + blend * wrap(tipped_heading - yaw_heading),
+        ))
+    }
+}
+
+/// the angle from true north to magnetic north in radians, positive when magnetic north is east
+/// of true north. The default of 0 leaves headings magnetic.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Declination(pub f32);
+
+impl Declination {
+    pub fn from_degrees(degrees: f32) -> Declination {
+        Declination(degrees.to_radians())
+    }
+
+    /// looks the declination up in the world magnetic model. `year` is a decimal year, see
+    /// [`wmm::decimal_year`], and has to be within the years the model is valid for.
+    pub fn from_model(location: &Location, year: f32) -> Result<Declination, wmm::OutOfRange> {
+        wmm::declination(location, year).map(Declination)
+    }
+}
+
+/// turns a magnetic heading into a true one, keeping it in -pi..=pi.
+pub fn true_heading(heading: Heading, declination: Declination) -> Heading {
+    Heading(wrap(heading.0 + declination.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the readings of a board with the given heading, pitch and roll, rotated like the
+    /// simulators synthetic readings.
+    fn readings(heading: f32, pitch: f32, roll: f32) -> (NedMeasurement, NedMeasurement) {
+        let yaw = -heading;
+        let rotate = |v: [f32; 3]| {
+            let v = [
+                cosf(yaw) * v[0] + sinf(yaw) * v[1],
+                -sinf(yaw) * v[0] + cosf(yaw) * v[1],
+                v[2],
+            ];
+            let v = [
+                cosf(pitch) * v[0] - sinf(pitch) * v[2],
+                v[1],
+                sinf(pitch) * v[0] + cosf(pitch) * v[2],
+            ];
+            NedMeasurement::new(
+                v[0],
+                cosf(roll) * v[1] + sinf(roll) * v[2],
+                -sinf(roll) * v[1] + cosf(roll) * v[2],
+            )
+        };
+        (rotate([0.0, 0.0, 1000.0]), rotate([20000.0, 0.0, 45000.0]))
+    }
+
+    fn orientation(heading: f32, pitch: f32, roll: f32) -> Orientation {
+        let (accel, mag) = readings(heading, pitch, roll);
+        Orientation::from_measurements(&accel, &mag).unwrap()
+    }
+
+    fn angle_difference(a: f32, b: f32) -> f32 {
+        fabsf(wrap(a - b))
+    }
+
+    fn degrees(range: core::ops::RangeInclusive<i32>, step: usize) -> Vec<f32> {
+        range
+            .step_by(step)
+            .map(|d| (d as f32).to_radians())
+            .collect()
+    }
+
+    #[test]
+    fn quaternion_rotations() {
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(
+            Quaternion::from_rotation_matrix(&identity),
+            Quaternion::IDENTITY
+        );
+
+        // a quarter turn about z takes x to y, through every branch of the conversion.
+        for matrix in [
+            [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]],
+            [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+            [[-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]],
+        ] {
+            let q = Quaternion::from_rotation_matrix(&matrix);
+            for (axis, column) in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+                .iter()
+                .zip(0..3)
+            {
+                let rotated = q.rotate(*axis);
+                for (row, value) in rotated.iter().enumerate() {
+                    assert!((value - matrix[row][column]).abs() < 1e-6, "{:?}", matrix);
+                }
+            }
+            let back = q.mul(&q.conjugate());
+            assert!((back.w - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn matches_euler_angles() {
+        for heading in degrees(-180..=165, 15) {
+            for (pitch, roll) in [(0.0, 0.0), (0.3, 0.0), (0.0, -0.5), (-0.4, 0.6), (0.8, 0.9)] {
+                let orientation = orientation(heading, pitch, roll);
+                let (yaw, attitude) = orientation.euler_angles();
+                assert!(
+                    angle_difference(-yaw, heading) < 1e-4,
+                    "{} {:?}",
+                    heading,
+                    attitude
+                );
+                assert!((attitude.pitch - pitch).abs() < 1e-4);
+                assert!((attitude.roll - roll).abs() < 1e-4);
+                assert!(angle_difference(orientation.heading().0, heading) < 1e-4);
+
+                // the same attitude as from the accelerometer alone.
+                let (accel, _) = readings(heading, pitch, roll);
+                let from_accel = calc_attitude(&accel);
+                assert!((from_accel.pitch - pitch).abs() < 1e-4);
+                assert!((from_accel.roll - roll).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn heading_through_vertical() {
+        // tipping the front up and down through vertical doesn't change where the board faces.
+        for heading in degrees(-180..=165, 15) {
+            for pitch in degrees(-90..=90, 1) {
+                let found = orientation(heading, pitch, 0.0).heading().0;
+                assert!(
+                    angle_difference(found, heading) < 1e-3,
+                    "heading {} pitch {}: {}",
+                    heading.to_degrees(),
+                    pitch.to_degrees(),
+                    found.to_degrees()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn heading_stays_smooth() {
+        // rolled as well, the heading moves gradually from following the front to following the
+        // back, and never jumps.
+        for heading in degrees(-180..=150, 30) {
+            for roll in degrees(-60..=60, 20) {
+                let mut last = None;
+                for tenths in -900..=900 {
+                    let pitch = (tenths as f32 / 10.0).to_radians();
+                    let found = orientation(heading, pitch, roll).heading().0;
+                    assert!(found.is_finite());
+                    if let Some(last) = last {
+                        assert!(
+                            angle_difference(found, last) < 0.6_f32.to_radians(),
+                            "heading {} roll {} pitch {}",
+                            heading.to_degrees(),
+                            roll.to_degrees(),
+                            pitch.to_degrees()
+                        );
+                    }
+                    last = Some(found);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vertical_attitude_is_finite() {
+        for pitch in [PI / 2.0, -PI / 2.0] {
+            let (accel, _) = readings(0.3, pitch, 0.0);
+            let attitude = calc_attitude(&accel);
+            assert!((attitude.pitch - pitch).abs() < 1e-3, "{:?}", attitude);
+            assert!(attitude.roll.is_finite());
+        }
+    }
+
+    #[test]
+    fn degenerate_readings() {
+        let zero = NedMeasurement::new(0.0, 0.0, 0.0);
+        let down = NedMeasurement::new(0.0, 0.0, 1000.0);
+        assert_eq!(Orientation::from_measurements(&zero, &down), None);
+        assert_eq!(Orientation::from_measurements(&down, &zero), None);
+        // a field straight down, as at the magnetic poles.
+        assert_eq!(Orientation::from_measurements(&down, &down), None);
+    }
+
+    #[test]
+    fn true_heading_adds_declination() {
+        let heading = true_heading(Heading(0.5), Declination::from_degrees(10.0));
+        assert!((heading.0 - (0.5 + 10.0_f32.to_radians())).abs() < 1e-6);
+        let heading = true_heading(Heading(0.5), Declination::default());
+        assert_eq!(heading.0, 0.5);
+    }
+
+    #[test]
+    fn true_heading_wraps() {
+        let heading = true_heading(Heading(PI - 0.1), Declination(0.3));
+        assert!((heading.0 - (-PI + 0.2)).abs() < 1e-6);
+        let heading = true_heading(Heading(-PI + 0.1), Declination(-0.3));
+        assert!((heading.0 - (PI - 0.2)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn declination_from_model() {
+        let location = Location {
+            latitude: -80.0,
+            longitude: 240.0,
+            altitude: 0.0,
+        };
+        let declination = Declination::from_model(&location, 2025.0).unwrap();
+        // from the WMM test values in `wmm::tests`.
+        assert!((declination.0.to_degrees() - 68.78).abs() <= 0.01);
+        assert!(Declination::from_model(&location, 2031.0).is_err());
+    }
+}
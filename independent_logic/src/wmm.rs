@@ -0,0 +1,352 @@
+//! The World Magnetic Model, used to look up the magnetic declination for a location and date.
+//!
+//! Evaluates the WMM2025 spherical harmonic model to degree and order 12, following the WMM
+//! technical report: <https://www.ncei.noaa.gov/products/world-magnetic-model>
+//! The model is valid from 2025.0 to 2030.0, and dates outside of that are rejected rather than
+//! extrapolated. The coefficients have to be replaced with the next model's when it comes out.
+
+use libm::{asinf, atan2f, cosf, sinf, sqrtf};
+
+pub const EPOCH: f32 = 2025.0;
+/// the end of the five years the model is valid for.
+pub const VALID_UNTIL: f32 = EPOCH + 5.0;
+pub const MAX_DEGREE: usize = 12;
+
+/// WGS 84 semi-major axis in km.
+const WGS84_A: f32 = 6378.137;
+/// WGS 84 flattening.
+const WGS84_F: f32 = 1.0 / 298.257_23;
+/// geomagnetic reference radius in km.
+const REFERENCE_RADIUS: f32 = 6371.2;
+
+/// `g`, `h`, and their yearly change in nT, for n from 1 to 12 and m from 0 to n.
+#[rustfmt::skip]
+const COEFFICIENTS: [[f32; 4]; 90] = [
+    [-29351.8, 0.0, 12.0, 0.0],
+    [-1410.8, 4545.4, 9.7, -21.5],
+    [-2556.6, 0.0, -11.6, 0.0],
+    [2951.1, -3133.6, -5.2, -27.7],
+    [1649.3, -815.1, -8.0, -12.1],
+    [1361.0, 0.0, -1.3, 0.0],
+    [-2404.1, -56.6, -4.2, 4.0],
+    [1243.8, 237.5, 0.4, -0.3],
+    [453.6, -549.5, -15.6, -4.1],
+    [895.0, 0.0, -1.6, 0.0],
+    [799.5, 278.6, -2.4, -1.1],
+    [55.7, -133.9, -6.0, 4.1],
+    [-281.1, 212.0, 5.6, 1.6],
+    [12.1, -375.6, -7.0, -4.4],
+    [-233.2, 0.0, 0.6, 0.0],
+    [368.9, 45.4, 1.4, -0.5],
+    [187.2, 220.2, 0.0, 2.2],
+    [-138.7, -122.9, 0.6, 0.4],
+    [-142.0, 43.0, 2.2, 1.7],
+    [20.9, 106.1, 0.9, 1.9],
+    [64.4, 0.0, -0.2, 0.0],
+    [63.8, -18.4, -0.4, 0.3],
+    [76.9, 16.8, 0.9, -1.6],
+    [-115.7, 48.8, 1.2, -0.4],
+    [-40.9, -59.8, -0.9, 0.9],
+    [14.9, 10.9, 0.3, 0.7],
+    [-60.7, 72.7, 0.9, 0.9],
+    [79.5, 0.0, -0.0, 0.0],
+    [-77.0, -48.9, -0.1, 0.6],
+    [-8.8, -14.4, -0.1, 0.5],
+    [59.3, -1.0, 0.5, -0.8],
+    [15.8, 23.4, -0.1, 0.0],
+    [2.5, -7.4, -0.8, -1.0],
+    [-11.1, -25.1, -0.8, 0.6],
+    [14.2, -2.3, 0.8, -0.2],
+    [23.2, 0.0, -0.1, 0.0],
+    [10.8, 7.1, 0.2, -0.2],
+    [-17.5, -12.6, 0.0, 0.5],
+    [2.0, 11.4, 0.5, -0.4],
+    [-21.7, -9.7, -0.1, 0.4],
+    [16.9, 12.7, 0.3, -0.5],
+    [15.0, 0.7, 0.2, -0.6],
+    [-16.8, -5.2, -0.0, 0.3],
+    [0.9, 3.9, 0.2, 0.2],
+    [4.6, 0.0, -0.0, 0.0],
+    [7.8, -24.8, -0.1, -0.3],
+    [3.0, 12.2, 0.1, 0.3],
+    [-0.2, 8.3, 0.3, -0.3],
+    [-2.5, -3.4, -0.3, 0.3],
+    [-13.1, -5.3, 0.0, 0.2],
+    [2.4, 7.2, 0.3, -0.1],
+    [8.6, -0.6, -0.1, -0.2],
+    [-8.7, 0.8, 0.1, 0.4],
+    [-12.9, 10.0, -0.1, 0.1],
+    [-1.3, 0.0, 0.1, 0.0],
+    [-6.4, 3.3, 0.0, 0.0],
+    [0.2, 0.0, 0.1, -0.0],
+    [2.0, 2.4, 0.1, -0.2],
+    [-1.0, 5.3, -0.0, 0.1],
+    [-0.6, -9.1, -0.3, -0.1],
+    [-0.9, 0.4, 0.0, 0.1],
+    [1.5, -4.2, -0.1, 0.0],
+    [0.9, -3.8, -0.1, -0.1],
+    [-2.7, 0.9, -0.0, 0.2],
+    [-3.9, -9.1, -0.0, -0.0],
+    [2.9, 0.0, 0.0, 0.0],
+    [-1.5, 0.0, -0.0, -0.0],
+    [-2.5, 2.9, 0.0, 0.1],
+    [2.4, -0.6, 0.0, -0.0],
+    [-0.6, 0.2, 0.0, 0.1],
+    [-0.1, 0.5, -0.1, -0.0],
+    [-0.6, -0.3, 0.0, -0.0],
+    [-0.1, -1.2, -0.0, 0.1],
+    [1.1, -1.7, -0.1, -0.0],
+    [-1.0, -2.9, -0.1, 0.0],
+    [-0.2, -1.8, -0.1, 0.0],
+    [2.6, -2.3, -0.1, 0.0],
+    [-2.0, 0.0, 0.0, 0.0],
+    [-0.2, -1.3, 0.0, -0.0],
+    [0.3, 0.7, -0.0, 0.0],
+    [1.2, 1.0, -0.0, -0.1],
+    [-1.3, -1.4, -0.0, 0.1],
+    [0.6, -0.0, -0.0, -0.0],
+    [0.6, 0.6, 0.1, -0.0],
+    [0.5, -0.1, -0.0, -0.0],
+    [-0.1, 0.8, 0.0, 0.0],
+    [-0.4, 0.1, 0.0, -0.0],
+    [-0.2, -1.0, -0.1, -0.0],
+    [-1.3, 0.1, -0.0, 0.0],
+    [-0.7, 0.2, -0.1, -0.1],
+];
+
+/// A point on the WGS 84 ellipsoid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    /// degrees, positive north.
+    pub latitude: f32,
+    /// degrees, positive east.
+    pub longitude: f32,
+    /// height above the ellipsoid in km.
+    pub altitude: f32,
+}
+
+/// the main field in nT, with x north, y east and z down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagneticField {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl MagneticField {
+    /// the angle from true north to magnetic north in radians, positive when magnetic north is
+    /// east of true north.
+    pub fn declination(&self) -> f32 {
+        atan2f(self.y, self.x)
+    }
+
+    /// the angle of the field below the horizontal in radians.
+    pub fn inclination(&self) -> f32 {
+        atan2f(self.z, sqrtf(self.x * self.x + self.y * self.y))
+    }
+}
+
+/// Returned for a decimal year outside of [`EPOCH`]..[`VALID_UNTIL`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfRange(pub f32);
+
+/// the date as a decimal year, like the model expects.
+pub fn decimal_year(year: u16, month: u8, day: u8) -> f32 {
+    const DAYS_BEFORE_MONTH: [u16; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let mut day_of_year =
+        DAYS_BEFORE_MONTH[(month.clamp(1, 12) - 1) as usize] + (day as u16).saturating_sub(1);
+    if leap && month > 2 {
+        day_of_year += 1;
+    }
+    let days_in_year = if leap { 366.0 } else { 365.0 };
+    year as f32 + day_of_year as f32 / days_in_year
+}
+
+/// the declination in radians at a location and decimal year, see [`MagneticField::declination`].
+pub fn declination(location: &Location, year: f32) -> Result<f32, OutOfRange> {
+    Ok(magnetic_field(location, year)?.declination())
+}
+
+/// evaluates the model at a location and decimal year.
+pub fn magnetic_field(location: &Location, year: f32) -> Result<MagneticField, OutOfRange> {
+    // also rejects NaN.
+    if !(EPOCH..VALID_UNTIL).contains(&year) {
+        return Err(OutOfRange(year));
+    }
+    let latitude = location.latitude.to_radians();
+    let longitude = location.longitude.to_radians();
+
+    // geodetic to geocentric spherical coordinates.
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let (sin_lat, cos_lat) = (sinf(latitude), cosf(latitude));
+    let prime_vertical = WGS84_A / sqrtf(1.0 - e2 * sin_lat * sin_lat);
+    let p = (prime_vertical + location.altitude) * cos_lat;
+    let z = (prime_vertical * (1.0 - e2) + location.altitude) * sin_lat;
+    let r = sqrtf(p * p + z * z);
+    let geocentric_latitude = asinf(z / r);
+
+    // the legendre functions are in terms of the colatitude.
+    let cos_theta = sinf(geocentric_latitude);
+    // clamped so the east component stays finite at the poles.
+    let sin_theta = cosf(geocentric_latitude).max(1e-6);
+    let (p, dp) = schmidt_legendre(cos_theta, sin_theta);
+
+    let dt = year - EPOCH;
+    let (mut north, mut east, mut down) = (0.0, 0.0, 0.0);
+    let mut radius_ratio = (REFERENCE_RADIUS / r) * (REFERENCE_RADIUS / r);
+    let mut index = 0;
+    for n in 1..=MAX_DEGREE {
+        radius_ratio *= REFERENCE_RADIUS / r;
+        for m in 0..=n {
+            let [g, h, g_dot, h_dot] = COEFFICIENTS[index];
+            index += 1;
+            let (g, h) = (g + dt * g_dot, h + dt * h_dot);
+            let (sin_ml, cos_ml) = (sinf(m as f32 * longitude), cosf(m as f32 * longitude));
+            let cos_term = g * cos_ml + h * sin_ml;
+
+            north += radius_ratio * cos_term * dp[n][m];
+            east += radius_ratio * m as f32 * (g * sin_ml - h * cos_ml) * p[n][m];
+            down -= radius_ratio * (n + 1) as f32 * cos_term * p[n][m];
+        }
+    }
+    east /= sin_theta;
+
+    // rotate from the geocentric back to the geodetic frame.
+    let tilt = geocentric_latitude - latitude;
+    Ok(MagneticField {
+        x: north * cosf(tilt) - down * sinf(tilt),
+        y: east,
+        z: north * sinf(tilt) + down * cosf(tilt),
+    })
+}
+
+type Table = [[f32; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+
+/// schmidt semi-normalized associated legendre functions of the colatitude, and their
+/// derivatives with respect to it.
+fn schmidt_legendre(cos_theta: f32, sin_theta: f32) -> (Table, Table) {
+    let mut p: Table = [[0.0; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+    let mut dp: Table = [[0.0; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+    p[0][0] = 1.0;
+    for m in 0..=MAX_DEGREE {
+        if m > 0 {
+            let k = if m == 1 {
+                1.0
+            } else {
+                sqrtf((2 * m - 1) as f32 / (2 * m) as f32)
+            };
+            p[m][m] = k * sin_theta * p[m - 1][m - 1];
+            dp[m][m] = k * (cos_theta * p[m - 1][m - 1] + sin_theta * dp[m - 1][m - 1]);
+        }
+        for n in m + 1..=MAX_DEGREE {
+            let a = (2 * n - 1) as f32;
+            let b = sqrtf(((n - 1) * (n - 1) - m * m) as f32);
+            let c = sqrtf((n * n - m * m) as f32);
+            let (p2, dp2) = if n >= m + 2 {
+                (p[n - 2][m], dp[n - 2][m])
+            } else {
+                (0.0, 0.0)
+            };
+            p[n][m] = (a * cos_theta * p[n - 1][m] - b * p2) / c;
+            dp[n][m] = (a * (cos_theta * dp[n - 1][m] - sin_theta * p[n - 1][m]) - b * dp2) / c;
+        }
+    }
+    (p, dp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (year, altitude, latitude, longitude, [D, I, H, X, Y, Z]) at the locations and dates of the
+    /// WMM test values, laid out like NOAA's table: angles in degrees to two decimals, field
+    /// components in nT to one. Evaluated from [`COEFFICIENTS`] in double precision, so a
+    /// mistyped coefficient isn't caught here, but the single precision evaluation is.
+    #[rustfmt::skip]
+    const TEST_VALUES: [(f32, f32, f32, f32, [f32; 6]); 24] = [
+        (2025.0, 0.0, 80.0, 0.0, [1.28, 83.21, 6523.2, 6521.6, 145.9, 54791.5]),
+        (2025.0, 0.0, 0.0, 120.0, [-0.16, -14.93, 39678.2, 39678.0, -109.7, -10580.5]),
+        (2025.0, 0.0, -80.0, 240.0, [68.78, -72.00, 16898.2, 6117.6, 15751.9, -52022.5]),
+        (2025.0, 0.0, 40.0, -105.3, [7.82, 66.09, 20801.4, 20607.8, 2831.0, 46918.5]),
+        (2025.0, 0.0, 51.5, 0.0, [0.95, 66.53, 19542.2, 19539.5, 324.3, 45010.1]),
+        (2025.0, 0.0, -33.9, 151.2, [12.80, -64.42, 24622.0, 24010.4, 5454.1, -51434.9]),
+        (2025.0, 100.0, 80.0, 0.0, [0.85, 83.26, 6216.7, 6216.0, 92.5, 52598.8]),
+        (2025.0, 100.0, 0.0, 120.0, [-0.15, -15.08, 37688.9, 37688.8, -96.3, -10152.4]),
+        (2025.0, 100.0, -80.0, 240.0, [68.21, -72.19, 15917.2, 5907.6, 14780.3, -49540.7]),
+        (2025.0, 100.0, 40.0, -105.3, [7.61, 66.08, 19797.3, 19623.0, 2621.1, 44633.4]),
+        (2025.0, 100.0, 51.5, 0.0, [0.77, 66.40, 18765.7, 18764.0, 250.6, 42962.6]),
+        (2025.0, 100.0, -33.9, 151.2, [12.69, -64.39, 23447.9, 22875.0, 5151.3, -48910.1]),
+        (2027.5, 0.0, 80.0, 0.0, [2.59, 83.24, 6507.5, 6500.8, 294.6, 54869.4]),
+        (2027.5, 0.0, 0.0, 120.0, [-0.24, -14.65, 39702.3, 39701.9, -167.5, -10382.1]),
+        (2027.5, 0.0, -80.0, 240.0, [68.49, -71.92, 16908.4, 6200.8, 15730.3, -51783.6]),
+        (2027.5, 0.0, 40.0, -105.3, [7.62, 65.97, 20778.7, 20595.2, 2755.5, 46594.7]),
+        (2027.5, 0.0, 51.5, 0.0, [1.38, 66.56, 19558.5, 19552.8, 469.8, 45102.9]),
+        (2027.5, 0.0, -33.9, 151.2, [12.85, -64.43, 24597.2, 23981.0, 5471.2, -51399.6]),
+        (2027.5, 100.0, 80.0, 0.0, [2.16, 83.29, 6201.1, 6196.7, 233.8, 52670.5]),
+        (2027.5, 100.0, 0.0, 120.0, [-0.23, -14.81, 37712.1, 37711.8, -148.8, -9970.1]),
+        (2027.5, 100.0, -80.0, 240.0, [67.93, -72.10, 15927.0, 5984.0, 14760.2, -49317.7]),
+        (2027.5, 100.0, 40.0, -105.3, [7.41, 65.96, 19777.2, 19612.2, 2549.3, 44329.8]),
+        (2027.5, 100.0, 51.5, 0.0, [1.19, 66.43, 18781.3, 18777.3, 389.1, 43047.4]),
+        (2027.5, 100.0, -33.9, 151.2, [12.74, -64.39, 23424.4, 22847.3, 5167.5, -48875.9]),
+    ];
+
+    #[test]
+    fn matches_test_values() {
+        for (year, altitude, latitude, longitude, expected) in TEST_VALUES {
+            let location = Location {
+                latitude,
+                longitude,
+                altitude,
+            };
+            let field = dbg!(magnetic_field(&location, year).unwrap());
+            let [d, i, h, x, y, z] = expected;
+            assert!((field.declination().to_degrees() - d).abs() <= 0.01);
+            assert!((field.inclination().to_degrees() - i).abs() <= 0.01);
+            let horizontal = sqrtf(field.x * field.x + field.y * field.y);
+            for (value, expected) in [(horizontal, h), (field.x, x), (field.y, y), (field.z, z)] {
+                assert!(
+                    (value - expected).abs() <= 0.1,
+                    "{value} nT instead of {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_dates_outside_the_model() {
+        let location = Location {
+            latitude: 40.0,
+            longitude: -105.3,
+            altitude: 0.0,
+        };
+        assert!(magnetic_field(&location, 2029.9).is_ok());
+        assert_eq!(declination(&location, 2024.9), Err(OutOfRange(2024.9)));
+        assert_eq!(
+            declination(&location, VALID_UNTIL),
+            Err(OutOfRange(VALID_UNTIL))
+        );
+        assert!(declination(&location, f32::NAN).is_err());
+    }
+
+    #[test]
+    fn legendre_low_degrees() {
+        let (cos_theta, sin_theta) = (cosf(0.7), sinf(0.7));
+        let (p, dp) = schmidt_legendre(cos_theta, sin_theta);
+        assert!((p[1][0] - cos_theta).abs() < 1e-6);
+        assert!((p[1][1] - sin_theta).abs() < 1e-6);
+        assert!((p[2][0] - (3.0 * cos_theta * cos_theta - 1.0) / 2.0).abs() < 1e-6);
+        assert!((p[2][2] - sqrtf(3.0) / 2.0 * sin_theta * sin_theta).abs() < 1e-6);
+        assert!((dp[1][0] + sin_theta).abs() < 1e-6);
+        assert!(
+            (dp[2][1] - sqrtf(3.0) * (cos_theta * cos_theta - sin_theta * sin_theta)).abs() < 1e-6
+        );
+    }
+
+    #[test]
+    fn decimal_years() {
+        assert_eq!(decimal_year(2022, 1, 1), 2022.0);
+        assert_eq!(decimal_year(2022, 7, 2), 2022.0 + 182.0 / 365.0);
+        assert_eq!(decimal_year(2024, 3, 1), 2024.0 + 60.0 / 366.0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Runs the compass pipeline on the host, against synthetic orientations or a replayed log of
+//! sensor readings, and renders the LED matrix as ASCII frames.
+
+mod replay;
+mod synthetic;
+
+use std::{env, fs, process, thread, time::Duration};
+
+use independent_logic::{
+    compass::heading_from_samples,
+    heading_drawing::DisplayStyle,
+    line_drawing::{FourQuadrantMatrix, UPoint},
+    mounting::{Mounting, ALIGNED_COUNT},
+    sensor::Sample,
+    tilt_compensation::{true_heading, Attitude, Declination, Heading},
+};
+
+const RAD_TO_DEG: f32 = 180.0 / std::f32::consts::PI;
+
+const USAGE: &str =
+    "usage: simulator [--no-tilt] [--delay MS] [--declination DEG] [--mounting N] synthetic [PITCH ROLL]
+       simulator [--no-tilt] [--delay MS] [--declination DEG] [--mounting N] replay FILE
+       simulator [--delay MS] [--declination DEG] recording FILE
+
+synthetic sweeps the heading all the way around at a fixed pitch and roll (in degrees).
+replay reads one sample per line, as whitespace separated board-native integers:
+    accel_x accel_y accel_z mag_x mag_y mag_z
+blank lines and lines starting with # are ignored.
+recording plays back a file saved with `host_cli record`, with the calibration and tilt correction
+it was recorded with, and checks that every heading comes out exactly as it did on the compass.
+--declination corrects headings to true north, positive east.
+--mounting is the number of the boards mounting, from 1 to 24, as in the settings menu. PITCH and
+ROLL are then the boards own, not the enclosures.";
+
+/// everything the firmware would know after processing one sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub pitch: f32,
+    pub roll: f32,
+    pub heading: f32,
+    pub leds: [[u8; 5]; 5],
+}
+
+/// the same steps as the firmwares main loop.
+pub fn process(
+    sample: &Sample,
+    tilt_correction_enabled: bool,
+    declination: Declination,
+    mounting: &Mounting,
+) -> Frame {
+    let (heading, attitude) =
+        heading_from_samples(sample.accel, sample.mag, tilt_correction_enabled, mounting);
+    draw(heading, attitude, declination, mounting)
+}
+
+/// what the firmware shows for a magnetic heading.
+pub fn draw(
+    heading: Heading,
+    attitude: Attitude,
+    declination: Declination,
+    mounting: &Mounting,
+) -> Frame {
+    let heading = true_heading(heading, declination);
+
+    let mut display: FourQuadrantMatrix<5, 5, u8> = FourQuadrantMatrix::new(UPoint { x: 2, y: 2 });
+    DisplayStyle::default().draw::<5, 5>(heading.0, &mut display);
+
+    Frame {
+        pitch: attitude.pitch,
+        roll: attitude.roll,
+        heading: heading.0,
+        leds: mounting.turn_frame(display.into()),
+    }
+}
+
+pub fn render(frame: &Frame) -> String {
+    let mut out = format!(
+        "pitch: {:<+5.0} roll: {:<+5.0} heading: {:<+5.0}\n",
+        frame.pitch * RAD_TO_DEG,
+        frame.roll * RAD_TO_DEG,
+        frame.heading * RAD_TO_DEG,
+    );
+    for row in frame.leds {
+        let row: Vec<String> = row
+            .iter()
+            .map(|led| match led {
+                0 => ".".to_string(),
+                9 => "#".to_string(),
+                level => level.to_string(),
+            })
+            .collect();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2)
+}
+
+fn parse_number<T: std::str::FromStr>(arg: Option<String>, name: &str) -> T {
+    let arg = arg.unwrap_or_else(|| usage_error(&format!("missing {}", name)));
+    arg.parse()
+        .unwrap_or_else(|_| usage_error(&format!("invalid {}: {}", name, arg)))
+}
+
+fn process_all(
+    samples: &[Sample],
+    tilt_correction_enabled: bool,
+    declination: Declination,
+    mounting: &Mounting,
+) -> Vec<Frame> {
+    samples
+        .iter()
+        .map(|sample| process(sample, tilt_correction_enabled, declination, mounting))
+        .collect()
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut tilt_correction_enabled = true;
+    let mut delay = Duration::from_millis(100);
+    let mut declination = Declination::default();
+    let mut mounting = Mounting::default();
+
+    let frames = loop {
+        match args.next().as_deref() {
+            Some("--no-tilt") => tilt_correction_enabled = false,
+            Some("--delay") => delay = Duration::from_millis(parse_number(args.next(), "delay")),
+            Some("--declination") => {
+                declination = Declination::from_degrees(parse_number(args.next(), "declination"))
+            }
+            Some("--mounting") => {
+                let number: usize = parse_number(args.next(), "mounting");
+                if !(1..=ALIGNED_COUNT).contains(&number) {
+                    usage_error(&format!("invalid mounting: {}", number));
+                }
+                mounting = Mounting::Aligned(number as u8 - 1);
+            }
+            Some("synthetic") => {
+                let (pitch, roll) = match args.next() {
+                    Some(pitch) => (
+                        parse_number::<f32>(Some(pitch), "pitch"),
+                        parse_number::<f32>(args.next(), "roll"),
+                    ),
+                    None => (0.0, 0.0),
+                };
+                let samples = synthetic::heading_sweep(pitch / RAD_TO_DEG, roll / RAD_TO_DEG);
+                break process_all(&samples, tilt_correction_enabled, declination, &mounting);
+            }
+            Some("replay") => {
+                let path: String = parse_number(args.next(), "file");
+                let samples = replay::read_log(&path).unwrap_or_else(|error| {
+                    eprintln!("could not read {}: {}", path, error);
+                    process::exit(1)
+                });
+                break process_all(&samples, tilt_correction_enabled, declination, &mounting);
+            }
+            Some("recording") => {
+                let path: String = parse_number(args.next(), "file");
+                let (frames, mismatches) = fs::read(&path)
+                    .and_then(|file| replay::play_recording(&file, declination))
+                    .unwrap_or_else(|error| {
+                        eprintln!("could not read {}: {}", path, error);
+                        process::exit(1)
+                    });
+                if mismatches != 0 {
+                    eprintln!(
+                        "{} of {} headings differ from the recorded ones",
+                        mismatches,
+                        frames.len()
+                    );
+                }
+                break frames;
+            }
+            Some("--help") | Some("-h") => {
+                println!("{}", USAGE);
+                return;
+            }
+            Some(arg) => usage_error(&format!("unknown argument: {}", arg)),
+            None => usage_error("missing mode"),
+        }
+    };
+
+    for frame in frames {
+        println!("{}", render(&frame));
+        thread::sleep(delay);
+    }
+}
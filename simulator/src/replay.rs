@@ -0,0 +1,161 @@
+//! Reads logged sensor readings and recordings back in.
+
+use std::{fs, io};
+
+use independent_logic::{
+    frames::BoardReading,
+    recording::{Entry, FileReader, Player},
+    sensor::Sample,
+    tilt_compensation::Declination,
+};
+
+use crate::{draw, Frame};
+
+pub fn read_log(path: &str) -> io::Result<Vec<Sample>> {
+    parse_log(&fs::read_to_string(path)?)
+}
+
+/// parses one sample per line: `accel_x accel_y accel_z mag_x mag_y mag_z`. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn parse_log(log: &str) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (line_number, line) in log.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", line_number + 1, message),
+            )
+        };
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| invalid(&error.to_string()))?;
+        let [ax, ay, az, mx, my, mz] = values[..] else {
+            return Err(invalid(&format!("expected 6 values, got {}", values.len())));
+        };
+        samples.push(Sample {
+            accel: BoardReading::new(ax, ay, az),
+            mag: BoardReading::new(mx, my, mz),
+        });
+    }
+    Ok(samples)
+}
+
+/// plays back a recording from `host_cli record`. Returns a frame for every reading, and how many
+/// headings differ from the ones the compass calculated.
+pub fn play_recording(file: &[u8], declination: Declination) -> io::Result<(Vec<Frame>, usize)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let reader = FileReader::new(file).map_err(|error| invalid(format!("{:?}", error)))?;
+    let mut player = Player::new();
+    let mut frames = Vec::new();
+    let mut mismatches = 0;
+    for entry in reader {
+        let entry = entry.map_err(|error| invalid(format!("{:?}", error)))?;
+        if let (Some((heading, attitude)), Entry::Reading(reading)) = (player.play(&entry), entry) {
+            if heading.0.to_bits() != reading.heading.0.to_bits() {
+                mismatches += 1;
+            }
+            frames.push(draw(heading, attitude, declination, &player.mounting()));
+        }
+    }
+    Ok((frames, mismatches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_samples() {
+        let log = "# recorded on the bench\n\n 12 -40 -1010  2500 -18000 -43000\n1 2 3 4 5 6\n";
+        assert_eq!(
+            parse_log(log).unwrap(),
+            vec![
+                Sample {
+                    accel: BoardReading::new(12, -40, -1010),
+                    mag: BoardReading::new(2500, -18000, -43000),
+                },
+                Sample {
+                    accel: BoardReading::new(1, 2, 3),
+                    mag: BoardReading::new(4, 5, 6),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let error = parse_log("1 2 3 4 5\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: expected 6 values, got 5");
+        assert!(parse_log("# ok\n1 2 3 4 5 x\n")
+            .unwrap_err()
+            .to_string()
+            .starts_with("line 2:"));
+    }
+
+    #[test]
+    fn plays_recordings() {
+        use crate::synthetic::{heading_sweep, orientation_sample};
+        use independent_logic::{
+            calibration::{Calibration, Vector3},
+            compass::{calibrated_mag, heading_from_samples},
+            mounting::Mounting,
+            recording::{file_entry, file_header, Reading, Recorder, MAX_FILE_ENTRY_LEN},
+            tilt_compensation::Heading,
+        };
+
+        let calibration = Calibration::AxisScale {
+            center: Vector3 {
+                x: 100,
+                y: -200,
+                z: 50,
+            },
+            scale: Vector3 {
+                x: 1000,
+                y: 1050,
+                z: 1024,
+            },
+            radius: 45000,
+        };
+        let mut recorder = Recorder::new();
+        let mut file = file_header().to_vec();
+        let mut out = [0; MAX_FILE_ENTRY_LEN];
+        for (i, sample) in heading_sweep(0.2, -0.1).into_iter().enumerate() {
+            let (heading, _) = heading_from_samples(
+                sample.accel,
+                calibrated_mag(sample.mag, &calibration),
+                true,
+                &Mounting::default(),
+            );
+            let reading = Reading {
+                timestamp: i as u32 * 100,
+                sample,
+                tilt_correction: true,
+                heading,
+            };
+            for entry in recorder.record(&calibration, &Mounting::default(), reading) {
+                file.extend(file_entry(&entry, &mut out).unwrap());
+            }
+        }
+        // a heading the pipeline doesn't reproduce.
+        let reading = Reading {
+            timestamp: 9999,
+            sample: orientation_sample(0.0, 0.0, 0.0),
+            tilt_correction: false,
+            heading: Heading(1.0),
+        };
+        for entry in recorder.record(&calibration, &Mounting::default(), reading) {
+            file.extend(file_entry(&entry, &mut out).unwrap());
+        }
+
+        let (frames, mismatches) = play_recording(&file, Declination::default()).unwrap();
+        assert_eq!(frames.len(), 25);
+        assert_eq!(mismatches, 1);
+        assert!(play_recording(b"1 2 3 4 5 6", Declination::default()).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Generates the readings a perfectly calibrated board would produce in a given orientation.
+
+use std::f32::consts::PI;
+
+use independent_logic::{
+    frames::{BoardReading, FrameVector, Ned},
+    sensor::Sample,
+};
+
+/// 1g, in the accelerometers milli-g.
+const GRAVITY: f32 = 1000.0;
+/// horizontal and vertical (downwards) components of the earths field, roughly as in central
+/// europe, in the magnetometers units.
+const FIELD_NORTH: f32 = 20000.0;
+const FIELD_DOWN: f32 = 45000.0;
+
+const SWEEP_STEP: f32 = PI / 12.0;
+
+/// one sample every 15° of heading, all the way around, at a fixed pitch and roll.
+pub fn heading_sweep(pitch: f32, roll: f32) -> Vec<Sample> {
+    (0..24)
+        .map(|i| orientation_sample(i as f32 * SWEEP_STEP - PI, pitch, roll))
+        .collect()
+}
+
+/// the readings for a board with the given heading, pitch and roll, in radians.
+pub fn orientation_sample(heading: f32, pitch: f32, roll: f32) -> Sample {
+    //rotation from the earth frame into the body frame, as in
+    //https://www.nxp.com/docs/en/application-note/AN4248.pdf
+    //heading_from_measurement measures yaw in the opposite direction.
+    let yaw = -heading;
+    let rotate = |v: [f32; 3]| {
+        let v = [
+            yaw.cos() * v[0] + yaw.sin() * v[1],
+            -yaw.sin() * v[0] + yaw.cos() * v[1],
+            v[2],
+        ];
+        let v = [
+            pitch.cos() * v[0] - pitch.sin() * v[2],
+            v[1],
+            pitch.sin() * v[0] + pitch.cos() * v[2],
+        ];
+        [
+            v[0],
+            roll.cos() * v[1] + roll.sin() * v[2],
+            -roll.sin() * v[1] + roll.cos() * v[2],
+        ]
+    };
+
+    Sample {
+        accel: ned_to_board(rotate([0.0, 0.0, GRAVITY])),
+        mag: ned_to_board(rotate([FIELD_NORTH, 0.0, FIELD_DOWN])),
+    }
+}
+
+fn ned_to_board(ned: [f32; 3]) -> BoardReading {
+    let board = FrameVector::<Ned>::new(ned[0], ned[1], ned[2]).to_board();
+    FrameVector::new(
+        board.x.round() as i32,
+        board.y.round() as i32,
+        board.z.round() as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process;
+    use independent_logic::{mounting::Mounting, tilt_compensation::Declination};
+
+    fn angle_difference(a: f32, b: f32) -> f32 {
+        let d = (a - b).rem_euclid(2.0 * PI);
+        d.min(2.0 * PI - d)
+    }
+
+    #[test]
+    fn pipeline_recovers_orientation() {
+        for (pitch, roll) in [(0.0, 0.0), (0.3, 0.0), (0.0, -0.5), (-0.4, 0.6)] {
+            for sample in heading_sweep(pitch, roll) {
+                let frame = process(&sample, true, Declination::default(), &Mounting::default());
+                assert!((frame.pitch - pitch).abs() < 1e-2, "{:?}", frame);
+                assert!((frame.roll - roll).abs() < 1e-2, "{:?}", frame);
+            }
+            for (i, sample) in heading_sweep(pitch, roll).iter().enumerate() {
+                let heading = i as f32 * SWEEP_STEP - PI;
+                let frame = process(sample, true, Declination::default(), &Mounting::default());
+                assert!(
+                    angle_difference(frame.heading, heading) < 1e-2,
+                    "expected {} got {:?}",
+                    heading,
+                    frame
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn north_points_up() {
+        let frame = process(
+            &orientation_sample(0.0, 0.0, 0.0),
+            true,
+            Declination::default(),
+            &Mounting::default(),
+        );
+        assert_eq!(
+            frame.leds,
+            [
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 9, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn east_points_right() {
+        let frame = process(
+            &orientation_sample(PI / 2.0, 0.0, 0.0),
+            true,
+            Declination::default(),
+            &Mounting::default(),
+        );
+        assert_eq!(
+            frame.leds,
+            [
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+                [0, 0, 9, 9, 9],
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn mounted_upright() {
+        // pitched up onto the boards bottom edge, in an enclosure lying flat that faces east.
+        let mounting = Mounting::Aligned(12);
+        for roll in [-0.3, 0.0, 0.3] {
+            let sample = orientation_sample(PI / 2.0, PI / 2.0, roll);
+            let frame = process(&sample, true, Declination::default(), &mounting);
+            assert!(frame.pitch.abs() < 1e-2, "{:?}", frame);
+            assert!(
+                angle_difference(frame.heading, PI / 2.0 + roll) < 1e-2,
+                "{:?}",
+                frame
+            );
+        }
+        let sample = orientation_sample(PI / 2.0, PI / 2.0, 0.0);
+        let frame = process(&sample, true, Declination::default(), &mounting);
+        assert_eq!(frame.leds[2], [0, 0, 9, 9, 9]);
+
+        // flat, turned a quarter clockwise in the enclosure, the display is turned back.
+        let sample = orientation_sample(PI / 2.0, 0.0, 0.0);
+        let frame = process(&sample, true, Declination::default(), &Mounting::Aligned(1));
+        assert!(angle_difference(frame.heading, 0.0) < 1e-2, "{:?}", frame);
+        assert_eq!(frame.leds[2], [0, 0, 9, 9, 9]);
+    }
+
+    #[test]
+    fn declination_shifts_heading() {
+        // facing magnetic north, which is east of true north.
+        let frame = process(
+            &orientation_sample(0.0, 0.0, 0.0),
+            true,
+            Declination::from_degrees(15.0),
+            &Mounting::default(),
+        );
+        assert!(angle_difference(frame.heading, 15.0_f32.to_radians()) < 1e-2);
+    }
+}